@@ -0,0 +1,414 @@
+use super::{
+    loader::{self, DataFile, Kline},
+    okx::{instrument_id, OkxResponse, OKX_URL},
+};
+use crate::{
+    messaging::message::{Candle, Timestamp},
+    tools::networking::{HttpClient, Method, Request},
+};
+use anyhow::{anyhow, bail, Result};
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CandleRange {
+    pub base: String,
+    pub quote: String,
+    // bar size as okx names it, e.g. 1m, 5m or 1H
+    pub bar: String,
+    // milliseconds, the start is included and the end is not
+    pub start: Timestamp,
+    pub end: Timestamp,
+}
+
+// Rate limits and retries are left to the client, e.g. a RateLimit wrapped in
+// a Retry.
+pub struct Downloader<C> {
+    client: C,
+    page_size: usize,
+}
+
+impl<C: HttpClient> Downloader<C> {
+    pub fn new(client: C) -> Self {
+        Downloader {
+            client,
+            page_size: 100,
+        }
+    }
+
+    pub fn with_page_size(mut self, page_size: usize) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    // Appends the candles of the range which are newer than the last one stored
+    // in the file, oldest first. Each page is written as it arrives, so a
+    // failed download resumes after the last page. Returns how many candles
+    // were added.
+    pub async fn download(&mut self, range: &CandleRange, file: &DataFile) -> Result<usize> {
+        let bar = bar_millis(&range.bar).ok_or_else(|| anyhow!("unknown bar {}", range.bar))?;
+        let mut start = match last_stored(file)? {
+            Some(time) => range.start.max(time + bar),
+            None => range.start,
+        };
+        let mut added = 0;
+        while start < range.end {
+            let end = range.end.min(start + bar * self.page_size as Timestamp);
+            let mut page: Vec<Kline> = self
+                .fetch_page(range, start, end)
+                .await?
+                .into_iter()
+                .filter(|kline| kline.time >= start && kline.time < end)
+                .collect();
+            // okx returns the newest candle first
+            page.sort_by_key(|kline| kline.time);
+            page.dedup_by_key(|kline| kline.time);
+            loader::append_klines(file, &page, Some(bar))?;
+            added += page.len();
+            start = end;
+        }
+        Ok(added)
+    }
+
+    // The candles from `start` up to `end`, okx excludes both `before` and `after`.
+    async fn fetch_page(
+        &mut self,
+        range: &CandleRange,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Vec<Kline>> {
+        let url = format!(
+            "{}/api/v5/market/history-candles?instId={}&bar={}&before={}&after={}&limit={}",
+            OKX_URL,
+            instrument_id(&range.base, &range.quote),
+            range.bar,
+            start.saturating_sub(1),
+            end,
+            self.page_size
+        );
+        let response = self
+            .client
+            .send(Request {
+                method: Method::GET,
+                url,
+                ..Default::default()
+            })
+            .await?
+            .error_for_status()?;
+        let page: OkxResponse<Vec<String>> = serde_json::from_str(&response.body)?;
+        page.data()?.iter().map(|row| kline(row)).collect()
+    }
+}
+
+// ts, open, high, low, close, base volume, quote volume, ...
+fn kline(row: &[String]) -> Result<Kline> {
+    let field = |index: usize| -> Result<f64> {
+        match row.get(index) {
+            Some(value) => value
+                .parse()
+                .map_err(|err| anyhow!("candle field {}: {}", index, err)),
+            None if index > 4 => Ok(0.0),
+            None => bail!("candle with {} fields", row.len()),
+        }
+    };
+    let candle = Candle {
+        open: field(1)?,
+        high: field(2)?,
+        low: field(3)?,
+        close: field(4)?,
+        volume_base: field(5)?,
+        volume_quote: field(6)?,
+    };
+    Ok(Kline {
+        time: row[0].parse()?,
+        close: candle.close,
+        candle: Some(candle),
+    })
+}
+
+fn bar_millis(bar: &str) -> Option<Timestamp> {
+    let bar = bar.trim_end_matches("utc");
+    let (count, unit) = bar.split_at(bar.find(|c: char| !c.is_ascii_digit())?);
+    let unit: Timestamp = match unit {
+        "s" => 1000,
+        "m" => 60 * 1000,
+        "H" => 60 * 60 * 1000,
+        "D" => 24 * 60 * 60 * 1000,
+        "W" => 7 * 24 * 60 * 60 * 1000,
+        _ => return None,
+    };
+    Some(count.parse::<Timestamp>().ok()? * unit)
+}
+
+fn last_stored(file: &DataFile) -> Result<Option<Timestamp>> {
+    if !loader::has_content(&file.path) {
+        return Ok(None);
+    }
+    let mut last = None;
+    for kline in loader::read_klines(file)? {
+        let time = kline?.time;
+        last = Some(last.map_or(time, |last: Timestamp| last.max(time)));
+    }
+    Ok(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::networking::{tests::MockClient, HttpError, Response, Url};
+    use loader::DataFormat;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    const FIVE_MINUTES: Timestamp = 5 * 60 * 1000;
+
+    fn temp_file(name: &str) -> DataFile {
+        let path = std::env::temp_dir().join(name);
+        fs::remove_file(&path).ok();
+        DataFile::new("BTC/USDT", path.to_string_lossy().into_owned())
+    }
+
+    fn page(times: &[Timestamp]) -> String {
+        let rows: Vec<String> = times
+            .iter()
+            .map(|time| {
+                format!(
+                    "[\"{}\",\"10\",\"12\",\"9\",\"11\",\"2\",\"22\",\"22\",\"1\"]",
+                    time
+                )
+            })
+            .collect();
+        format!(
+            "{{\"code\":\"0\",\"msg\":\"\",\"data\":[{}]}}",
+            rows.join(",")
+        )
+    }
+
+    // responds to the page of candles from `start` up to `end`
+    fn respond(start: Timestamp, end: Timestamp, body: &str) -> ((Method, Url), Response) {
+        (
+            (
+                Method::GET,
+                format!(
+                    "{}/api/v5/market/history-candles?instId=BTC-USDT&bar=5m&before={}&after={}&limit=2",
+                    OKX_URL,
+                    start.saturating_sub(1),
+                    end
+                ),
+            ),
+            Response {
+                status: 200,
+                body: body.to_string(),
+            },
+        )
+    }
+
+    fn new_downloader(responses: Vec<((Method, Url), Response)>) -> Downloader<MockClient> {
+        Downloader::new(MockClient::new(responses.into_iter().collect())).with_page_size(2)
+    }
+
+    fn btc_range(start: Timestamp, end: Timestamp) -> CandleRange {
+        CandleRange {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            bar: "5m".into(),
+            start,
+            end,
+        }
+    }
+
+    fn stored_times(file: &DataFile) -> Vec<Timestamp> {
+        loader::read_klines(file)
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect()
+    }
+
+    #[async_std::test]
+    async fn download_should_store_pages_oldest_first() {
+        let file = temp_file("download_pages.jsonl");
+        let mut downloader = new_downloader(vec![
+            respond(0, 2 * FIVE_MINUTES, &page(&[FIVE_MINUTES, 0])),
+            respond(
+                2 * FIVE_MINUTES,
+                4 * FIVE_MINUTES,
+                &page(&[3 * FIVE_MINUTES, 2 * FIVE_MINUTES, FIVE_MINUTES]),
+            ),
+        ]);
+        let added = downloader
+            .download(&btc_range(0, 4 * FIVE_MINUTES), &file)
+            .await
+            .unwrap();
+        assert_eq!(4, added);
+        assert_eq!(
+            vec![0, FIVE_MINUTES, 2 * FIVE_MINUTES, 3 * FIVE_MINUTES],
+            stored_times(&file)
+        )
+    }
+
+    #[async_std::test]
+    async fn download_should_stop_at_range_start() {
+        let file = temp_file("download_start.jsonl");
+        let mut downloader = new_downloader(vec![respond(
+            3 * FIVE_MINUTES,
+            4 * FIVE_MINUTES,
+            &page(&[3 * FIVE_MINUTES, 2 * FIVE_MINUTES]),
+        )]);
+        downloader
+            .download(&btc_range(3 * FIVE_MINUTES, 4 * FIVE_MINUTES), &file)
+            .await
+            .unwrap();
+        assert_eq!(vec![3 * FIVE_MINUTES], stored_times(&file))
+    }
+
+    #[async_std::test]
+    async fn download_should_resume_after_last_stored_candle() {
+        let file = temp_file("download_resume.csv");
+        let mut first = new_downloader(vec![respond(
+            0,
+            2 * FIVE_MINUTES,
+            &page(&[FIVE_MINUTES, 0]),
+        )]);
+        first
+            .download(&btc_range(0, 2 * FIVE_MINUTES), &file)
+            .await
+            .unwrap();
+        let mut second = new_downloader(vec![respond(
+            2 * FIVE_MINUTES,
+            4 * FIVE_MINUTES,
+            &page(&[3 * FIVE_MINUTES, 2 * FIVE_MINUTES]),
+        )]);
+        let added = second
+            .download(&btc_range(0, 4 * FIVE_MINUTES), &file)
+            .await
+            .unwrap();
+        assert_eq!(2, added);
+        assert_eq!(
+            vec![0, FIVE_MINUTES, 2 * FIVE_MINUTES, 3 * FIVE_MINUTES],
+            stored_times(&file)
+        )
+    }
+
+    #[async_std::test]
+    async fn download_should_write_readable_csv() {
+        let file = temp_file("download_candles.csv");
+        let mut downloader = new_downloader(vec![respond(0, FIVE_MINUTES, &page(&[0]))]);
+        downloader
+            .download(&btc_range(0, FIVE_MINUTES), &file)
+            .await
+            .unwrap();
+        assert_eq!(
+            format!("{}0,10,12,9,11,2,299999,22\n", loader::CSV_HEADER),
+            fs::read_to_string(&file.path).unwrap()
+        );
+        let expected = Kline {
+            time: 0,
+            close: 11.0,
+            candle: Some(Candle {
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 11.0,
+                volume_base: 2.0,
+                volume_quote: 22.0,
+            }),
+        };
+        let actual: Vec<Kline> = loader::read_klines(&file)
+            .unwrap()
+            .map(|kline| kline.unwrap())
+            .collect();
+        assert_eq!(vec![expected], actual)
+    }
+
+    #[async_std::test]
+    async fn download_should_append_readable_okx_pages_and_gzip_members() {
+        let file = temp_file("download_okx.json.gz").with_format(DataFormat::Okx);
+        for (end, time) in [(FIVE_MINUTES, 0), (2 * FIVE_MINUTES, FIVE_MINUTES)] {
+            let mut downloader = new_downloader(vec![respond(time, end, &page(&[time]))]);
+            downloader
+                .download(&btc_range(0, end), &file)
+                .await
+                .unwrap();
+        }
+        assert_eq!(vec![0, FIVE_MINUTES], stored_times(&file))
+    }
+
+    #[async_std::test]
+    async fn download_should_fail_on_error_status() {
+        let file = temp_file("download_rate_limited.jsonl");
+        let (request, response) = respond(
+            0,
+            FIVE_MINUTES,
+            "{\"code\":\"50011\",\"msg\":\"Too Many Requests\",\"data\":[]}",
+        );
+        let mut downloader = new_downloader(vec![(
+            request,
+            Response {
+                status: 429,
+                ..response
+            },
+        )]);
+        let actual = downloader
+            .download(&btc_range(0, FIVE_MINUTES), &file)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            actual.downcast_ref::<HttpError>(),
+            Some(HttpError::Status { status: 429, .. })
+        ))
+    }
+
+    #[async_std::test]
+    async fn download_should_fail_on_okx_error() {
+        let file = temp_file("download_error.jsonl");
+        let mut downloader = new_downloader(vec![respond(
+            0,
+            FIVE_MINUTES,
+            "{\"code\":\"51001\",\"msg\":\"Instrument ID does not exist\",\"data\":[]}",
+        )]);
+        let actual = downloader
+            .download(&btc_range(0, FIVE_MINUTES), &file)
+            .await
+            .unwrap_err();
+        assert_eq!(
+            "okx error 51001: Instrument ID does not exist",
+            actual.to_string()
+        );
+        assert!(!loader::has_content(&file.path))
+    }
+
+    #[async_std::test]
+    async fn download_should_keep_pages_written_before_failing() {
+        let file = temp_file("download_partial.jsonl");
+        let mut downloader = new_downloader(vec![
+            respond(0, 2 * FIVE_MINUTES, &page(&[FIVE_MINUTES, 0])),
+            respond(
+                2 * FIVE_MINUTES,
+                4 * FIVE_MINUTES,
+                "{\"code\":\"50001\",\"msg\":\"Service temporarily unavailable\",\"data\":[]}",
+            ),
+        ]);
+        downloader
+            .download(&btc_range(0, 4 * FIVE_MINUTES), &file)
+            .await
+            .unwrap_err();
+        assert_eq!(vec![0, FIVE_MINUTES], stored_times(&file))
+    }
+
+    #[async_std::test]
+    async fn download_should_reject_unknown_bar() {
+        let file = temp_file("download_unknown_bar.jsonl");
+        let mut downloader = new_downloader(vec![]);
+        let range = CandleRange {
+            bar: "1M".into(),
+            ..btc_range(0, FIVE_MINUTES)
+        };
+        let actual = downloader.download(&range, &file).await.unwrap_err();
+        assert_eq!("unknown bar 1M", actual.to_string())
+    }
+
+    #[test]
+    fn bar_millis_should_convert_okx_bars() {
+        assert_eq!(Some(FIVE_MINUTES), bar_millis("5m"));
+        assert_eq!(Some(6 * 60 * 60 * 1000), bar_millis("6Hutc"));
+        assert_eq!(None, bar_millis("1M"))
+    }
+}
@@ -0,0 +1,525 @@
+use super::{okx::instrument_id, Subscription};
+use crate::{
+    messaging::message::{Candle, Msg, MsgData, MsgMetaData, PairId, PriceUpdated},
+    tools::{time::TimeProvider, uuid::IdProvider},
+};
+use anyhow::{anyhow, bail, Result};
+use crossbeam::channel;
+use flate2::read::DeflateDecoder;
+use serde::Deserialize;
+use serde_json::json;
+use std::{
+    collections::HashMap,
+    io::{ErrorKind, Read},
+    thread,
+    time::Duration,
+};
+use websocket::{result::WebSocketError, stream::sync::AsTcpStream, ClientBuilder, OwnedMessage};
+
+pub const OKX_PUBLIC_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Channel {
+    #[default]
+    Tickers,
+    // bar size as okx names it, e.g. 1m, 5m or 1H
+    Candles(String),
+}
+
+impl Channel {
+    fn name(&self) -> String {
+        match self {
+            Channel::Tickers => "tickers".into(),
+            Channel::Candles(bar) => format!("candle{}", bar),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct Backoff {
+    pub initial: Duration,
+    pub max: Duration,
+    pub max_retries: Option<u32>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Backoff {
+            initial: Duration::from_secs(1),
+            max: Duration::from_secs(60),
+            max_retries: None,
+        }
+    }
+}
+
+impl Backoff {
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.initial.saturating_mul(factor).min(self.max)
+    }
+
+    fn exhausted(&self, retry: u32) -> bool {
+        self.max_retries.is_some_and(|max| retry > max)
+    }
+}
+
+enum Closed {
+    Receiver,
+    Connection,
+}
+
+pub struct LiveFeed<T: TimeProvider, I: IdProvider> {
+    url: String,
+    subscriptions: Vec<Subscription>,
+    pairs: HashMap<String, PairId>,
+    channel: Channel,
+    backoff: Backoff,
+    ping_interval: Duration,
+    retry: u32,
+    time_provider: T,
+    id_provider: I,
+}
+
+impl<T, I> LiveFeed<T, I>
+where
+    T: TimeProvider + Send + 'static,
+    I: IdProvider + Send + 'static,
+{
+    pub fn new(
+        url: impl Into<String>,
+        subscriptions: Vec<Subscription>,
+        time_provider: T,
+        id_provider: I,
+    ) -> Self {
+        // subscriptions name the pair as bid/ask currency, e.g. BTC/USDT.
+        // pair ids are 'static, so each subscribed pair is leaked once
+        let pairs = subscriptions
+            .iter()
+            .map(|subscription| {
+                let pair_id: PairId = Box::leak(
+                    format!(
+                        "{}/{}",
+                        subscription.bid_currency, subscription.ask_currency
+                    )
+                    .into_boxed_str(),
+                );
+                (
+                    instrument_id(&subscription.bid_currency, &subscription.ask_currency),
+                    pair_id,
+                )
+            })
+            .collect();
+        LiveFeed {
+            url: url.into(),
+            subscriptions,
+            pairs,
+            channel: Channel::Tickers,
+            backoff: Backoff::default(),
+            ping_interval: Duration::from_secs(25),
+            retry: 0,
+            time_provider,
+            id_provider,
+        }
+    }
+
+    pub fn with_channel(mut self, channel: Channel) -> Self {
+        self.channel = channel;
+        self
+    }
+
+    pub fn with_backoff(mut self, backoff: Backoff) -> Self {
+        self.backoff = backoff;
+        self
+    }
+
+    #[cfg(test)]
+    pub fn with_ping_interval(mut self, ping_interval: Duration) -> Self {
+        self.ping_interval = ping_interval;
+        self
+    }
+
+    // Streams price updates into the sender until its receiver is dropped or the
+    // backoff runs out of retries, in which case Shutdown is sent last.
+    pub fn start(self, sender: channel::Sender<Msg>) -> thread::JoinHandle<()> {
+        thread::spawn(move || self.run(sender))
+    }
+
+    fn run(mut self, sender: channel::Sender<Msg>) {
+        loop {
+            match self.session(&sender) {
+                Ok(Closed::Receiver) => return,
+                Ok(Closed::Connection) => log::warn!("websocket {} closed", self.url),
+                Err(err) => log::warn!("websocket {} failed: {}", self.url, err),
+            }
+            self.retry += 1;
+            if self.backoff.exhausted(self.retry) {
+                log::error!("giving up on websocket {}", self.url);
+                break;
+            }
+            thread::sleep(self.backoff.delay(self.retry));
+        }
+        sender
+            .send(Msg {
+                data: MsgData::Shutdown,
+                metadata: MsgMetaData {
+                    ..Default::default()
+                },
+            })
+            .ok();
+    }
+
+    fn session(&mut self, sender: &channel::Sender<Msg>) -> Result<Closed> {
+        let mut client = ClientBuilder::new(&self.url)?.connect(None)?;
+        client
+            .stream_ref()
+            .as_tcp()
+            .set_read_timeout(Some(self.ping_interval))?;
+        client.send_message(&OwnedMessage::Text(subscribe_request(
+            &self.channel,
+            &self.subscriptions,
+        )))?;
+        let mut awaiting_pong = false;
+        loop {
+            let message = match client.recv_message() {
+                Ok(message) => message,
+                Err(WebSocketError::IoError(err))
+                    if matches!(err.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) =>
+                {
+                    if awaiting_pong {
+                        bail!("no pong within {:?}", self.ping_interval)
+                    }
+                    client.send_message(&OwnedMessage::Text("ping".into()))?;
+                    awaiting_pong = true;
+                    continue;
+                }
+                Err(WebSocketError::NoDataAvailable) => return Ok(Closed::Connection),
+                Err(err) => return Err(err.into()),
+            };
+            awaiting_pong = false;
+            let text = match message {
+                OwnedMessage::Text(text) => text,
+                OwnedMessage::Binary(data) => inflate(&data)?,
+                OwnedMessage::Ping(data) => {
+                    client.send_message(&OwnedMessage::Pong(data))?;
+                    continue;
+                }
+                OwnedMessage::Pong(_) => continue,
+                OwnedMessage::Close(_) => return Ok(Closed::Connection),
+            };
+            if text == "pong" {
+                continue;
+            }
+            for update in parse_updates(&text, &self.pairs)? {
+                self.retry = 0;
+                if sender.send(self.message(update)).is_err() {
+                    return Ok(Closed::Receiver);
+                }
+            }
+        }
+    }
+
+    fn message(&mut self, update: PriceUpdated) -> Msg {
+        let id = self.id_provider.new_random();
+        Msg {
+            metadata: MsgMetaData {
+                id,
+                correlation_id: id,
+                causation_id: id,
+                creation_time: self.time_provider.now(),
+                correlation_time: update.datetime,
+                correlation_price: update.price,
+                correlation_pair_id: update.pair_id,
+            },
+            data: MsgData::LivePriceUpdated(update),
+        }
+    }
+}
+
+fn subscribe_request(channel: &Channel, subscriptions: &[Subscription]) -> String {
+    let args: Vec<_> = subscriptions
+        .iter()
+        .map(|subscription| {
+            json!({
+                "channel": channel.name(),
+                "instId": instrument_id(&subscription.bid_currency, &subscription.ask_currency),
+            })
+        })
+        .collect();
+    json!({"op": "subscribe", "args": args}).to_string()
+}
+
+fn inflate(data: &[u8]) -> Result<String> {
+    let mut text = String::new();
+    DeflateDecoder::new(data).read_to_string(&mut text)?;
+    Ok(text)
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Frame {
+    Push { arg: Arg, data: Vec<Update> },
+    Event { event: String, msg: Option<String> },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Arg {
+    inst_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Update {
+    Ticker { last: String, ts: String },
+    // ts, open, high, low, close, base volume, quote volume, ... and whether the
+    // candle is confirmed as the last field
+    Candle(Vec<String>),
+}
+
+fn parse_updates(text: &str, pairs: &HashMap<String, PairId>) -> Result<Vec<PriceUpdated>> {
+    let (arg, data) = match serde_json::from_str(text)? {
+        Frame::Push { arg, data } => (arg, data),
+        Frame::Event { event, msg } => {
+            if event == "error" {
+                log::error!("websocket error: {}", msg.unwrap_or_default());
+            }
+            return Ok(vec![]);
+        }
+    };
+    let pair_id = match pairs.get(&arg.inst_id) {
+        Some(pair_id) => *pair_id,
+        None => return Ok(vec![]),
+    };
+    let mut updates = vec![];
+    for update in data {
+        match update {
+            Update::Ticker { last, ts } => updates.push(PriceUpdated {
+                pair_id,
+                datetime: ts.parse()?,
+                price: last.parse()?,
+                candle: None,
+            }),
+            Update::Candle(row) => {
+                if row.len() < 7 {
+                    bail!("candle with {} fields", row.len())
+                }
+                // okx keeps pushing the running candle, only closed ones are prices
+                if row.get(8).is_some_and(|confirm| confirm != "1") {
+                    continue;
+                }
+                let field = |index: usize| -> Result<f64> {
+                    row[index]
+                        .parse()
+                        .map_err(|err| anyhow!("candle field {}: {}", index, err))
+                };
+                let candle = Candle {
+                    open: field(1)?,
+                    high: field(2)?,
+                    low: field(3)?,
+                    close: field(4)?,
+                    volume_base: field(5)?,
+                    volume_quote: field(6)?,
+                };
+                updates.push(PriceUpdated {
+                    pair_id,
+                    datetime: row[0].parse()?,
+                    price: candle.close,
+                    candle: Some(candle),
+                });
+            }
+        }
+    }
+    Ok(updates)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{time::tests::MockTimeProvider, uuid::tests::MockUuidProvider};
+    use flate2::{write::DeflateEncoder, Compression};
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+    use uuid::Uuid;
+    use websocket::sync::Server;
+
+    const TICKER: &str = "{\"arg\":{\"channel\":\"tickers\",\"instId\":\"BTC-USDT\"},\"data\":[{\"instType\":\"SPOT\",\"instId\":\"BTC-USDT\",\"last\":\"9999.99\",\"ts\":\"1597026383085\"}]}";
+
+    fn btc_usdt() -> Subscription {
+        Subscription {
+            id: Uuid::from_u128(1),
+            bid_currency: "BTC".into(),
+            ask_currency: "USDT".into(),
+        }
+    }
+
+    fn pairs() -> HashMap<String, PairId> {
+        HashMap::from([("BTC-USDT".to_string(), "BTC/USDT")])
+    }
+
+    fn deflate(text: &str) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(vec![], Compression::default());
+        encoder.write_all(text.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn subscribe_request_should_subscribe_each_pair() {
+        let actual = subscribe_request(&Channel::Candles("5m".into()), &[btc_usdt()]);
+        assert_eq!(
+            "{\"op\":\"subscribe\",\"args\":[{\"channel\":\"candle5m\",\"instId\":\"BTC-USDT\"}]}",
+            actual
+        )
+    }
+
+    #[test]
+    fn parse_updates_should_read_tickers() {
+        let actual = parse_updates(TICKER, &pairs()).unwrap();
+        assert_eq!(
+            vec![PriceUpdated {
+                pair_id: "BTC/USDT",
+                datetime: 1597026383085,
+                price: 9999.99,
+                candle: None,
+            }],
+            actual
+        )
+    }
+
+    #[test]
+    fn parse_updates_should_read_confirmed_candles_only() {
+        let text = "{\"arg\":{\"channel\":\"candle1m\",\"instId\":\"BTC-USDT\"},\"data\":[[\"1597026383085\",\"8533.02\",\"8553.74\",\"8527.17\",\"8548.26\",\"45247\",\"529.5858061\",\"529.5858061\",\"1\"],[\"1597026443085\",\"8548.26\",\"8550\",\"8540\",\"8545\",\"10\",\"1\",\"1\",\"0\"]]}";
+        let actual = parse_updates(text, &pairs()).unwrap();
+        assert_eq!(
+            vec![PriceUpdated {
+                pair_id: "BTC/USDT",
+                datetime: 1597026383085,
+                price: 8548.26,
+                candle: Some(Candle {
+                    open: 8533.02,
+                    high: 8553.74,
+                    low: 8527.17,
+                    close: 8548.26,
+                    volume_base: 45247.0,
+                    volume_quote: 529.5858061,
+                }),
+            }],
+            actual
+        )
+    }
+
+    #[test]
+    fn parse_updates_should_ignore_events_and_unknown_pairs() {
+        let event =
+            "{\"event\":\"subscribe\",\"arg\":{\"channel\":\"tickers\",\"instId\":\"BTC-USDT\"}}";
+        assert_eq!(0, parse_updates(event, &pairs()).unwrap().len());
+        assert_eq!(0, parse_updates(TICKER, &HashMap::new()).unwrap().len())
+    }
+
+    #[test]
+    fn inflate_should_decompress_deflate_frames() {
+        assert_eq!(TICKER, inflate(&deflate(TICKER)).unwrap())
+    }
+
+    #[test]
+    fn backoff_should_double_delay_up_to_max() {
+        let backoff = Backoff {
+            initial: Duration::from_millis(100),
+            max: Duration::from_millis(500),
+            max_retries: Some(5),
+        };
+        assert_eq!(Duration::from_millis(100), backoff.delay(1));
+        assert_eq!(Duration::from_millis(400), backoff.delay(3));
+        assert_eq!(Duration::from_millis(500), backoff.delay(10));
+        assert!(!backoff.exhausted(5));
+        assert!(backoff.exhausted(6))
+    }
+
+    #[test]
+    fn live_feed_should_stream_prices_and_reconnect() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut subscriptions = vec![];
+            for frame in [
+                OwnedMessage::Text(TICKER.into()),
+                OwnedMessage::Binary(deflate(&TICKER.replace("9999.99", "10000.5"))),
+            ] {
+                let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+                subscriptions.push(client.recv_message().unwrap());
+                client.send_message(&OwnedMessage::Ping(vec![1])).unwrap();
+                assert_eq!(OwnedMessage::Pong(vec![1]), client.recv_message().unwrap());
+                client.send_message(&frame).unwrap();
+                client.send_message(&OwnedMessage::Close(None)).unwrap();
+            }
+            subscriptions
+        });
+
+        let (sender, receiver) = channel::unbounded();
+        LiveFeed::new(
+            url,
+            vec![btc_usdt()],
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+        )
+        .with_backoff(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+            max_retries: Some(2),
+        })
+        .start(sender)
+        .join()
+        .unwrap();
+
+        let actual: Vec<MsgData> = receiver.iter().map(|msg| msg.data).collect();
+        let price = |price: f64| {
+            MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id: "BTC/USDT",
+                datetime: 1597026383085,
+                price,
+                candle: None,
+            })
+        };
+        assert_eq!(
+            vec![price(9999.99), price(10000.5), MsgData::Shutdown],
+            actual
+        );
+        let subscribe = OwnedMessage::Text(subscribe_request(&Channel::Tickers, &[btc_usdt()]));
+        assert_eq!(vec![subscribe.clone(), subscribe], server.join().unwrap())
+    }
+
+    #[test]
+    fn live_feed_should_ping_idle_connection_and_reconnect_without_pong() {
+        let mut server = Server::bind("127.0.0.1:0").unwrap();
+        let url = format!("ws://{}", server.local_addr().unwrap());
+        let server = thread::spawn(move || {
+            let mut silent = server.accept().ok().unwrap().accept().ok().unwrap();
+            silent.recv_message().unwrap();
+            let ping = silent.recv_message().unwrap();
+            let mut client = server.accept().ok().unwrap().accept().ok().unwrap();
+            client.recv_message().unwrap();
+            client.send_message(&OwnedMessage::Close(None)).unwrap();
+            ping
+        });
+
+        let (sender, receiver) = channel::unbounded();
+        LiveFeed::new(
+            url,
+            vec![btc_usdt()],
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+        )
+        .with_ping_interval(Duration::from_millis(50))
+        .with_backoff(Backoff {
+            initial: Duration::from_millis(10),
+            max: Duration::from_millis(20),
+            max_retries: Some(1),
+        })
+        .start(sender)
+        .join()
+        .unwrap();
+
+        let actual: Vec<MsgData> = receiver.iter().map(|msg| msg.data).collect();
+        assert_eq!(vec![MsgData::Shutdown], actual);
+        assert_eq!(OwnedMessage::Text("ping".into()), server.join().unwrap())
+    }
+}
@@ -0,0 +1,152 @@
+use super::Amount;
+use crate::messaging::message::{Price, Timestamp};
+use std::collections::VecDeque;
+
+pub const THIRTY_DAYS_MILLIS: Timestamp = 30 * 24 * 60 * 60 * 1000;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum FeeCurrency {
+    Base,
+    #[default]
+    Quote,
+    Separate {
+        currency: String,
+        price: Price,
+    },
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct FeeTier {
+    pub min_volume: Amount,
+    pub maker: f64,
+    pub taker: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct FeeSchedule {
+    pub maker: f64,
+    pub taker: f64,
+    pub tiers: Vec<FeeTier>,
+    pub currency: FeeCurrency,
+}
+
+impl FeeSchedule {
+    pub fn flat(fee: f64) -> Self {
+        FeeSchedule {
+            maker: fee,
+            taker: fee,
+            ..Default::default()
+        }
+    }
+
+    pub fn rate(&self, liquidity: Liquidity, volume: Amount) -> f64 {
+        let (maker, taker) = self
+            .tiers
+            .iter()
+            .filter(|tier| tier.min_volume <= volume)
+            .max_by(|a, b| a.min_volume.total_cmp(&b.min_volume))
+            .map(|tier| (tier.maker, tier.taker))
+            .unwrap_or((self.maker, self.taker));
+        match liquidity {
+            Liquidity::Maker => maker,
+            Liquidity::Taker => taker,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct VolumeTracker {
+    trades: VecDeque<(Timestamp, Amount)>,
+    volume: Amount,
+}
+
+impl VolumeTracker {
+    pub fn add(&mut self, time: Timestamp, notional: Amount) {
+        self.trades.push_back((time, notional));
+        self.volume += notional;
+    }
+
+    pub fn volume(&mut self, now: Timestamp) -> Amount {
+        while let Some((time, notional)) = self.trades.front().copied() {
+            if time + THIRTY_DAYS_MILLIS > now {
+                break;
+            }
+            self.volume -= notional;
+            self.trades.pop_front();
+        }
+        self.volume
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn tiered() -> FeeSchedule {
+        FeeSchedule {
+            maker: 0.001,
+            taker: 0.002,
+            tiers: vec![
+                FeeTier {
+                    min_volume: 1000.0,
+                    maker: 0.0008,
+                    taker: 0.0015,
+                },
+                FeeTier {
+                    min_volume: 10000.0,
+                    maker: 0.0005,
+                    taker: 0.001,
+                },
+            ],
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn rate_should_separate_maker_and_taker() {
+        let fees = tiered();
+        assert_eq!(0.001, fees.rate(Liquidity::Maker, 0.0));
+        assert_eq!(0.002, fees.rate(Liquidity::Taker, 0.0));
+    }
+
+    #[test]
+    fn rate_should_use_highest_reached_tier() {
+        let fees = tiered();
+        assert_eq!(0.0015, fees.rate(Liquidity::Taker, 5000.0));
+        assert_eq!(0.0005, fees.rate(Liquidity::Maker, 10000.0));
+    }
+
+    #[test]
+    fn flat_should_charge_same_fee_for_maker_and_taker() {
+        let fees = FeeSchedule::flat(0.1);
+        assert_eq!(0.1, fees.rate(Liquidity::Maker, 0.0));
+        assert_eq!(0.1, fees.rate(Liquidity::Taker, 0.0));
+    }
+
+    #[test]
+    fn volume_tracker_should_sum_recent_volume() {
+        let mut tracker = VolumeTracker {
+            ..Default::default()
+        };
+        tracker.add(0, 100.0);
+        tracker.add(1000, 50.0);
+        assert_eq!(150.0, tracker.volume(2000))
+    }
+
+    #[test]
+    fn volume_tracker_should_drop_volume_older_than_thirty_days() {
+        let mut tracker = VolumeTracker {
+            ..Default::default()
+        };
+        tracker.add(0, 100.0);
+        tracker.add(1000, 50.0);
+        assert_eq!(50.0, tracker.volume(THIRTY_DAYS_MILLIS + 500))
+    }
+}
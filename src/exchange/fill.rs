@@ -0,0 +1,140 @@
+use super::OrderType;
+use crate::messaging::message::{Candle, PairId, Price, Timestamp};
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum FillModel {
+    #[default]
+    Close,
+    NextOpen,
+    VolumeWeighted,
+    WorstOfHighLow,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Quote {
+    pub pair_id: PairId,
+    pub time: Timestamp,
+    pub price: Price,
+    pub candle: Option<Candle>,
+    pub next_open: Option<Price>,
+}
+
+impl FillModel {
+    pub fn fill_price(&self, quote: &Quote, order_type: OrderType) -> Price {
+        match self {
+            FillModel::Close => quote.price,
+            FillModel::NextOpen => quote.next_open.unwrap_or(quote.price),
+            FillModel::VolumeWeighted => match quote.candle {
+                Some(candle) if candle.volume_base > 0.0 => {
+                    candle.volume_quote / candle.volume_base
+                }
+                Some(candle) => (candle.high + candle.low + candle.close) / 3.0,
+                None => quote.price,
+            },
+            FillModel::WorstOfHighLow => match (quote.candle, order_type) {
+                (Some(candle), OrderType::Buy) => candle.high,
+                (Some(candle), OrderType::Sell) => candle.low,
+                (None, _) => quote.price,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn quote() -> Quote {
+        Quote {
+            pair_id: "BTC/USDT",
+            time: 0,
+            price: 10.0,
+            candle: Some(Candle {
+                open: 9.0,
+                high: 12.0,
+                low: 8.0,
+                close: 10.0,
+                volume_base: 4.0,
+                volume_quote: 42.0,
+            }),
+            next_open: Some(11.0),
+        }
+    }
+
+    #[test]
+    fn close_should_fill_at_price() {
+        assert_eq!(10.0, FillModel::Close.fill_price(&quote(), OrderType::Buy));
+    }
+
+    #[test]
+    fn next_open_should_fill_at_open_of_next_candle() {
+        assert_eq!(
+            11.0,
+            FillModel::NextOpen.fill_price(&quote(), OrderType::Buy)
+        );
+    }
+
+    #[test]
+    fn next_open_should_fall_back_to_price_without_next_candle() {
+        let quote = Quote {
+            next_open: None,
+            ..quote()
+        };
+        assert_eq!(
+            10.0,
+            FillModel::NextOpen.fill_price(&quote, OrderType::Sell)
+        );
+    }
+
+    #[test]
+    fn volume_weighted_should_fill_at_quote_volume_per_base_volume() {
+        assert_eq!(
+            10.5,
+            FillModel::VolumeWeighted.fill_price(&quote(), OrderType::Buy)
+        );
+    }
+
+    #[test]
+    fn volume_weighted_should_fill_at_typical_price_without_volume() {
+        let quote = Quote {
+            candle: Some(Candle {
+                volume_base: 0.0,
+                ..quote().candle.unwrap()
+            }),
+            ..quote()
+        };
+        assert_eq!(
+            10.0,
+            FillModel::VolumeWeighted.fill_price(&quote, OrderType::Buy)
+        );
+    }
+
+    #[test]
+    fn worst_of_high_low_should_buy_at_high() {
+        assert_eq!(
+            12.0,
+            FillModel::WorstOfHighLow.fill_price(&quote(), OrderType::Buy)
+        );
+    }
+
+    #[test]
+    fn worst_of_high_low_should_sell_at_low() {
+        assert_eq!(
+            8.0,
+            FillModel::WorstOfHighLow.fill_price(&quote(), OrderType::Sell)
+        );
+    }
+
+    #[test]
+    fn worst_of_high_low_should_fall_back_to_price_without_candle() {
+        let quote = Quote {
+            candle: None,
+            ..quote()
+        };
+        assert_eq!(
+            10.0,
+            FillModel::WorstOfHighLow.fill_price(&quote, OrderType::Buy)
+        );
+    }
+}
@@ -0,0 +1,94 @@
+use crate::messaging::message::Timestamp;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Latency {
+    #[default]
+    None,
+    Fixed(Timestamp),
+    Random {
+        min: Timestamp,
+        max: Timestamp,
+        seed: u64,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct LatencyModel {
+    latency: Latency,
+    rng: StdRng,
+}
+
+impl Default for LatencyModel {
+    fn default() -> Self {
+        LatencyModel::new(Latency::None)
+    }
+}
+
+impl LatencyModel {
+    pub fn new(latency: Latency) -> Self {
+        let seed = match latency {
+            Latency::Random { seed, .. } => seed,
+            _ => 0,
+        };
+        LatencyModel {
+            latency,
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn delay(&mut self) -> Timestamp {
+        match self.latency {
+            Latency::None => 0,
+            Latency::Fixed(delay) => delay,
+            Latency::Random { min, max, .. } if min < max => self.rng.gen_range(min..=max),
+            Latency::Random { min, .. } => min,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn delay_should_be_zero_without_latency() {
+        let mut model = LatencyModel::new(Latency::None);
+        assert_eq!(0, model.delay())
+    }
+
+    #[test]
+    fn delay_should_be_fixed() {
+        let mut model = LatencyModel::new(Latency::Fixed(250));
+        assert_eq!(250, model.delay());
+        assert_eq!(250, model.delay())
+    }
+
+    #[test]
+    fn delay_should_stay_within_random_range() {
+        let mut model = LatencyModel::new(Latency::Random {
+            min: 100,
+            max: 200,
+            seed: 42,
+        });
+        for _ in 0..100 {
+            let delay = model.delay();
+            assert!((100..=200).contains(&delay))
+        }
+    }
+
+    #[test]
+    fn delay_should_repeat_for_same_seed() {
+        let latency = Latency::Random {
+            min: 0,
+            max: 1000,
+            seed: 7,
+        };
+        let mut first = LatencyModel::new(latency);
+        let mut second = LatencyModel::new(latency);
+        let expected: Vec<Timestamp> = (0..10).map(|_| first.delay()).collect();
+        let actual: Vec<Timestamp> = (0..10).map(|_| second.delay()).collect();
+        assert_eq!(expected, actual)
+    }
+}
@@ -0,0 +1,782 @@
+use crate::messaging::message::{Candle, PairId, Price, Timestamp};
+use flate2::{read::MultiGzDecoder, write::GzEncoder, Compression};
+use serde::{
+    de::{self, MapAccess, SeqAccess, Visitor},
+    Deserialize, Deserializer,
+};
+use serde_json::json;
+use std::{
+    fmt, fs,
+    io::{self, BufRead, BufReader, Read, Write},
+};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+pub const CSV_HEADER: &str = "open_time,open,high,low,close,volume,close_time,quote_volume\n";
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum DataFormat {
+    #[default]
+    Auto,
+    // Arrays of candle objects, chunks of such arrays or JSON Lines.
+    Json,
+    Csv,
+    Okx,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct DataFile {
+    pub pair_id: PairId,
+    pub path: String,
+    pub format: DataFormat,
+}
+
+impl DataFile {
+    pub fn new(pair_id: PairId, path: impl Into<String>) -> Self {
+        DataFile {
+            pair_id,
+            path: path.into(),
+            format: DataFormat::Auto,
+        }
+    }
+
+    pub fn with_format(mut self, format: DataFormat) -> Self {
+        self.format = format;
+        self
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum LoadError {
+    Io {
+        path: String,
+        reason: String,
+    },
+    Parse {
+        path: String,
+        line: usize,
+        column: usize,
+        reason: String,
+    },
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadError::Io { path, reason } => write!(f, "{}: {}", path, reason),
+            LoadError::Parse {
+                path,
+                line,
+                column,
+                reason,
+            } => write!(f, "{}:{}:{}: {}", path, line, column, reason),
+        }
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Kline {
+    pub time: Timestamp,
+    pub close: Price,
+    pub candle: Option<Candle>,
+}
+
+pub type Klines = Box<dyn Iterator<Item = Result<Kline, LoadError>> + Send + Sync>;
+
+pub fn read_klines(file: &DataFile) -> Result<Klines, LoadError> {
+    let mut reader = open(&file.path)?;
+    let format = match file.format {
+        DataFormat::Auto => detect(&file.path, &mut reader),
+        format => format,
+    };
+    let path = file.path.clone();
+    let klines: Klines = match format {
+        DataFormat::Csv => Box::new(csv_klines(reader, path)),
+        DataFormat::Okx => Box::new(okx_klines(reader, path)),
+        _ => Box::new(json_klines(reader, path)),
+    };
+    Ok(Box::new(klines.scan(false, |failed, kline| {
+        if *failed {
+            return None;
+        }
+        *failed = kline.is_err();
+        Some(kline)
+    })))
+}
+
+pub fn detect_format(file: &DataFile) -> Result<DataFormat, LoadError> {
+    match file.format {
+        DataFormat::Auto => Ok(detect(&file.path, &mut open(&file.path)?)),
+        format => Ok(format),
+    }
+}
+
+pub fn validate(file: &DataFile) -> Result<(), LoadError> {
+    for kline in read_klines(file)? {
+        kline?;
+    }
+    Ok(())
+}
+
+pub fn has_content(path: &str) -> bool {
+    fs::metadata(path).is_ok_and(|metadata| metadata.len() > 0)
+}
+
+// Appends the klines in the format of the file, creating it if needed. Without a
+// given format the one of the existing file is kept, new files use the extension.
+pub fn append_klines(
+    file: &DataFile,
+    klines: &[Kline],
+    interval: Option<Timestamp>,
+) -> Result<(), LoadError> {
+    if klines.is_empty() {
+        return Ok(());
+    }
+    let exists = has_content(&file.path);
+    let format = match file.format {
+        DataFormat::Auto if exists => detect_format(file)?,
+        DataFormat::Auto if file.path.trim_end_matches(".gz").ends_with(".csv") => DataFormat::Csv,
+        DataFormat::Auto => DataFormat::Json,
+        format => format,
+    };
+    let candles = klines.iter().map(|kline| {
+        let candle = kline.candle.unwrap_or(Candle {
+            open: kline.close,
+            high: kline.close,
+            low: kline.close,
+            close: kline.close,
+            ..Default::default()
+        });
+        (kline.time, candle)
+    });
+    let mut content = String::new();
+    match format {
+        DataFormat::Csv => {
+            if !exists {
+                content.push_str(CSV_HEADER);
+            }
+            for (time, candle) in candles {
+                let close_time = interval
+                    .map(|interval| (time + interval - 1).to_string())
+                    .unwrap_or_default();
+                content.push_str(&format!(
+                    "{},{},{},{},{},{},{},{}\n",
+                    time,
+                    candle.open,
+                    candle.high,
+                    candle.low,
+                    candle.close,
+                    candle.volume_base,
+                    close_time,
+                    candle.volume_quote
+                ));
+            }
+        }
+        DataFormat::Okx => {
+            let rows: Vec<_> = candles
+                .map(|(time, candle)| {
+                    json!([
+                        time.to_string(),
+                        candle.open.to_string(),
+                        candle.high.to_string(),
+                        candle.low.to_string(),
+                        candle.close.to_string(),
+                        candle.volume_base.to_string(),
+                        candle.volume_quote.to_string(),
+                    ])
+                })
+                .collect();
+            content.push_str(&format!("{}\n", json!(rows)));
+        }
+        _ => {
+            for (time, candle) in candles {
+                let record = json!({
+                    "time": time as u64,
+                    "open": candle.open,
+                    "high": candle.high,
+                    "low": candle.low,
+                    "close": candle.close,
+                    "volume_base": candle.volume_base,
+                    "volume_quote": candle.volume_quote,
+                });
+                content.push_str(&format!("{}\n", record));
+            }
+        }
+    }
+    let write = || -> io::Result<()> {
+        let mut out = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&file.path)?;
+        // gzip members can be concatenated, so appending keeps the file readable
+        if file.path.ends_with(".gz") {
+            let mut encoder = GzEncoder::new(out, Compression::default());
+            encoder.write_all(content.as_bytes())?;
+            encoder.finish()?;
+        } else {
+            out.write_all(content.as_bytes())?;
+        }
+        Ok(())
+    };
+    write().map_err(|err| LoadError::Io {
+        path: file.path.clone(),
+        reason: err.to_string(),
+    })
+}
+
+type Reader = Box<dyn BufRead + Send + Sync>;
+
+fn open(path: &str) -> Result<Reader, LoadError> {
+    let io_error = |err: io::Error| LoadError::Io {
+        path: path.into(),
+        reason: err.to_string(),
+    };
+    let mut reader = BufReader::new(fs::File::open(path).map_err(io_error)?);
+    if reader
+        .fill_buf()
+        .map_err(io_error)?
+        .starts_with(&GZIP_MAGIC)
+    {
+        let mut reader: Reader = Box::new(BufReader::new(MultiGzDecoder::new(reader)));
+        reader.fill_buf().map_err(io_error)?;
+        return Ok(reader);
+    }
+    Ok(Box::new(reader))
+}
+
+// Only looks at the first buffered chunk of the (decompressed) file.
+fn detect(path: &str, reader: &mut Reader) -> DataFormat {
+    let name = path.trim_end_matches(".gz");
+    if name.ends_with(".csv") {
+        return DataFormat::Csv;
+    }
+    let head = String::from_utf8_lossy(reader.fill_buf().unwrap_or_default());
+    let compact: String = head.chars().filter(|c| !c.is_whitespace()).collect();
+    if compact.starts_with("[[") || (compact.starts_with('{') && compact.contains("\"data\":[")) {
+        DataFormat::Okx
+    } else if compact.starts_with('[') || compact.starts_with('{') {
+        DataFormat::Json
+    } else {
+        DataFormat::Csv
+    }
+}
+
+fn parse_error(path: &str, err: serde_json::Error) -> LoadError {
+    let reason = err.to_string();
+    let position = format!(" at line {} column {}", err.line(), err.column());
+    LoadError::Parse {
+        path: path.into(),
+        line: err.line(),
+        column: err.column(),
+        reason: reason.trim_end_matches(&position).into(),
+    }
+}
+
+#[derive(Deserialize)]
+struct CandleRecord {
+    time: u128,
+    open: Option<f64>,
+    high: Option<f64>,
+    low: Option<f64>,
+    close: f64,
+    #[serde(default)]
+    volume_base: f64,
+    #[serde(default)]
+    volume_quote: f64,
+}
+
+impl CandleRecord {
+    fn kline(&self) -> Kline {
+        let candle = || {
+            Some(Candle {
+                open: self.open?,
+                high: self.high?,
+                low: self.low?,
+                close: self.close,
+                volume_base: self.volume_base,
+                volume_quote: self.volume_quote,
+            })
+        };
+        Kline {
+            time: self.time,
+            close: self.close,
+            candle: candle(),
+        }
+    }
+}
+
+// Turns the brackets and commas of top level arrays into whitespace, so JSON
+// arrays, chunks of arrays and JSON Lines all read as a plain sequence of records.
+// Line and column positions stay the same.
+struct JsonRecords<R> {
+    inner: R,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl<R: Read> JsonRecords<R> {
+    fn new(inner: R) -> Self {
+        JsonRecords {
+            inner,
+            depth: 0,
+            in_string: false,
+            escaped: false,
+        }
+    }
+
+    fn strip(&mut self, byte: u8) -> u8 {
+        if self.in_string {
+            match byte {
+                _ if self.escaped => self.escaped = false,
+                b'\\' => self.escaped = true,
+                b'"' => self.in_string = false,
+                _ => {}
+            }
+            return byte;
+        }
+        match (byte, self.depth) {
+            (b'[' | b']' | b',', 0) => return b' ',
+            (b'"', _) => self.in_string = true,
+            (b'{' | b'[', _) => self.depth += 1,
+            (b'}' | b']', _) => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+        byte
+    }
+}
+
+impl<R: Read> Read for JsonRecords<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        for byte in &mut buf[..read] {
+            *byte = self.strip(*byte);
+        }
+        Ok(read)
+    }
+}
+
+fn json_klines(
+    reader: Reader,
+    path: String,
+) -> impl Iterator<Item = Result<Kline, LoadError>> + Send + Sync {
+    serde_json::Deserializer::from_reader(JsonRecords::new(reader))
+        .into_iter::<CandleRecord>()
+        .map(move |record| {
+            record
+                .map(|record| record.kline())
+                .map_err(|err| parse_error(&path, err))
+        })
+}
+
+// A decimal given either as JSON number or as string, like OKX does.
+struct Decimal(f64);
+
+impl<'de> Deserialize<'de> for Decimal {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(f64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(number) => Ok(Decimal(number)),
+            Raw::Text(text) => text
+                .parse()
+                .map(Decimal)
+                .map_err(|_| de::Error::custom(format!("invalid decimal \"{}\"", text))),
+        }
+    }
+}
+
+struct OkxRow(Kline);
+
+impl<'de> Deserialize<'de> for OkxRow {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RowVisitor;
+
+        impl<'de> Visitor<'de> for RowVisitor {
+            type Value = OkxRow;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a row of [ts, o, h, l, c, vol, volCcy]")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OkxRow, A::Error> {
+                let mut values = vec![];
+                while let Some(Decimal(value)) = seq.next_element()? {
+                    values.push(value);
+                }
+                if values.len() < 5 {
+                    return Err(de::Error::invalid_length(values.len(), &self));
+                }
+                let value = |index: usize| values.get(index).copied().unwrap_or_default();
+                Ok(OkxRow(Kline {
+                    time: value(0) as Timestamp,
+                    close: value(4),
+                    candle: Some(Candle {
+                        open: value(1),
+                        high: value(2),
+                        low: value(3),
+                        close: value(4),
+                        volume_base: value(5),
+                        volume_quote: value(6),
+                    }),
+                }))
+            }
+        }
+
+        deserializer.deserialize_seq(RowVisitor)
+    }
+}
+
+// Either a whole REST response or just its `data` array. OKX sends the newest
+// candle first, so every page is sorted before its candles are handed out.
+struct OkxPage(Vec<Kline>);
+
+impl<'de> Deserialize<'de> for OkxPage {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct PageVisitor;
+
+        impl<'de> Visitor<'de> for PageVisitor {
+            type Value = OkxPage;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "an OKX candles response or its data array")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<OkxPage, A::Error> {
+                let mut klines = vec![];
+                while let Some(OkxRow(kline)) = seq.next_element()? {
+                    klines.push(kline);
+                }
+                klines.sort_by_key(|kline| kline.time);
+                Ok(OkxPage(klines))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<OkxPage, A::Error> {
+                let mut page = None;
+                while let Some(key) = map.next_key::<String>()? {
+                    if key == "data" {
+                        page = Some(map.next_value::<OkxPage>()?);
+                    } else {
+                        map.next_value::<de::IgnoredAny>()?;
+                    }
+                }
+                page.ok_or_else(|| de::Error::missing_field("data"))
+            }
+        }
+
+        deserializer.deserialize_any(PageVisitor)
+    }
+}
+
+fn okx_klines(
+    reader: Reader,
+    path: String,
+) -> impl Iterator<Item = Result<Kline, LoadError>> + Send + Sync {
+    serde_json::Deserializer::from_reader(reader)
+        .into_iter::<OkxPage>()
+        .flat_map(move |page| -> Vec<Result<Kline, LoadError>> {
+            match page {
+                Ok(OkxPage(klines)) => klines.into_iter().map(Ok).collect(),
+                Err(err) => vec![Err(parse_error(&path, err))],
+            }
+        })
+}
+
+// Binance kline columns: open time, open, high, low, close, volume, close time,
+// quote asset volume, ... An optional header line is skipped.
+fn csv_klines(
+    reader: Reader,
+    path: String,
+) -> impl Iterator<Item = Result<Kline, LoadError>> + Send + Sync {
+    reader.lines().enumerate().filter_map(move |(index, line)| {
+        let line = match line {
+            Ok(line) => line,
+            Err(err) => {
+                return Some(Err(LoadError::Io {
+                    path: path.clone(),
+                    reason: err.to_string(),
+                }))
+            }
+        };
+        let is_header = index == 0
+            && line
+                .split(',')
+                .next()
+                .is_some_and(|field| field.trim().parse::<f64>().is_err());
+        if is_header || line.trim().is_empty() {
+            return None;
+        }
+        Some(
+            csv_kline(&line).map_err(|(column, reason)| LoadError::Parse {
+                path: path.clone(),
+                line: index + 1,
+                column,
+                reason,
+            }),
+        )
+    })
+}
+
+fn csv_kline(line: &str) -> Result<Kline, (usize, String)> {
+    let mut fields = vec![];
+    let mut column = 1;
+    for field in line.split(',') {
+        fields.push((column, field));
+        column += field.chars().count() + 1;
+    }
+    let value = |index: usize| -> Result<f64, (usize, String)> {
+        let (column, field) = fields
+            .get(index)
+            .copied()
+            .ok_or_else(|| (column, format!("missing column {}", index + 1)))?;
+        field
+            .trim()
+            .trim_matches('"')
+            .parse()
+            .map_err(|_| (column, format!("invalid number \"{}\"", field)))
+    };
+    let optional = |index: usize| -> Result<f64, (usize, String)> {
+        if index < fields.len() {
+            value(index)
+        } else {
+            Ok(0.0)
+        }
+    };
+    let time = value(0)? as Timestamp;
+    let (open, high, low, close) = (value(1)?, value(2)?, value(3)?, value(4)?);
+    Ok(Kline {
+        time,
+        close,
+        candle: Some(Candle {
+            open,
+            high,
+            low,
+            close,
+            volume_base: optional(5)?,
+            volume_quote: optional(7)?,
+        }),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::{write::GzEncoder, Compression};
+    use pretty_assertions::assert_eq;
+    use std::io::Write;
+
+    fn write_file(name: &str, content: &[u8]) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn gzip(content: &str) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(vec![], Compression::default());
+        encoder.write_all(content.as_bytes()).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn read_times(name: &str, content: &str) -> Vec<Timestamp> {
+        let file = DataFile::new("BTC/USDT", write_file(name, content.as_bytes()));
+        read_klines(&file)
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect()
+    }
+
+    fn read_error(name: &str, content: &str) -> LoadError {
+        let file = DataFile::new("BTC/USDT", write_file(name, content.as_bytes()));
+        validate(&file).unwrap_err()
+    }
+
+    const CSV: &str = "open_time,open,high,low,close,volume,close_time,quote_volume\n\
+        1,10.0,12.0,9.0,11.0,2.0,299,22.0\n\
+        300,11.0,13.0,10.0,12.0,3.0,599,36.0\n";
+
+    #[test]
+    fn read_klines_should_read_json_array() {
+        let actual = read_times(
+            "loader_array.json",
+            r#"[{"time": 1, "close": 1.0}, {"time": 2, "close": 2.0}]"#,
+        );
+        assert_eq!(vec![1, 2], actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_json_lines() {
+        let actual = read_times(
+            "loader_lines.jsonl",
+            "{\"time\": 1, \"close\": 1.0}\n{\"time\": 2, \"close\": 2.0}\n",
+        );
+        assert_eq!(vec![1, 2], actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_chunked_arrays() {
+        let actual = read_times(
+            "loader_chunks.json",
+            "[{\"time\": 1, \"close\": 1.0}, {\"time\": 2, \"close\": 2.0}]\n[{\"time\": 3, \"close\": 3.0}]",
+        );
+        assert_eq!(vec![1, 2, 3], actual)
+    }
+
+    #[test]
+    fn json_records_should_keep_brackets_and_commas_inside_strings() {
+        let mut records = JsonRecords::new(r#"[{"a": "[x, y]"}]"#.as_bytes());
+        let mut actual = String::new();
+        records.read_to_string(&mut actual).unwrap();
+        assert_eq!(r#" {"a": "[x, y]"} "#, actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_binance_csv() {
+        let file = DataFile::new("BTC/USDT", write_file("loader_klines.csv", CSV.as_bytes()));
+        let actual: Vec<Kline> = read_klines(&file).unwrap().map(|k| k.unwrap()).collect();
+        let expected = Kline {
+            time: 1,
+            close: 11.0,
+            candle: Some(Candle {
+                open: 10.0,
+                high: 12.0,
+                low: 9.0,
+                close: 11.0,
+                volume_base: 2.0,
+                volume_quote: 22.0,
+            }),
+        };
+        assert_eq!(2, actual.len());
+        assert_eq!(expected, actual[0])
+    }
+
+    #[test]
+    fn read_klines_should_read_csv_without_header() {
+        let actual = read_times("loader_no_header.csv", "1,10,12,9,11\n2,11,13,10,12\n");
+        assert_eq!(vec![1, 2], actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_okx_rows_oldest_first() {
+        let actual = read_times(
+            "loader_okx_rows.json",
+            r#"[["300","11","13","10","12","3","36"],["1","10","12","9","11","2","22"]]"#,
+        );
+        assert_eq!(vec![1, 300], actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_okx_responses() {
+        let actual = read_times(
+            "loader_okx_response.json",
+            "{\"code\":\"0\",\"msg\":\"\",\"data\":[[\"2\",\"1\",\"1\",\"1\",\"1\",\"0\",\"0\"],[\"1\",\"1\",\"1\",\"1\",\"1\",\"0\",\"0\"]]}\n\
+             {\"code\":\"0\",\"msg\":\"\",\"data\":[[\"3\",\"1\",\"1\",\"1\",\"1\",\"0\",\"0\"]]}",
+        );
+        assert_eq!(vec![1, 2, 3], actual)
+    }
+
+    #[test]
+    fn read_klines_should_read_gzip_compressed_files() {
+        let file = DataFile::new("BTC/USDT", write_file("loader_klines.csv.gz", &gzip(CSV)));
+        let actual: Vec<Timestamp> = read_klines(&file)
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect();
+        assert_eq!(vec![1, 300], actual)
+    }
+
+    #[test]
+    fn read_klines_should_detect_gzip_compressed_json() {
+        let path = write_file("loader_gzip.data", &gzip(r#"[{"time": 5, "close": 1.0}]"#));
+        let actual: Vec<Timestamp> = read_klines(&DataFile::new("BTC/USDT", path))
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect();
+        assert_eq!(vec![5], actual)
+    }
+
+    #[test]
+    fn read_klines_should_use_given_format() {
+        let path = write_file("loader_explicit.txt", b"1,10,12,9,11\n");
+        let file = DataFile::new("BTC/USDT", path).with_format(DataFormat::Csv);
+        let actual: Vec<Timestamp> = read_klines(&file)
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect();
+        assert_eq!(vec![1], actual)
+    }
+
+    #[test]
+    fn validate_should_report_line_and_column_of_json_errors() {
+        let actual = read_error(
+            "loader_broken.json",
+            "[\n{\"time\": 1, \"close\": 1.0},\n{\"time\": 2, \"close\": \"x\"}\n]",
+        );
+        match actual {
+            LoadError::Parse { line, column, .. } => assert_eq!((3, 24), (line, column)),
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn validate_should_report_line_and_column_of_csv_errors() {
+        let actual = read_error("loader_broken.csv", "1,10,12,9,11\n2,11,oops,10,12\n");
+        match actual {
+            LoadError::Parse {
+                line,
+                column,
+                reason,
+                ..
+            } => {
+                assert_eq!((2, 6), (line, column));
+                assert_eq!("invalid number \"oops\"", reason)
+            }
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn validate_should_report_missing_csv_columns() {
+        let actual = read_error("loader_short.csv", "1,10,12\n");
+        match actual {
+            LoadError::Parse { line, reason, .. } => {
+                assert_eq!(1, line);
+                assert_eq!("missing column 4", reason)
+            }
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn validate_should_report_line_and_column_of_okx_errors() {
+        let actual = read_error(
+            "loader_broken_okx.json",
+            "[[\"1\",\"1\",\"1\",\"1\",\"1\"],\n[\"2\",\"1\",\"bad\",\"1\",\"1\"]]",
+        );
+        match actual {
+            LoadError::Parse { line, .. } => assert_eq!(2, line),
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[test]
+    fn validate_should_report_missing_file() {
+        let actual = validate(&DataFile::new("BTC/USDT", "does_not_exist.json")).unwrap_err();
+        assert!(matches!(actual, LoadError::Io { .. }))
+    }
+
+    #[test]
+    fn load_error_should_display_position() {
+        let error = LoadError::Parse {
+            path: "data.csv".into(),
+            line: 3,
+            column: 7,
+            reason: "invalid number \"x\"".into(),
+        };
+        assert_eq!("data.csv:3:7: invalid number \"x\"", error.to_string())
+    }
+}
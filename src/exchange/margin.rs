@@ -0,0 +1,123 @@
+use super::{Amount, Assets};
+use crate::messaging::message::{Price, Timestamp};
+
+pub const DAY_MILLIS: Timestamp = 24 * 60 * 60 * 1000;
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub struct MarginOptions {
+    pub leverage: f64,
+    pub daily_interest: f64,
+    pub maintenance_margin: f64,
+}
+
+impl Default for MarginOptions {
+    fn default() -> Self {
+        MarginOptions {
+            leverage: 1.0,
+            daily_interest: 0.0,
+            maintenance_margin: 0.0,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct Exposure {
+    pub assets: Amount,
+    pub liabilities: Amount,
+}
+
+impl Exposure {
+    pub fn of(assets: &Assets, base: &str, quote: &str, price: Price) -> Self {
+        Exposure {
+            assets: assets.amount(quote) + assets.amount(base) * price,
+            liabilities: assets.loan(quote) + assets.loan(base) * price,
+        }
+    }
+
+    pub fn equity(&self) -> Amount {
+        self.assets - self.liabilities
+    }
+}
+
+impl MarginOptions {
+    pub fn can_borrow(&self, exposure: Exposure, value: Amount) -> bool {
+        exposure.liabilities + value <= (self.leverage - 1.0) * exposure.equity()
+    }
+
+    pub fn is_liquidated(&self, exposure: Exposure) -> bool {
+        exposure.liabilities > 0.0
+            && exposure.equity() < self.maintenance_margin * exposure.liabilities
+    }
+
+    pub fn interest(&self, loan: Amount, elapsed: Timestamp) -> Amount {
+        loan * self.daily_interest * elapsed as f64 / DAY_MILLIS as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::Asset;
+    use pretty_assertions::assert_eq;
+
+    fn margin() -> MarginOptions {
+        MarginOptions {
+            leverage: 3.0,
+            daily_interest: 0.001,
+            maintenance_margin: 0.1,
+        }
+    }
+
+    #[test]
+    fn exposure_should_value_balances_and_loans_in_quote() {
+        let mut assets = Assets::from(vec![Asset {
+            name: "USDT".into(),
+            amount: 100.0,
+        }]);
+        assets.borrow("BTC", 2.0);
+        let actual = Exposure::of(&assets, "BTC", "USDT", 10.0);
+        let expected = Exposure {
+            assets: 120.0,
+            liabilities: 20.0,
+        };
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn can_borrow_should_allow_up_to_leverage() {
+        let exposure = Exposure {
+            assets: 100.0,
+            liabilities: 0.0,
+        };
+        assert!(margin().can_borrow(exposure, 200.0));
+        assert!(!margin().can_borrow(exposure, 201.0))
+    }
+
+    #[test]
+    fn is_liquidated_should_trigger_below_maintenance_margin() {
+        let healthy = Exposure {
+            assets: 110.0,
+            liabilities: 100.0,
+        };
+        let unhealthy = Exposure {
+            assets: 109.0,
+            liabilities: 100.0,
+        };
+        assert!(!margin().is_liquidated(healthy));
+        assert!(margin().is_liquidated(unhealthy))
+    }
+
+    #[test]
+    fn is_liquidated_should_ignore_accounts_without_loans() {
+        let exposure = Exposure {
+            assets: 0.0,
+            liabilities: 0.0,
+        };
+        assert!(!margin().is_liquidated(exposure))
+    }
+
+    #[test]
+    fn interest_should_accrue_by_elapsed_days() {
+        assert_eq!(0.2, margin().interest(100.0, 2 * DAY_MILLIS))
+    }
+}
@@ -1,28 +1,176 @@
+pub mod download;
+pub mod feed;
+pub mod fees;
+pub mod fill;
+pub mod latency;
+pub mod loader;
+pub mod margin;
+pub mod okx;
+pub mod paper;
+pub mod resample;
+pub mod rules;
 pub mod simulation;
+pub mod sizing;
+pub mod stream;
 pub mod trade;
 
-use crate::messaging::message::{MessageId, Msg};
+use crate::messaging::message::{Fee, Liquidation, MessageId, Price, Timestamp};
 use anyhow::Result;
 use async_trait::async_trait;
-use std::iter::Iterator;
+use fees::FeeSchedule;
+use fill::FillModel;
+use latency::Latency;
+use margin::MarginOptions;
+use rules::TradingRules;
+use std::collections::HashMap;
+use std::fmt;
+use stream::Events;
 use uuid::Uuid;
 
 pub type Amount = f64;
+pub type OrderId = Uuid;
 
 #[async_trait]
-pub trait Exchange {
-    async fn event_stream(&self) -> Box<dyn Iterator<Item = Msg>>;
+pub trait MarketDataSource {
+    async fn event_stream(&self) -> Events;
+}
+
+#[async_trait]
+pub trait OrderExecutor {
+    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill>;
+
+    async fn place_limit_order(&mut self, order: &LimitOrder) -> Result<OrderId>;
+
+    async fn cancel_order(&mut self, order_id: OrderId) -> Result<()>;
 
-    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Amount>;
+    async fn open_orders(&self) -> Result<Vec<Pair>>;
+
+    async fn poll_fills(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Fill>>;
+
+    async fn poll_liquidations(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Liquidation>>;
+
+    // Reports the current state of an order which was not final when last seen.
+    async fn poll_order(&mut self, last: &Fill) -> Result<Fill>;
 
     async fn fetch_assets(&self) -> Result<Assets>;
 }
 
+// Streams the prices of one venue and executes orders on another, e.g. a live
+// feed of one exchange driving orders on a simulation.
+pub struct CombinedExchange<M, O> {
+    pub market_data: M,
+    pub executor: O,
+}
+
+impl<M, O> CombinedExchange<M, O> {
+    pub fn new(market_data: M, executor: O) -> Self {
+        CombinedExchange {
+            market_data,
+            executor,
+        }
+    }
+}
+
+#[async_trait]
+impl<M, O> MarketDataSource for CombinedExchange<M, O>
+where
+    M: MarketDataSource + Send + Sync,
+    O: Send + Sync,
+{
+    async fn event_stream(&self) -> Events {
+        self.market_data.event_stream().await
+    }
+}
+
+#[async_trait]
+impl<M, O> OrderExecutor for CombinedExchange<M, O>
+where
+    M: Send + Sync,
+    O: OrderExecutor + Send + Sync,
+{
+    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill> {
+        self.executor.place_market_order(order).await
+    }
+
+    async fn place_limit_order(&mut self, order: &LimitOrder) -> Result<OrderId> {
+        self.executor.place_limit_order(order).await
+    }
+
+    async fn cancel_order(&mut self, order_id: OrderId) -> Result<()> {
+        self.executor.cancel_order(order_id).await
+    }
+
+    async fn open_orders(&self) -> Result<Vec<Pair>> {
+        self.executor.open_orders().await
+    }
+
+    async fn poll_fills(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Fill>> {
+        self.executor
+            .poll_fills(correlation_id, correlation_time)
+            .await
+    }
+
+    async fn poll_liquidations(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Liquidation>> {
+        self.executor
+            .poll_liquidations(correlation_id, correlation_time)
+            .await
+    }
+
+    async fn poll_order(&mut self, last: &Fill) -> Result<Fill> {
+        self.executor.poll_order(last).await
+    }
+
+    async fn fetch_assets(&self) -> Result<Assets> {
+        self.executor.fetch_assets().await
+    }
+}
+
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct ExchangeOptions {
-    pub fee: f64,
+    pub fees: FeeSchedule,
+    pub fill_model: FillModel,
+    pub latency: Latency,
+    pub margin: Option<MarginOptions>,
+    pub trading_rules: HashMap<String, TradingRules>,
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderRejected {
+    pub reason: String,
+}
+
+impl OrderRejected {
+    pub fn new(reason: impl Into<String>) -> Self {
+        OrderRejected {
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for OrderRejected {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "order rejected: {}", self.reason)
+    }
 }
 
+impl std::error::Error for OrderRejected {}
+
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Asset {
     pub name: String,
@@ -31,8 +179,58 @@ pub struct Asset {
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Assets {
-    pub base: Option<Asset>,
-    pub quote: Option<Asset>,
+    pub balances: HashMap<String, Amount>,
+    pub loans: HashMap<String, Amount>,
+}
+
+impl Assets {
+    pub fn amount(&self, name: &str) -> Amount {
+        self.balances.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn credit(&mut self, name: &str, amount: Amount) {
+        *self.balances.entry(name.into()).or_default() += amount;
+    }
+
+    pub fn debit(&mut self, name: &str, amount: Amount) {
+        self.credit(name, -amount)
+    }
+
+    pub fn loan(&self, name: &str) -> Amount {
+        self.loans.get(name).copied().unwrap_or_default()
+    }
+
+    pub fn borrow(&mut self, name: &str, amount: Amount) {
+        self.credit(name, amount);
+        *self.loans.entry(name.into()).or_default() += amount;
+    }
+
+    pub fn repay(&mut self, name: &str) {
+        let repaid = self.loan(name).min(self.amount(name)).max(0.0);
+        if repaid > 0.0 {
+            self.debit(name, repaid);
+            *self.loans.entry(name.into()).or_default() -= repaid;
+        }
+        if self.loan(name) <= 0.0 {
+            self.loans.remove(name);
+        }
+    }
+}
+
+impl From<Vec<Asset>> for Assets {
+    fn from(assets: Vec<Asset>) -> Self {
+        let mut result = Assets {
+            ..Default::default()
+        };
+        for asset in assets {
+            result.credit(&asset.name, asset.amount);
+        }
+        result
+    }
+}
+
+pub fn split_pair_id(pair_id: &str) -> (&str, &str) {
+    pair_id.split_once('/').unwrap_or((pair_id, ""))
 }
 
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Default)]
@@ -44,13 +242,16 @@ pub struct Subscription {
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Pair {
-    pub id: Uuid,
+    pub base: String,
+    pub quote: String,
     pub bid_orders: Vec<Order>,
     pub ask_orders: Vec<Order>,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone, Default)]
 pub struct Order {
+    pub id: OrderId,
+    pub correlation_id: MessageId,
     pub price: f64,
     pub amount: f64,
 }
@@ -67,44 +268,158 @@ impl Default for OrderType {
     }
 }
 
+// Placed orders end up filled, rejected or cancelled, possibly after being
+// partially filled first.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum OrderState {
+    Placed,
+    PartiallyFilled,
+    Filled,
+    Rejected,
+    Cancelled,
+}
+
+impl OrderState {
+    pub fn is_final(self) -> bool {
+        matches!(
+            self,
+            OrderState::Filled | OrderState::Rejected | OrderState::Cancelled
+        )
+    }
+
+    pub fn can_become(self, next: OrderState) -> bool {
+        match (self, next) {
+            (OrderState::Placed, _) => true,
+            (OrderState::PartiallyFilled, OrderState::Placed | OrderState::Rejected) => false,
+            (OrderState::PartiallyFilled, _) => true,
+            _ => false,
+        }
+    }
+}
+
 #[derive(PartialEq, Debug, Clone, Default)]
 pub struct MarketOrder {
+    pub correlation_id: MessageId,
+    pub correlation_time: Timestamp,
+    pub base: String,
+    pub quote: String,
+    pub order_type: OrderType,
+    pub amount: f64,
+}
+
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct LimitOrder {
     pub correlation_id: MessageId,
     pub base: String,
     pub quote: String,
     pub order_type: OrderType,
+    pub price: f64,
     pub amount: f64,
 }
 
+#[derive(PartialEq, Debug, Clone)]
+pub struct Fill {
+    pub order_id: OrderId,
+    pub base: String,
+    pub quote: String,
+    pub order_type: OrderType,
+    // received so far, after fees
+    pub amount: Amount,
+    // average price of what was filled so far
+    pub price: Price,
+    pub fee: Fee,
+    pub state: OrderState,
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::messaging::message::{Msg, MsgData, PriceUpdated};
     use pretty_assertions::assert_eq;
+    use stream::EventSource;
 
-    #[derive(Default)]
     pub struct MockExchange {
-        assets: Assets,
+        pub assets: Assets,
+        pub rejection: Option<OrderRejected>,
         pub recorded_orders: Vec<MarketOrder>,
+        pub recorded_limit_orders: Vec<LimitOrder>,
+        pub cancelled_orders: Vec<OrderId>,
+        pub fills: Vec<Fill>,
+        pub liquidations: Vec<Liquidation>,
+        pub placed_state: OrderState,
+        pub order_updates: Vec<Fill>,
     }
 
     impl MockExchange {
         pub fn new(assets: Assets) -> Self {
             MockExchange {
                 assets,
-                ..Default::default()
+                rejection: None,
+                recorded_orders: vec![],
+                recorded_limit_orders: vec![],
+                cancelled_orders: vec![],
+                fills: vec![],
+                liquidations: vec![],
+                placed_state: OrderState::Filled,
+                order_updates: vec![],
             }
         }
     }
 
     #[async_trait]
-    impl Exchange for MockExchange {
-        async fn event_stream(&self) -> Box<dyn Iterator<Item = Msg>> {
-            unimplemented!()
+    impl OrderExecutor for MockExchange {
+        async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill> {
+            self.recorded_orders.push(order.clone());
+            if let Some(rejected) = &self.rejection {
+                return Err(rejected.clone().into());
+            }
+            let amount = match self.placed_state {
+                OrderState::Filled => order.amount * 0.9,
+                _ => 0.0,
+            };
+            Ok(Fill {
+                order_id: Uuid::from_u128(self.recorded_orders.len() as u128),
+                base: order.base.clone(),
+                quote: order.quote.clone(),
+                order_type: order.order_type,
+                amount,
+                price: 0.0,
+                fee: Fee::default(),
+                state: self.placed_state,
+            })
         }
 
-        async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Amount> {
-            self.recorded_orders.push(order.clone());
-            Ok(order.amount * 0.9)
+        async fn place_limit_order(&mut self, order: &LimitOrder) -> Result<OrderId> {
+            self.recorded_limit_orders.push(order.clone());
+            Ok(Uuid::from_u128(self.recorded_limit_orders.len() as u128))
+        }
+
+        async fn cancel_order(&mut self, order_id: OrderId) -> Result<()> {
+            self.cancelled_orders.push(order_id);
+            Ok(())
+        }
+
+        async fn open_orders(&self) -> Result<Vec<Pair>> {
+            Ok(vec![])
+        }
+
+        async fn poll_fills(&mut self, _: MessageId, _: Timestamp) -> Result<Vec<Fill>> {
+            Ok(self.fills.drain(..).collect())
+        }
+
+        async fn poll_liquidations(
+            &mut self,
+            _: MessageId,
+            _: Timestamp,
+        ) -> Result<Vec<Liquidation>> {
+            Ok(self.liquidations.drain(..).collect())
+        }
+
+        async fn poll_order(&mut self, last: &Fill) -> Result<Fill> {
+            if self.order_updates.is_empty() {
+                return Ok(last.clone());
+            }
+            Ok(self.order_updates.remove(0))
         }
 
         async fn fetch_assets(&self) -> Result<Assets> {
@@ -114,13 +429,10 @@ mod tests {
 
     #[async_std::test]
     async fn mock_should_fetch_provided_assets() {
-        let given_assets = Assets {
-            quote: Some(Asset {
-                name: "USD".into(),
-                amount: 50.0,
-            }),
-            base: None,
-        };
+        let given_assets = Assets::from(vec![Asset {
+            amount: 50.0,
+            name: "USD".into(),
+        }]);
         let exchange = MockExchange::new(given_assets.clone());
         let actual = exchange.fetch_assets().await.unwrap();
         assert_eq!(given_assets, actual)
@@ -128,13 +440,10 @@ mod tests {
 
     #[async_std::test]
     async fn mock_should_fetch_different_assets() {
-        let given_assets = Assets {
-            quote: None,
-            base: Some(Asset {
-                name: "BTW".into(),
-                amount: 0.01,
-            }),
-        };
+        let given_assets = Assets::from(vec![Asset {
+            amount: 0.01,
+            name: "BTW".into(),
+        }]);
         let exchange = MockExchange::new(given_assets.clone());
         let actual = exchange.fetch_assets().await.unwrap();
         assert_eq!(given_assets, actual)
@@ -171,4 +480,118 @@ mod tests {
         exchange.place_market_order(&expected_order).await.unwrap();
         assert_eq!(vec![expected_order], exchange.recorded_orders)
     }
+
+    #[async_std::test]
+    async fn mock_should_record_placed_limit_orders() {
+        let mut exchange = MockExchange::new(Assets {
+            ..Default::default()
+        });
+        let expected_order = LimitOrder {
+            base: "BTC".into(),
+            quote: "EUR".into(),
+            amount: 40.0,
+            price: 2.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        };
+        let order_id = exchange.place_limit_order(&expected_order).await.unwrap();
+        assert_eq!(vec![expected_order], exchange.recorded_limit_orders);
+        assert_eq!(Uuid::from_u128(1), order_id)
+    }
+
+    #[async_std::test]
+    async fn mock_should_return_given_fills_once() {
+        let mut exchange = MockExchange::new(Assets {
+            ..Default::default()
+        });
+        exchange.fills = vec![Fill {
+            order_id: Uuid::from_u128(1),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount: 1.0,
+            price: 1.0,
+            fee: Fee::default(),
+            state: OrderState::Filled,
+        }];
+        assert_eq!(1, exchange.poll_fills(Uuid::nil(), 0).await.unwrap().len());
+        assert_eq!(0, exchange.poll_fills(Uuid::nil(), 0).await.unwrap().len())
+    }
+
+    #[test]
+    fn assets_should_sum_balances_of_same_currency() {
+        let assets = Assets::from(vec![
+            Asset {
+                amount: 1.5,
+                name: "BTC".into(),
+            },
+            Asset {
+                amount: 2.0,
+                name: "BTC".into(),
+            },
+        ]);
+        assert_eq!(3.5, assets.amount("BTC"))
+    }
+
+    #[test]
+    fn assets_should_return_zero_for_unknown_currency() {
+        let assets = Assets::from(vec![Asset {
+            amount: 1.5,
+            name: "BTC".into(),
+        }]);
+        assert_eq!(0.0, assets.amount("ETH"))
+    }
+
+    #[test]
+    fn assets_should_credit_and_debit_balances() {
+        let mut assets = Assets {
+            ..Default::default()
+        };
+        assets.credit("ETH", 4.0);
+        assets.debit("ETH", 1.0);
+        assert_eq!(3.0, assets.amount("ETH"))
+    }
+
+    #[async_std::test]
+    async fn combined_exchange_should_stream_prices_of_one_venue_and_execute_on_other() {
+        let price = Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id: "BTC/USDT",
+            price: 10.0,
+            ..Default::default()
+        }));
+        let mut exchange = CombinedExchange::new(
+            EventSource::Events(vec![price.clone()]),
+            MockExchange::new(Assets::from(vec![Asset {
+                amount: 50.0,
+                name: "USDT".into(),
+            }])),
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 50.0,
+            ..Default::default()
+        };
+        exchange.place_market_order(&order).await.unwrap();
+        let actual: Vec<Msg> = exchange.event_stream().await.collect();
+        assert_eq!(vec![price], actual);
+        assert_eq!(vec![order], exchange.executor.recorded_orders);
+        assert_eq!(50.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[test]
+    fn order_state_should_only_leave_open_states() {
+        assert!(OrderState::Placed.can_become(OrderState::PartiallyFilled));
+        assert!(OrderState::Placed.can_become(OrderState::Rejected));
+        assert!(OrderState::PartiallyFilled.can_become(OrderState::PartiallyFilled));
+        assert!(OrderState::PartiallyFilled.can_become(OrderState::Cancelled));
+        assert!(!OrderState::PartiallyFilled.can_become(OrderState::Placed));
+        assert!(!OrderState::Filled.can_become(OrderState::Cancelled));
+        assert!(!OrderState::Cancelled.can_become(OrderState::Filled))
+    }
+
+    #[test]
+    fn split_pair_id_should_return_base_and_quote() {
+        assert_eq!(("ETH", "BTC"), split_pair_id("ETH/BTC"))
+    }
 }
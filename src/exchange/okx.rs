@@ -0,0 +1,551 @@
+use super::{
+    Amount, Assets, Fill, LimitOrder, MarketOrder, OrderExecutor, OrderId, OrderRejected,
+    OrderState, OrderType, Pair,
+};
+use crate::{
+    messaging::message::{Fee, Liquidation, MessageId},
+    tools::{
+        credentials::Credentials,
+        networking::{Headers, HttpClient, Method, Request},
+        time::{TimeProvider, Timestamp},
+    },
+};
+use anyhow::{anyhow, bail, Result};
+use async_trait::async_trait;
+use chrono::{TimeZone, Utc};
+use hmac::{Hmac, Mac};
+use serde::{de::DeserializeOwned, Deserialize};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+pub const OKX_URL: &str = "https://www.okx.com";
+
+#[derive(Debug, Deserialize)]
+pub struct OkxResponse<T> {
+    pub code: String,
+    pub msg: String,
+    pub data: Vec<T>,
+}
+
+impl<T> OkxResponse<T> {
+    pub fn data(self) -> Result<Vec<T>> {
+        if self.code != "0" {
+            bail!("okx error {}: {}", self.code, self.msg)
+        }
+        Ok(self.data)
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct Balance {
+    details: Vec<BalanceDetail>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BalanceDetail {
+    ccy: String,
+    avail_bal: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderPlaced {
+    ord_id: String,
+    s_code: String,
+    s_msg: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct OrderDetails {
+    acc_fill_sz: String,
+    avg_px: String,
+    fee: String,
+    fee_ccy: String,
+    state: String,
+}
+
+pub struct OkxExchange<C, T> {
+    client: C,
+    credentials: Credentials,
+    time_provider: T,
+}
+
+impl<C, T> OkxExchange<C, T>
+where
+    C: HttpClient + Send + Sync,
+    T: TimeProvider + Clone + Send + Sync,
+{
+    pub fn new(client: C, credentials: Credentials, time_provider: T) -> Self {
+        OkxExchange {
+            client,
+            credentials,
+            time_provider,
+        }
+    }
+
+    async fn request<R: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: String,
+    ) -> Result<OkxResponse<R>> {
+        let timestamp = iso_timestamp(self.time_provider.clone().now())?;
+        let signature = sign(
+            self.credentials.secret.expose(),
+            &timestamp,
+            &method,
+            path,
+            &body,
+        );
+        let request = Request {
+            method,
+            url: format!("{}{}", OKX_URL, path),
+            body,
+            headers: Headers::from([
+                ("accept".to_string(), "application/json".to_string()),
+                ("content-type".to_string(), "application/json".to_string()),
+                (
+                    "OK-ACCESS-KEY".to_string(),
+                    self.credentials.api_key.expose().to_string(),
+                ),
+                ("OK-ACCESS-SIGN".to_string(), signature),
+                ("OK-ACCESS-TIMESTAMP".to_string(), timestamp),
+                (
+                    "OK-ACCESS-PASSPHRASE".to_string(),
+                    self.credentials.passphrase.expose().to_string(),
+                ),
+            ]),
+        };
+        let response = self.client.send(request).await?;
+        serde_json::from_str(&response.body).map_err(|err| {
+            anyhow!(
+                "unexpected okx response with status {}: {}",
+                response.status,
+                err
+            )
+        })
+    }
+}
+
+#[async_trait]
+impl<C, T> OrderExecutor for OkxExchange<C, T>
+where
+    C: HttpClient + Send + Sync,
+    T: TimeProvider + Clone + Send + Sync,
+{
+    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill> {
+        let inst_id = instrument_id(&order.base, &order.quote);
+        let (side, target_currency) = match order.order_type {
+            OrderType::Buy => ("buy", "quote_ccy"),
+            OrderType::Sell => ("sell", "base_ccy"),
+        };
+        let order_id = Uuid::new_v4();
+        let body = json!({
+            "instId": inst_id,
+            "tdMode": "cash",
+            "clOrdId": order_id.to_simple().to_string(),
+            "side": side,
+            "ordType": "market",
+            "sz": order.amount.to_string(),
+            "tgtCcy": target_currency,
+        })
+        .to_string();
+        let response: OkxResponse<OrderPlaced> = self
+            .request(Method::POST, "/api/v5/trade/order", body)
+            .await?;
+        if let Some(placed) = response.data.iter().find(|placed| placed.s_code != "0") {
+            return Err(OrderRejected::new(placed.s_msg.clone()).into());
+        }
+        let placed = response
+            .data()?
+            .pop()
+            .ok_or_else(|| anyhow!("okx returned no placed order"))?;
+
+        let path = format!(
+            "/api/v5/trade/order?instId={}&ordId={}",
+            inst_id, placed.ord_id
+        );
+        let details: OrderDetails = self
+            .request(Method::GET, &path, String::new())
+            .await?
+            .data()?
+            .pop()
+            .ok_or_else(|| anyhow!("okx returned no order {}", placed.ord_id))?;
+        fill(
+            Fill {
+                order_id,
+                base: order.base.clone(),
+                quote: order.quote.clone(),
+                order_type: order.order_type,
+                amount: 0.0,
+                price: 0.0,
+                fee: Fee::default(),
+                state: OrderState::Placed,
+            },
+            details,
+        )
+    }
+
+    async fn place_limit_order(&mut self, _: &LimitOrder) -> Result<OrderId> {
+        Err(anyhow!("limit orders are not supported on okx yet"))
+    }
+
+    async fn cancel_order(&mut self, _: OrderId) -> Result<()> {
+        Err(anyhow!("cancelling orders is not supported on okx yet"))
+    }
+
+    async fn open_orders(&self) -> Result<Vec<Pair>> {
+        Ok(vec![])
+    }
+
+    async fn poll_fills(&mut self, _: MessageId, _: Timestamp) -> Result<Vec<Fill>> {
+        Ok(vec![])
+    }
+
+    async fn poll_liquidations(&mut self, _: MessageId, _: Timestamp) -> Result<Vec<Liquidation>> {
+        Ok(vec![])
+    }
+
+    async fn poll_order(&mut self, last: &Fill) -> Result<Fill> {
+        let path = format!(
+            "/api/v5/trade/order?instId={}&clOrdId={}",
+            instrument_id(&last.base, &last.quote),
+            last.order_id.to_simple()
+        );
+        let details: OrderDetails = self
+            .request(Method::GET, &path, String::new())
+            .await?
+            .data()?
+            .pop()
+            .ok_or_else(|| anyhow!("okx returned no order {}", last.order_id))?;
+        fill(last.clone(), details)
+    }
+
+    async fn fetch_assets(&self) -> Result<Assets> {
+        let balances: Vec<Balance> = self
+            .request(Method::GET, "/api/v5/account/balance", String::new())
+            .await?
+            .data()?;
+        let mut assets = Assets {
+            ..Default::default()
+        };
+        for detail in balances.iter().flat_map(|balance| &balance.details) {
+            assets.credit(&detail.ccy, decimal(&detail.avail_bal)?);
+        }
+        Ok(assets)
+    }
+}
+
+// Updates the fill with the order details, amounts are reported as received
+// after fees.
+fn fill(last: Fill, details: OrderDetails) -> Result<Fill> {
+    let filled = decimal(&details.acc_fill_sz)?;
+    let price = decimal(&details.avg_px)?;
+    // okx reports charged fees as negative amounts
+    let fee = -decimal(&details.fee)?;
+    let (received, currency) = match last.order_type {
+        OrderType::Buy => (filled, &last.base),
+        OrderType::Sell => (filled * price, &last.quote),
+    };
+    let amount = if details.fee_ccy == *currency {
+        received - fee
+    } else {
+        received
+    };
+    let state = match details.state.as_str() {
+        "live" => OrderState::Placed,
+        "partially_filled" => OrderState::PartiallyFilled,
+        "filled" => OrderState::Filled,
+        "canceled" | "mmp_canceled" => OrderState::Cancelled,
+        other => bail!("unknown okx order state {:?}", other),
+    };
+    Ok(Fill {
+        amount,
+        price,
+        fee: Fee {
+            currency: details.fee_ccy,
+            amount: fee,
+        },
+        state,
+        ..last
+    })
+}
+
+pub fn instrument_id(base: &str, quote: &str) -> String {
+    format!("{}-{}", base, quote)
+}
+
+// okx expects the time in milliseconds as ISO 8601, e.g. 2020-12-08T09:08:57.715Z
+fn iso_timestamp(micros: Timestamp) -> Result<String> {
+    let time = i64::try_from(micros / 1000)
+        .ok()
+        .and_then(|millis| Utc.timestamp_millis_opt(millis).single())
+        .ok_or_else(|| anyhow!("time {} is out of range", micros))?;
+    Ok(time.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+}
+
+fn sign(secret: &str, timestamp: &str, method: &Method, path: &str, body: &str) -> String {
+    let method = match method {
+        Method::GET => "GET",
+        Method::POST => "POST",
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("hmac accepts keys of any size");
+    mac.update(format!("{}{}{}{}", timestamp, method, path, body).as_bytes());
+    base64::encode(mac.finalize().into_bytes())
+}
+
+// okx sends numbers as strings and leaves unset ones empty
+fn decimal(value: &str) -> Result<Amount> {
+    if value.is_empty() {
+        return Ok(0.0);
+    }
+    value
+        .parse()
+        .map_err(|err| anyhow!("invalid okx number {:?}: {}", value, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::{
+        cassette::Replayer,
+        credentials::Secret,
+        networking::{tests::MockClient, Response, Url},
+        time::tests::MockTimeProvider,
+    };
+    use pretty_assertions::assert_eq;
+
+    const ORDER_DETAILS_PATH: &str = "/api/v5/trade/order?instId=BTC-USDT&ordId=312269865356374016";
+
+    fn respond(method: Method, path: &str, body: &str) -> ((Method, Url), Response) {
+        (
+            (method, format!("{}{}", OKX_URL, path)),
+            Response {
+                status: 200,
+                body: body.to_string(),
+            },
+        )
+    }
+
+    fn new_exchange(
+        responses: Vec<((Method, Url), Response)>,
+    ) -> OkxExchange<MockClient, MockTimeProvider> {
+        OkxExchange::new(
+            MockClient::new(responses.into_iter().collect()),
+            Credentials {
+                api_key: Secret::new("key"),
+                secret: Secret::new("secret"),
+                passphrase: Secret::new("passphrase"),
+            },
+            MockTimeProvider::new(),
+        )
+    }
+
+    fn btc_order(order_type: OrderType, amount: f64) -> MarketOrder {
+        MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type,
+            amount,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn sign_should_sign_request_without_body() {
+        let actual = sign(
+            "secret",
+            "2020-12-08T09:08:57.715Z",
+            &Method::GET,
+            "/api/v5/account/balance",
+            "",
+        );
+        assert_eq!("5ktoTKif8DCJlIPb/3Kfd1A17bIRye6jpS9QBWj+9AU=", actual)
+    }
+
+    #[test]
+    fn sign_should_include_body() {
+        let actual = sign(
+            "secret",
+            "2020-12-08T09:08:57.715Z",
+            &Method::POST,
+            "/api/v5/trade/order",
+            "{\"instId\":\"BTC-USDT\"}",
+        );
+        assert_eq!("I64FCA5BpQbPVAu81/jgcqwMMz/badKJDsMDOMpXB0Y=", actual)
+    }
+
+    #[test]
+    fn iso_timestamp_should_format_micros_as_milliseconds() {
+        assert_eq!(
+            "2020-12-08T09:08:57.715Z",
+            iso_timestamp(1607418537715123).unwrap()
+        )
+    }
+
+    #[test]
+    fn iso_timestamp_should_fail_for_time_out_of_range() {
+        assert!(iso_timestamp(Timestamp::MAX).is_err())
+    }
+
+    #[async_std::test]
+    async fn fetch_assets_should_return_available_balances() {
+        let exchange = new_exchange(vec![respond(
+            Method::GET,
+            "/api/v5/account/balance",
+            include_str!("../../fixtures/okx/balance.json"),
+        )]);
+        let actual = exchange.fetch_assets().await.unwrap();
+        assert_eq!(0.25, actual.amount("BTC"));
+        assert_eq!(1500.5, actual.amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn fetch_assets_should_fail_on_okx_error() {
+        let exchange = new_exchange(vec![respond(
+            Method::GET,
+            "/api/v5/account/balance",
+            include_str!("../../fixtures/okx/unauthorized.json"),
+        )]);
+        let actual = exchange.fetch_assets().await.unwrap_err();
+        assert_eq!("okx error 50113: Invalid Sign", actual.to_string())
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_return_bought_amount_after_fee() {
+        let mut exchange = new_exchange(vec![
+            respond(
+                Method::POST,
+                "/api/v5/trade/order",
+                include_str!("../../fixtures/okx/order_placed.json"),
+            ),
+            respond(
+                Method::GET,
+                ORDER_DETAILS_PATH,
+                include_str!("../../fixtures/okx/buy_filled.json"),
+            ),
+        ]);
+        let actual = exchange
+            .place_market_order(&btc_order(OrderType::Buy, 100.0))
+            .await
+            .unwrap();
+        assert_eq!(0.002 - 0.000002, actual.amount);
+        assert_eq!(OrderState::Filled, actual.state);
+        assert_eq!(
+            Fee {
+                currency: "BTC".into(),
+                amount: 0.000002,
+            },
+            actual.fee
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_return_sold_amount_in_quote_after_fee() {
+        let mut exchange = new_exchange(vec![
+            respond(
+                Method::POST,
+                "/api/v5/trade/order",
+                include_str!("../../fixtures/okx/order_placed.json"),
+            ),
+            respond(
+                Method::GET,
+                ORDER_DETAILS_PATH,
+                include_str!("../../fixtures/okx/sell_filled.json"),
+            ),
+        ]);
+        let actual = exchange
+            .place_market_order(&btc_order(OrderType::Sell, 0.002))
+            .await
+            .unwrap();
+        assert_eq!(99.9, actual.amount);
+        assert_eq!(OrderType::Sell, actual.order_type)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_with_okx_reason() {
+        let mut exchange = new_exchange(vec![respond(
+            Method::POST,
+            "/api/v5/trade/order",
+            include_str!("../../fixtures/okx/order_rejected.json"),
+        )]);
+        let actual = exchange
+            .place_market_order(&btc_order(OrderType::Buy, 100.0))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&OrderRejected::new(
+                "Order failed. Insufficient USDT balance in account."
+            )),
+            actual.downcast_ref::<OrderRejected>()
+        )
+    }
+
+    #[async_std::test]
+    async fn poll_order_should_report_partial_fill_by_client_order_id() {
+        let mut exchange = new_exchange(vec![respond(
+            Method::GET,
+            "/api/v5/trade/order?instId=BTC-USDT&clOrdId=00000000000000000000000000000001",
+            include_str!("../../fixtures/okx/buy_partially_filled.json"),
+        )]);
+        let last = Fill {
+            order_id: Uuid::from_u128(1),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount: 0.0,
+            price: 0.0,
+            fee: Fee::default(),
+            state: OrderState::Placed,
+        };
+        let actual = exchange.poll_order(&last).await.unwrap();
+        assert_eq!(
+            Fill {
+                amount: 0.001 - 0.000001,
+                price: 50000.0,
+                fee: Fee {
+                    currency: "BTC".into(),
+                    amount: 0.000001,
+                },
+                state: OrderState::PartiallyFilled,
+                ..last
+            },
+            actual
+        )
+    }
+
+    #[async_std::test]
+    async fn should_follow_order_and_balance_of_recorded_session() {
+        let client = Replayer::from_file("fixtures/cassettes/okx_order_lifecycle.json")
+            .unwrap()
+            .with_headers(&["accept", "content-type"]);
+        let mut exchange = OkxExchange::new(
+            client,
+            Credentials {
+                ..Default::default()
+            },
+            MockTimeProvider::new(),
+        );
+        let placed = Fill {
+            order_id: Uuid::from_u128(1),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount: 0.0,
+            price: 0.0,
+            fee: Fee::default(),
+            state: OrderState::Placed,
+        };
+        let live = exchange.poll_order(&placed).await.unwrap();
+        let filled = exchange.poll_order(&live).await.unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(OrderState::Placed, live.state);
+        assert_eq!(OrderState::Filled, filled.state);
+        assert_eq!(0.002 - 0.000002, filled.amount);
+        assert_eq!(1500.5, assets.amount("USDT"))
+    }
+}
@@ -0,0 +1,342 @@
+use super::{
+    simulation::ExchangeSimulation, stream::Events, Amount, Assets, ExchangeOptions, Fill,
+    LimitOrder, MarketDataSource, MarketOrder, OrderExecutor, OrderId, OrderType, Pair,
+};
+use crate::{
+    messaging::message::{Liquidation, MessageId, Msg, Timestamp},
+    tools::time::TimeProvider,
+};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use crossbeam::channel;
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, fs};
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct PaperTrade {
+    pub time: Timestamp,
+    pub order_id: String,
+    pub base: String,
+    pub quote: String,
+    pub side: String,
+    pub amount: Amount,
+    pub fee_currency: String,
+    pub fee: Amount,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+struct PaperState {
+    balances: HashMap<String, Amount>,
+    loans: HashMap<String, Amount>,
+    trades: Vec<PaperTrade>,
+}
+
+// Trades against live prices with the fees and fill models of the simulation.
+// Balances and trades are saved to the state file after every change and read
+// back on start. Open limit orders are not kept across restarts, the funds they
+// reserve are.
+pub struct PaperExchange<T: TimeProvider> {
+    simulation: ExchangeSimulation,
+    state_path: String,
+    trades: Vec<PaperTrade>,
+    time_provider: T,
+}
+
+impl<T: TimeProvider + Send + Sync> PaperExchange<T> {
+    pub fn new(
+        feed: channel::Receiver<Msg>,
+        state_path: impl Into<String>,
+        assets: Assets,
+        options: ExchangeOptions,
+        time_provider: T,
+    ) -> Result<Self> {
+        let state_path = state_path.into();
+        let (assets, trades) = match fs::read_to_string(&state_path) {
+            Ok(content) => {
+                let state: PaperState = serde_json::from_str(&content)
+                    .map_err(|err| anyhow!("{}: {}", state_path, err))?;
+                let assets = Assets {
+                    balances: state.balances,
+                    loans: state.loans,
+                };
+                (assets, state.trades)
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => (assets, vec![]),
+            Err(err) => return Err(anyhow!("{}: {}", state_path, err)),
+        };
+        Ok(PaperExchange {
+            simulation: ExchangeSimulation::new_from_events(
+                Box::new(feed.into_iter()),
+                assets,
+                options,
+            ),
+            state_path,
+            trades,
+            time_provider,
+        })
+    }
+
+    pub fn trades(&self) -> &[PaperTrade] {
+        &self.trades
+    }
+
+    fn record(&mut self, fill: &Fill) {
+        self.trades.push(PaperTrade {
+            time: self.time_provider.now(),
+            order_id: fill.order_id.to_string(),
+            base: fill.base.clone(),
+            quote: fill.quote.clone(),
+            side: match fill.order_type {
+                OrderType::Buy => "buy".into(),
+                OrderType::Sell => "sell".into(),
+            },
+            amount: fill.amount,
+            fee_currency: fill.fee.currency.clone(),
+            fee: fill.fee.amount,
+        });
+    }
+
+    async fn save(&self) -> Result<()> {
+        let mut assets = self.simulation.fetch_assets().await?;
+        for book in self.simulation.open_orders().await? {
+            for order in &book.bid_orders {
+                assets.credit(&book.quote, order.amount);
+            }
+            for order in &book.ask_orders {
+                assets.credit(&book.base, order.amount);
+            }
+        }
+        let state = PaperState {
+            balances: assets.balances,
+            loans: assets.loans,
+            trades: self.trades.clone(),
+        };
+        // write aside and rename, so a crash never leaves half a state file
+        let temp_path = format!("{}.tmp", self.state_path);
+        fs::write(&temp_path, serde_json::to_string_pretty(&state)?)?;
+        fs::rename(&temp_path, &self.state_path)?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl<T: TimeProvider + Send + Sync> MarketDataSource for PaperExchange<T> {
+    // The live events can be streamed once.
+    async fn event_stream(&self) -> Events {
+        self.simulation.event_stream().await
+    }
+}
+
+#[async_trait]
+impl<T: TimeProvider + Send + Sync> OrderExecutor for PaperExchange<T> {
+    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill> {
+        let fill = self.simulation.place_market_order(order).await?;
+        self.record(&fill);
+        self.save().await?;
+        Ok(fill)
+    }
+
+    async fn place_limit_order(&mut self, order: &LimitOrder) -> Result<OrderId> {
+        let order_id = self.simulation.place_limit_order(order).await?;
+        self.save().await?;
+        Ok(order_id)
+    }
+
+    async fn cancel_order(&mut self, order_id: OrderId) -> Result<()> {
+        self.simulation.cancel_order(order_id).await?;
+        self.save().await
+    }
+
+    async fn open_orders(&self) -> Result<Vec<Pair>> {
+        self.simulation.open_orders().await
+    }
+
+    async fn poll_fills(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Fill>> {
+        let fills = self
+            .simulation
+            .poll_fills(correlation_id, correlation_time)
+            .await?;
+        if !fills.is_empty() {
+            for fill in &fills {
+                self.record(fill);
+            }
+            self.save().await?;
+        }
+        Ok(fills)
+    }
+
+    async fn poll_liquidations(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Liquidation>> {
+        let liquidations = self
+            .simulation
+            .poll_liquidations(correlation_id, correlation_time)
+            .await?;
+        if !liquidations.is_empty() {
+            self.save().await?;
+        }
+        Ok(liquidations)
+    }
+
+    async fn poll_order(&mut self, last: &Fill) -> Result<Fill> {
+        self.simulation.poll_order(last).await
+    }
+
+    async fn fetch_assets(&self) -> Result<Assets> {
+        self.simulation.fetch_assets().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        exchange::{fees::FeeSchedule, Asset},
+        messaging::message::{MsgData, MsgMetaData, PriceUpdated},
+        tools::time::tests::MockTimeProvider,
+    };
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    fn state_file(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::remove_file(&path).ok();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn price_msg(id: u128, price: f64) -> Msg {
+        Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id: "BTC/USDT",
+                price,
+                ..Default::default()
+            }),
+            metadata: MsgMetaData {
+                id: Uuid::from_u128(id),
+                correlation_id: Uuid::from_u128(id),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn new_paper(state_path: &str) -> (PaperExchange<MockTimeProvider>, channel::Sender<Msg>) {
+        let (sender, receiver) = channel::unbounded();
+        let paper = PaperExchange::new(
+            receiver,
+            state_path,
+            Assets::from(vec![Asset {
+                name: "USDT".into(),
+                amount: 100.0,
+            }]),
+            ExchangeOptions {
+                fees: FeeSchedule::flat(0.01),
+                ..Default::default()
+            },
+            MockTimeProvider::new(),
+        )
+        .unwrap();
+        (paper, sender)
+    }
+
+    fn buy_btc(correlation_id: u128, amount: f64) -> MarketOrder {
+        MarketOrder {
+            correlation_id: Uuid::from_u128(correlation_id),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount,
+            correlation_time: 0,
+        }
+    }
+
+    #[async_std::test]
+    async fn event_stream_should_forward_live_events_once() {
+        let (paper, sender) = new_paper(&state_file("paper_events.json"));
+        sender.send(price_msg(1, 10.0)).unwrap();
+        drop(sender);
+        let actual: Vec<Msg> = paper.event_stream().await.collect();
+        assert_eq!(vec![price_msg(1, 10.0)], actual);
+        assert_eq!(0, paper.event_stream().await.count())
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_live_price_with_fees() {
+        let (mut paper, sender) = new_paper(&state_file("paper_fill.json"));
+        let mut events = paper.event_stream().await;
+        sender.send(price_msg(1, 10.0)).unwrap();
+        events.next().unwrap();
+        let fill = paper.place_market_order(&buy_btc(1, 50.5)).await.unwrap();
+        assert_eq!(5.0, fill.amount);
+        let assets = paper.fetch_assets().await.unwrap();
+        assert_eq!(49.5, assets.amount("USDT"));
+        assert_eq!(5.0, assets.amount("BTC"));
+        assert_eq!("buy", paper.trades()[0].side)
+    }
+
+    #[async_std::test]
+    async fn new_should_continue_saved_session() {
+        let path = state_file("paper_restart.json");
+        let (mut paper, sender) = new_paper(&path);
+        let mut events = paper.event_stream().await;
+        sender.send(price_msg(1, 10.0)).unwrap();
+        events.next().unwrap();
+        paper.place_market_order(&buy_btc(1, 50.5)).await.unwrap();
+        let expected_trades = paper.trades().to_vec();
+
+        let (restarted, _sender) = new_paper(&path);
+        let assets = restarted.fetch_assets().await.unwrap();
+        assert_eq!(49.5, assets.amount("USDT"));
+        assert_eq!(5.0, assets.amount("BTC"));
+        assert_eq!(expected_trades, restarted.trades())
+    }
+
+    #[async_std::test]
+    async fn new_should_return_funds_of_open_orders_after_restart() {
+        let path = state_file("paper_limit.json");
+        let (mut paper, _sender) = new_paper(&path);
+        paper
+            .place_limit_order(&LimitOrder {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                order_type: OrderType::Buy,
+                price: 5.0,
+                amount: 40.0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(60.0, paper.fetch_assets().await.unwrap().amount("USDT"));
+
+        let (restarted, _sender) = new_paper(&path);
+        assert_eq!(
+            100.0,
+            restarted.fetch_assets().await.unwrap().amount("USDT")
+        );
+        assert_eq!(0, restarted.open_orders().await.unwrap().len())
+    }
+
+    #[async_std::test]
+    async fn new_should_fail_on_corrupt_state() {
+        let path = state_file("paper_corrupt.json");
+        fs::write(&path, "{").unwrap();
+        let (_sender, receiver) = channel::unbounded();
+        let actual = PaperExchange::new(
+            receiver,
+            path.as_str(),
+            Assets {
+                ..Default::default()
+            },
+            ExchangeOptions {
+                ..Default::default()
+            },
+            MockTimeProvider::new(),
+        );
+        assert!(actual.is_err())
+    }
+}
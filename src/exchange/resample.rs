@@ -0,0 +1,393 @@
+use super::loader::{self, DataFile, Kline, LoadError};
+use crate::messaging::{
+    message::{Candle, Msg, MsgData, MsgKind, PairId, PriceUpdated, Timestamp},
+    processor::{Actor, Subscriptions},
+};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::{collections::HashMap, fs, io, path::Path};
+
+// Number of resampled candles written to the output file at once.
+const WRITE_CHUNK: usize = 1000;
+
+// Aggregates candles into buckets of `interval` milliseconds which start on
+// multiples of the interval. Buckets without any candle are left out.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct CandleAggregator {
+    interval: Timestamp,
+    current: Option<Kline>,
+}
+
+impl CandleAggregator {
+    pub fn new(interval: Timestamp) -> Self {
+        CandleAggregator {
+            interval,
+            current: None,
+        }
+    }
+
+    // Returns the previous candle once a kline of a later bucket arrives.
+    pub fn push(&mut self, kline: Kline) -> Option<Kline> {
+        let bucket = kline.time - kline.time % self.interval.max(1);
+        let candle = kline.candle.unwrap_or(Candle {
+            open: kline.close,
+            high: kline.close,
+            low: kline.close,
+            close: kline.close,
+            ..Default::default()
+        });
+        match &mut self.current {
+            Some(current) if current.time == bucket => {
+                let merged = current.candle.get_or_insert(candle);
+                merged.high = merged.high.max(candle.high);
+                merged.low = merged.low.min(candle.low);
+                merged.close = candle.close;
+                merged.volume_base += candle.volume_base;
+                merged.volume_quote += candle.volume_quote;
+                current.close = candle.close;
+                None
+            }
+            Some(current) if current.time > bucket => {
+                log::warn!(
+                    "skipping kline at {} before bucket {}",
+                    kline.time,
+                    current.time
+                );
+                None
+            }
+            _ => self.current.replace(Kline {
+                time: bucket,
+                close: candle.close,
+                candle: Some(candle),
+            }),
+        }
+    }
+
+    pub fn flush(&mut self) -> Option<Kline> {
+        self.current.take()
+    }
+}
+
+pub fn resample(
+    klines: impl Iterator<Item = Kline>,
+    interval: Timestamp,
+) -> impl Iterator<Item = Kline> {
+    let mut aggregator = CandleAggregator::new(interval);
+    let mut klines = klines.fuse();
+    std::iter::from_fn(move || {
+        for kline in klines.by_ref() {
+            if let Some(closed) = aggregator.push(kline) {
+                return Some(closed);
+            }
+        }
+        aggregator.flush()
+    })
+}
+
+// Replaces the output file with the input resampled to the interval. The last,
+// possibly incomplete, bucket of the input is written as well. The candles are
+// written to a file next to the output which is renamed once complete, so the
+// input may be resampled in place.
+pub fn resample_file(
+    input: &DataFile,
+    output: &DataFile,
+    interval: Timestamp,
+) -> Result<usize, LoadError> {
+    loader::validate(input)?;
+    let temp = DataFile {
+        path: temp_path(&output.path),
+        ..output.clone()
+    };
+    remove_file(&temp.path)?;
+    let written = match write_resampled(input, &temp, interval) {
+        Ok(written) => written,
+        Err(err) => {
+            fs::remove_file(&temp.path).ok();
+            return Err(err);
+        }
+    };
+    if !Path::new(&temp.path).exists() {
+        remove_file(&output.path)?;
+        return Ok(0);
+    }
+    fs::rename(&temp.path, &output.path).map_err(|err| LoadError::Io {
+        path: output.path.clone(),
+        reason: err.to_string(),
+    })?;
+    Ok(written)
+}
+
+// Keeps the file name's extension, which tells the format.
+fn temp_path(path: &str) -> String {
+    let path = Path::new(path);
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    path.with_file_name(format!(".resampling.{}", name))
+        .to_string_lossy()
+        .into_owned()
+}
+
+fn remove_file(path: &str) -> Result<(), LoadError> {
+    match fs::remove_file(path) {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(LoadError::Io {
+            path: path.into(),
+            reason: err.to_string(),
+        }),
+        _ => Ok(()),
+    }
+}
+
+fn write_resampled(
+    input: &DataFile,
+    output: &DataFile,
+    interval: Timestamp,
+) -> Result<usize, LoadError> {
+    let klines = loader::read_klines(input)?.map_while(Result::ok);
+    let mut written = 0;
+    let mut chunk = Vec::with_capacity(WRITE_CHUNK);
+    for kline in resample(klines, interval) {
+        chunk.push(kline);
+        if chunk.len() == WRITE_CHUNK {
+            loader::append_klines(output, &chunk, Some(interval))?;
+            written += chunk.len();
+            chunk.clear();
+        }
+    }
+    loader::append_klines(output, &chunk, Some(interval))?;
+    Ok(written + chunk.len())
+}
+
+// Turns price updates into a CandleClosed message per pair whenever a bucket of
+// the interval is complete.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Resampler {
+    interval: Timestamp,
+    aggregators: HashMap<PairId, CandleAggregator>,
+}
+
+impl Resampler {
+    pub fn new(interval: Timestamp) -> Self {
+        Resampler {
+            interval,
+            aggregators: HashMap::new(),
+        }
+    }
+}
+
+#[async_trait]
+impl Actor for Resampler {
+    async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
+        let res = match &msg.data {
+            MsgData::LivePriceUpdated(e) => {
+                let interval = self.interval;
+                self.aggregators
+                    .entry(e.pair_id)
+                    .or_insert_with(|| CandleAggregator::new(interval))
+                    .push(Kline {
+                        time: e.datetime,
+                        close: e.price,
+                        candle: e.candle,
+                    })
+                    .map(|closed| {
+                        MsgData::CandleClosed(PriceUpdated {
+                            pair_id: e.pair_id,
+                            datetime: closed.time,
+                            price: closed.close,
+                            candle: closed.candle,
+                        })
+                    })
+                    .into_iter()
+                    .collect()
+            }
+            _ => vec![],
+        };
+        Ok(res)
+    }
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions::Only(vec![MsgKind::LivePriceUpdated])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const MINUTE: Timestamp = 60 * 1000;
+
+    fn kline(time: Timestamp, open: f64, high: f64, low: f64, close: f64, volume: f64) -> Kline {
+        Kline {
+            time,
+            close,
+            candle: Some(Candle {
+                open,
+                high,
+                low,
+                close,
+                volume_base: volume,
+                volume_quote: volume * close,
+            }),
+        }
+    }
+
+    fn price_msg(pair_id: PairId, datetime: Timestamp, price: f64) -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id,
+            datetime,
+            price,
+            ..Default::default()
+        }))
+    }
+
+    fn write_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    #[test]
+    fn resample_should_aggregate_ohlcv_on_time_boundaries() {
+        let klines = vec![
+            kline(0, 10.0, 12.0, 9.0, 11.0, 1.0),
+            kline(MINUTE, 11.0, 15.0, 10.0, 14.0, 2.0),
+            kline(2 * MINUTE, 14.0, 14.0, 8.0, 9.0, 3.0),
+            kline(3 * MINUTE, 9.0, 10.0, 9.0, 10.0, 4.0),
+        ];
+        let actual: Vec<Kline> = resample(klines.into_iter(), 3 * MINUTE).collect();
+        let expected = vec![
+            Kline {
+                time: 0,
+                close: 9.0,
+                candle: Some(Candle {
+                    open: 10.0,
+                    high: 15.0,
+                    low: 8.0,
+                    close: 9.0,
+                    volume_base: 6.0,
+                    volume_quote: 11.0 + 28.0 + 27.0,
+                }),
+            },
+            kline(3 * MINUTE, 9.0, 10.0, 9.0, 10.0, 4.0),
+        ];
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn resample_should_leave_out_gaps() {
+        let klines = vec![
+            kline(MINUTE, 10.0, 10.0, 10.0, 10.0, 1.0),
+            kline(10 * MINUTE, 11.0, 11.0, 11.0, 11.0, 1.0),
+        ];
+        let actual: Vec<Timestamp> = resample(klines.into_iter(), 5 * MINUTE)
+            .map(|kline| kline.time)
+            .collect();
+        assert_eq!(vec![0, 10 * MINUTE], actual)
+    }
+
+    #[test]
+    fn resample_should_build_candles_from_close_prices() {
+        let klines = vec![
+            Kline {
+                time: 0,
+                close: 2.0,
+                candle: None,
+            },
+            Kline {
+                time: MINUTE,
+                close: 1.0,
+                candle: None,
+            },
+        ];
+        let actual: Vec<Kline> = resample(klines.into_iter(), 5 * MINUTE).collect();
+        assert_eq!(vec![kline(0, 2.0, 2.0, 1.0, 1.0, 0.0)], actual)
+    }
+
+    #[test]
+    fn resample_file_should_write_resampled_candles() {
+        let input = DataFile::new(
+            "BTC/USDT",
+            write_file(
+                "resample_input.jsonl",
+                "{\"time\": 0, \"open\": 1, \"high\": 3, \"low\": 1, \"close\": 2, \"volume_base\": 1}\n\
+                 {\"time\": 60000, \"open\": 2, \"high\": 4, \"low\": 0.5, \"close\": 3, \"volume_base\": 2}\n\
+                 {\"time\": 120000, \"open\": 3, \"high\": 3, \"low\": 3, \"close\": 3, \"volume_base\": 1}\n",
+            ),
+        );
+        let output = DataFile::new("BTC/USDT", write_file("resample_output.csv", "stale"));
+        let written = resample_file(&input, &output, 2 * MINUTE).unwrap();
+        let actual: Vec<Kline> = loader::read_klines(&output)
+            .unwrap()
+            .map(|kline| kline.unwrap())
+            .collect();
+        let expected: Vec<Kline> = vec![
+            kline(0, 1.0, 4.0, 0.5, 3.0, 3.0),
+            kline(2 * MINUTE, 3.0, 3.0, 3.0, 3.0, 1.0),
+        ]
+        .into_iter()
+        .map(|mut kline| {
+            // the input has no quote volume
+            kline.candle.as_mut().unwrap().volume_quote = 0.0;
+            kline
+        })
+        .collect();
+        assert_eq!(2, written);
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn resample_file_should_resample_file_in_place() {
+        let file = DataFile::new(
+            "BTC/USDT",
+            write_file(
+                "resample_in_place.jsonl",
+                "{\"time\": 0, \"close\": 1}\n{\"time\": 60000, \"close\": 2}\n",
+            ),
+        );
+        let written = resample_file(&file, &file, 2 * MINUTE).unwrap();
+        let actual: Vec<Timestamp> = loader::read_klines(&file)
+            .unwrap()
+            .map(|kline| kline.unwrap().time)
+            .collect();
+        assert_eq!(1, written);
+        assert_eq!(vec![0], actual);
+        assert!(!Path::new(&temp_path(&file.path)).exists())
+    }
+
+    #[async_std::test]
+    async fn resampler_should_emit_closed_candles_per_pair() {
+        let mut actor = Resampler::new(2 * MINUTE);
+        let mut actual = vec![];
+        for msg in [
+            price_msg("BTC/USDT", 0, 10.0),
+            price_msg("ETH/USDT", 0, 1.0),
+            price_msg("BTC/USDT", MINUTE, 12.0),
+            price_msg("BTC/USDT", 2 * MINUTE, 11.0),
+            price_msg("ETH/USDT", MINUTE, 2.0),
+        ] {
+            actual.extend(actor.act(&msg).await.unwrap());
+        }
+        let expected = vec![MsgData::CandleClosed(PriceUpdated {
+            pair_id: "BTC/USDT",
+            datetime: 0,
+            price: 12.0,
+            candle: Some(Candle {
+                open: 10.0,
+                high: 12.0,
+                low: 10.0,
+                close: 12.0,
+                ..Default::default()
+            }),
+        })];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn resampler_should_ignore_other_messages() {
+        let mut actor = Resampler::new(MINUTE);
+        let actual = actor.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        assert_eq!(0, actual.len())
+    }
+}
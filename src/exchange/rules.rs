@@ -0,0 +1,137 @@
+use super::{Amount, OrderRejected};
+use crate::messaging::message::Price;
+
+const EPSILON: f64 = 1e-9;
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct TradingRules {
+    pub min_notional: Option<Amount>,
+    pub lot_size: Option<Amount>,
+    pub tick_size: Option<Price>,
+    pub max_order_size: Option<Amount>,
+}
+
+impl TradingRules {
+    pub fn quantity(&self, quantity: Amount, price: Price) -> Result<Amount, OrderRejected> {
+        let quantity = match self.lot_size {
+            Some(step) if step > 0.0 => (quantity / step + EPSILON).floor() * step,
+            _ => quantity,
+        };
+        if self.lot_size.is_some() && quantity <= 0.0 {
+            return Err(OrderRejected::new("quantity below lot size"));
+        }
+        if let Some(max) = self.max_order_size {
+            if quantity > max {
+                return Err(OrderRejected::new(format!(
+                    "quantity {} above max order size {}",
+                    quantity, max
+                )));
+            }
+        }
+        if let Some(min) = self.min_notional {
+            if quantity * price < min {
+                return Err(OrderRejected::new(format!(
+                    "notional {} below minimum {}",
+                    quantity * price,
+                    min
+                )));
+            }
+        }
+        Ok(quantity)
+    }
+
+    pub fn price(&self, price: Price) -> Result<Price, OrderRejected> {
+        match self.tick_size {
+            Some(tick) if tick > 0.0 => {
+                let ticks = (price / tick).round();
+                if (ticks * tick - price).abs() > EPSILON * price.abs().max(1.0) {
+                    return Err(OrderRejected::new(format!(
+                        "price {} not a multiple of tick size {}",
+                        price, tick
+                    )));
+                }
+                Ok(price)
+            }
+            _ => Ok(price),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn quantity_should_pass_without_rules() {
+        let rules = TradingRules {
+            ..Default::default()
+        };
+        assert_eq!(Ok(0.123456), rules.quantity(0.123456, 10.0))
+    }
+
+    #[test]
+    fn quantity_should_round_down_to_lot_size() {
+        let rules = TradingRules {
+            lot_size: Some(0.01),
+            ..Default::default()
+        };
+        let actual = rules.quantity(0.1299, 10.0).unwrap();
+        assert!((actual - 0.12).abs() < EPSILON)
+    }
+
+    #[test]
+    fn quantity_should_keep_exact_multiple_of_lot_size() {
+        let rules = TradingRules {
+            lot_size: Some(0.1),
+            ..Default::default()
+        };
+        let actual = rules.quantity(0.3, 10.0).unwrap();
+        assert!((actual - 0.3).abs() < EPSILON)
+    }
+
+    #[test]
+    fn quantity_should_reject_dust_below_lot_size() {
+        let rules = TradingRules {
+            lot_size: Some(0.01),
+            ..Default::default()
+        };
+        assert!(rules.quantity(0.009, 10.0).is_err())
+    }
+
+    #[test]
+    fn quantity_should_reject_below_min_notional() {
+        let rules = TradingRules {
+            min_notional: Some(10.0),
+            ..Default::default()
+        };
+        assert!(rules.quantity(0.9, 10.0).is_err())
+    }
+
+    #[test]
+    fn quantity_should_reject_above_max_order_size() {
+        let rules = TradingRules {
+            max_order_size: Some(5.0),
+            ..Default::default()
+        };
+        assert!(rules.quantity(5.5, 10.0).is_err())
+    }
+
+    #[test]
+    fn price_should_accept_multiple_of_tick_size() {
+        let rules = TradingRules {
+            tick_size: Some(0.1),
+            ..Default::default()
+        };
+        assert_eq!(Ok(47605.1), rules.price(47605.1))
+    }
+
+    #[test]
+    fn price_should_reject_off_tick_price() {
+        let rules = TradingRules {
+            tick_size: Some(0.1),
+            ..Default::default()
+        };
+        assert!(rules.price(47605.14).is_err())
+    }
+}
@@ -1,126 +1,580 @@
-use super::{Amount, Asset, Assets, Exchange, ExchangeOptions, MarketOrder, OrderType};
-use crate::{
-    messaging::message::{Msg, MsgData, MsgMetaData, PriceUpdated},
-    tools::time::{TimeProvider, TimeProviderImpl},
+use super::{
+    fees::{FeeCurrency, Liquidity, VolumeTracker},
+    fill::Quote,
+    latency::LatencyModel,
+    loader::{self, DataFile, LoadError},
+    margin::{Exposure, MarginOptions},
+    rules::TradingRules,
+    split_pair_id,
+    stream::{self, EventSource, Events, QuoteCursor},
+    Amount, Asset, Assets, ExchangeOptions, Fill, LimitOrder, MarketDataSource, MarketOrder, Order,
+    OrderExecutor, OrderId, OrderRejected, OrderState, OrderType, Pair,
 };
-use anyhow::Result;
+use crate::messaging::message::{Fee, Liquidation, MessageId, Msg, PairId, Price, Timestamp};
+use anyhow::{bail, Result};
 use async_trait::async_trait;
-use serde::Deserialize;
-use std::{collections::HashMap, fs};
+use std::{collections::HashMap, sync::Mutex};
 use uuid::Uuid;
 
-pub type Price = f64;
-
-#[derive(Default, Clone)]
 pub struct ExchangeSimulation {
-    event_stream: Vec<Msg>,
+    source: EventSource,
+    streamed: Mutex<Option<Events>>,
+    quotes: QuoteCursor,
     assets: Assets,
-    prices: HashMap<Uuid, Price>,
+    order_books: HashMap<String, Pair>,
+    volume: VolumeTracker,
+    latency: LatencyModel,
+    accrued_at: Option<Timestamp>,
     options: ExchangeOptions,
 }
 
-#[derive(Deserialize)]
-struct Candle {
-    time: u128,
-    close: f64,
-}
-
 impl ExchangeSimulation {
     pub fn new(event_stream: Vec<Msg>, assets: Assets, options: ExchangeOptions) -> Self {
-        let mut prices = HashMap::new();
-        for event in &event_stream {
-            if let MsgData::LivePriceUpdated(price_updated) = &event.data {
-                prices.insert(event.metadata.correlation_id, price_updated.price);
-            }
+        ExchangeSimulation::from_source(EventSource::Events(event_stream), assets, options)
+    }
+
+    pub fn new_from_file(
+        file: &str,
+        starting_quote: Asset,
+        options: ExchangeOptions,
+    ) -> Result<ExchangeSimulation, LoadError> {
+        ExchangeSimulation::new_from_files(
+            &[("BTC/USDT", file)],
+            Assets::from(vec![starting_quote]),
+            options,
+        )
+    }
+
+    pub fn new_from_files(
+        files: &[(PairId, &str)],
+        assets: Assets,
+        options: ExchangeOptions,
+    ) -> Result<ExchangeSimulation, LoadError> {
+        let files = files
+            .iter()
+            .map(|(pair_id, path)| DataFile::new(pair_id, *path))
+            .collect();
+        ExchangeSimulation::new_from_data_files(files, assets, options)
+    }
+
+    pub fn new_from_data_files(
+        files: Vec<DataFile>,
+        assets: Assets,
+        options: ExchangeOptions,
+    ) -> Result<ExchangeSimulation, LoadError> {
+        for file in &files {
+            loader::validate(file)?;
         }
+        Ok(ExchangeSimulation::from_source(
+            EventSource::Files(files),
+            assets,
+            options,
+        ))
+    }
+
+    // Fills orders at the prices of any stream, e.g. of a MarketDataSource. The
+    // events can be streamed from the simulation once.
+    pub fn new_from_events(events: Events, assets: Assets, options: ExchangeOptions) -> Self {
+        let (events, quotes) = stream::tee(events);
+        let mut simulation =
+            ExchangeSimulation::from_parts(EventSource::Events(vec![]), quotes, assets, options);
+        simulation.streamed = Mutex::new(Some(events));
+        simulation
+    }
+
+    fn from_source(source: EventSource, assets: Assets, options: ExchangeOptions) -> Self {
+        let quotes = source.quotes();
+        ExchangeSimulation::from_parts(source, quotes, assets, options)
+    }
+
+    fn from_parts(
+        source: EventSource,
+        quotes: QuoteCursor,
+        assets: Assets,
+        options: ExchangeOptions,
+    ) -> Self {
         ExchangeSimulation {
-            event_stream,
+            quotes,
+            source,
+            streamed: Mutex::new(None),
             assets,
-            prices,
+            order_books: HashMap::new(),
+            volume: VolumeTracker::default(),
+            latency: LatencyModel::new(options.latency),
+            accrued_at: None,
             options,
         }
     }
 
-    pub fn new_from_file(
-        file: &str,
-        starting_quote: Asset,
-        options: ExchangeOptions,
-    ) -> ExchangeSimulation {
-        let mut time = TimeProviderImpl {};
-        let file = fs::File::open(file).expect("file should open read only");
-        let candles: Vec<Candle> =
-            serde_json::from_reader(file).expect("file should be proper JSON");
-
-        let mut event_stream = vec![];
-        for candle in candles {
-            let message_id = Uuid::new_v4();
-            event_stream.push(Msg {
-                data: MsgData::LivePriceUpdated(PriceUpdated {
-                    datetime: candle.time,
-                    pair_id: "BTC/USDT",
-                    price: candle.close,
-                }),
-                metadata: MsgMetaData {
-                    id: message_id,
-                    correlation_id: message_id,
-                    causation_id: message_id,
-                    creation_time: time.now(),
-                    correlation_time: candle.time,
-                    correlation_price: candle.close,
-                },
-            });
+    fn delayed_quote(&mut self, signal: Quote) -> Result<Quote, OrderRejected> {
+        let delay = self.latency.delay();
+        if delay == 0 {
+            return Ok(signal);
         }
-        event_stream.push(Msg {
-            data: MsgData::Shutdown,
-            metadata: MsgMetaData {
-                ..Default::default()
+        let arrival = signal.time + delay;
+        self.quotes
+            .first_after(signal.pair_id, arrival)
+            .ok_or_else(|| {
+                OrderRejected::new(format!("no price for {} after {}", signal.pair_id, arrival))
+            })
+    }
+
+    fn fund(
+        &mut self,
+        currency: &str,
+        needed: Amount,
+        base: &str,
+        quote: &str,
+        price: Price,
+    ) -> Result<(), OrderRejected> {
+        let margin = match self.options.margin {
+            Some(margin) => margin,
+            None if needed > self.assets.amount(currency) => {
+                return Err(OrderRejected::new(format!("insufficient {}", currency)))
+            }
+            None => return Ok(()),
+        };
+        let shortfall = needed - self.assets.amount(currency).max(0.0);
+        if shortfall <= 0.0 {
+            return Ok(());
+        }
+        let value = if currency == base {
+            shortfall * price
+        } else {
+            shortfall
+        };
+        if !margin.can_borrow(Exposure::of(&self.assets, base, quote, price), value) {
+            return Err(OrderRejected::new(format!(
+                "insufficient margin to borrow {} {}",
+                shortfall, currency
+            )));
+        }
+        self.assets.borrow(currency, shortfall);
+        Ok(())
+    }
+
+    fn accrue_interest(&mut self, margin: MarginOptions, time: Timestamp) {
+        let elapsed = self
+            .accrued_at
+            .map_or(0, |accrued_at| time.saturating_sub(accrued_at));
+        self.accrued_at = Some(time);
+        for loan in self.assets.loans.values_mut() {
+            *loan += margin.interest(*loan, elapsed);
+        }
+    }
+
+    fn rules(&self, base: &str, quote: &str) -> TradingRules {
+        self.options
+            .trading_rules
+            .get(&pair_id(base, quote))
+            .copied()
+            .unwrap_or_default()
+    }
+
+    fn rate(&mut self, liquidity: Liquidity, time: Timestamp) -> f64 {
+        self.options.fees.rate(liquidity, self.volume.volume(time))
+    }
+
+    // A fee in the currency an order spends is part of what it spends, e.g. a
+    // buy of 101 USDT with a 1% fee in USDT buys for 100 USDT.
+    fn without_fee(&self, order_type: OrderType, rate: f64, spent: Amount) -> Amount {
+        match (&self.options.fees.currency, order_type) {
+            (FeeCurrency::Quote, OrderType::Buy) | (FeeCurrency::Base, OrderType::Sell) => {
+                spent / (1.0 + rate)
+            }
+            _ => spent,
+        }
+    }
+
+    // The amount a fill credits and its fee. A fee in the credited currency is
+    // taken from the credit, one in the spent currency is paid by the caller
+    // along with the order and a separate one is checked to be affordable.
+    fn settlement(
+        &self,
+        rate: f64,
+        order_type: OrderType,
+        base: &str,
+        quote: &str,
+        quantity: Amount,
+        notional: Amount,
+    ) -> Result<(Amount, Fee), OrderRejected> {
+        let fee = match &self.options.fees.currency {
+            FeeCurrency::Separate { currency, price } => {
+                let fee = separate_fee(currency, notional * rate, *price)?;
+                if fee.amount > self.assets.amount(currency) {
+                    return Err(OrderRejected::new(format!(
+                        "insufficient {} for fee",
+                        currency
+                    )));
+                }
+                fee
+            }
+            FeeCurrency::Base => Fee {
+                currency: base.into(),
+                amount: quantity * rate,
             },
-        });
-        ExchangeSimulation::new(event_stream, Assets {
-            quote: Some(starting_quote),
-            base: None,
-        }, options)
+            FeeCurrency::Quote => Fee {
+                currency: quote.into(),
+                amount: notional * rate,
+            },
+        };
+        let amount = match order_type {
+            OrderType::Buy if fee.currency == base => quantity - fee.amount,
+            OrderType::Buy => quantity,
+            OrderType::Sell if fee.currency == quote => notional - fee.amount,
+            OrderType::Sell => notional,
+        };
+        Ok((amount, fee))
+    }
+
+    // Credits a fill whose spent amount, fees in that currency included, is
+    // already debited.
+    #[allow(clippy::too_many_arguments)]
+    fn settle(
+        &mut self,
+        order_type: OrderType,
+        base: &str,
+        quote: &str,
+        amount: Amount,
+        fee: &Fee,
+        notional: Amount,
+        time: Timestamp,
+    ) {
+        self.volume.add(time, notional);
+        if let FeeCurrency::Separate { .. } = self.options.fees.currency {
+            self.assets.debit(&fee.currency, fee.amount);
+        }
+        let currency = match order_type {
+            OrderType::Buy => base,
+            OrderType::Sell => quote,
+        };
+        self.assets.credit(currency, amount);
+        self.assets.repay(currency);
+    }
+}
+
+// The fee is valued in the quote, without a price for the fee currency it
+// can't be charged.
+fn separate_fee(currency: &str, value: Amount, price: Price) -> Result<Fee, OrderRejected> {
+    if value > 0.0 && price <= 0.0 {
+        return Err(OrderRejected::new(format!(
+            "no price to charge fees in {}",
+            currency
+        )));
     }
+    Ok(Fee {
+        currency: currency.into(),
+        amount: if value > 0.0 { value / price } else { 0.0 },
+    })
+}
+
+fn pair_id(base: &str, quote: &str) -> String {
+    format!("{}/{}", base, quote)
 }
 
 #[async_trait]
-impl Exchange for ExchangeSimulation {
-    async fn event_stream(&self) -> Box<dyn Iterator<Item = Msg>> {
-        Box::new(self.event_stream.clone().into_iter())
+impl MarketDataSource for ExchangeSimulation {
+    async fn event_stream(&self) -> Events {
+        match self.streamed.lock().expect("unpoisoned lock").take() {
+            Some(events) => events,
+            None => self.source.events(),
+        }
     }
+}
 
-    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Amount> {
-        let price = self
-            .prices
-            .get(&order.correlation_id)
-            .expect("unknown correlation id");
-        let amount = order.amount * (1.0 - self.options.fee);
-        match order.order_type {
+#[async_trait]
+impl OrderExecutor for ExchangeSimulation {
+    async fn place_market_order(&mut self, order: &MarketOrder) -> Result<Fill> {
+        let signal = self
+            .quotes
+            .get(order.correlation_id, order.correlation_time)
+            .ok_or_else(|| {
+                OrderRejected::new(format!("no price for correlation {}", order.correlation_id))
+            })?;
+        if signal.pair_id != pair_id(&order.base, &order.quote) {
+            bail!(
+                "order for {}/{} does not match price of {}",
+                order.base,
+                order.quote,
+                signal.pair_id
+            );
+        }
+        let quote = self.delayed_quote(signal)?;
+        let price = self.options.fill_model.fill_price(&quote, order.order_type);
+        let rules = self.rules(&order.base, &order.quote);
+        let rate = self.rate(Liquidity::Taker, quote.time);
+        let budget = self.without_fee(order.order_type, rate, order.amount);
+        let (spent_currency, quantity, notional) = match order.order_type {
             OrderType::Buy => {
-                let amount = if price > &0.0 { amount / price } else { 0.0 };
-                self.assets.quote = Some(Asset {
-                    name: "USDT".into(),
-                    amount: 0.0,
-                });
-                self.assets.base = Some(Asset {
-                    name: "BTC".into(),
-                    amount,
-                });
-                Ok(amount)
+                let gross = if price > 0.0 { budget / price } else { 0.0 };
+                let quantity = rules.quantity(gross, price)?;
+                let notional = if quantity < gross {
+                    quantity * price
+                } else {
+                    budget
+                };
+                (&order.quote, quantity, notional)
             }
             OrderType::Sell => {
-                let amount = amount * price;
-                self.assets.quote = Some(Asset {
-                    name: "USDT".into(),
-                    amount,
-                });
-                self.assets.base = Some(Asset {
-                    name: "BTC".into(),
-                    amount: 0.0,
-                });
-                Ok(amount)
+                let quantity = rules.quantity(budget, price)?;
+                (&order.base, quantity, quantity * price)
+            }
+        };
+        let (amount, fee) = self.settlement(
+            rate,
+            order.order_type,
+            &order.base,
+            &order.quote,
+            quantity,
+            notional,
+        )?;
+        let mut spent = match order.order_type {
+            OrderType::Buy => notional,
+            OrderType::Sell => quantity,
+        };
+        if fee.currency == *spent_currency {
+            spent += fee.amount;
+        }
+        self.fund(spent_currency, spent, &order.base, &order.quote, price)?;
+        self.assets.debit(spent_currency, spent);
+        self.settle(
+            order.order_type,
+            &order.base,
+            &order.quote,
+            amount,
+            &fee,
+            notional,
+            quote.time,
+        );
+        Ok(Fill {
+            order_id: Uuid::new_v4(),
+            base: order.base.clone(),
+            quote: order.quote.clone(),
+            order_type: order.order_type,
+            amount,
+            price,
+            fee,
+            state: OrderState::Filled,
+        })
+    }
+
+    async fn place_limit_order(&mut self, order: &LimitOrder) -> Result<OrderId> {
+        let rules = self.rules(&order.base, &order.quote);
+        let price = rules.price(order.price)?;
+        let (name, reserved) = match order.order_type {
+            OrderType::Buy => {
+                let gross = if price > 0.0 {
+                    order.amount / price
+                } else {
+                    0.0
+                };
+                let quantity = rules.quantity(gross, price)?;
+                let reserved = if quantity < gross {
+                    quantity * price
+                } else {
+                    order.amount
+                };
+                (&order.quote, reserved)
+            }
+            OrderType::Sell => (&order.base, rules.quantity(order.amount, price)?),
+        };
+        if reserved > self.assets.amount(name) {
+            return Err(OrderRejected::new(format!("insufficient {}", name)).into());
+        }
+        self.assets.debit(name, reserved);
+
+        let resting = Order {
+            id: Uuid::new_v4(),
+            correlation_id: order.correlation_id,
+            price,
+            amount: reserved,
+        };
+        let book = self
+            .order_books
+            .entry(pair_id(&order.base, &order.quote))
+            .or_insert_with(|| Pair {
+                base: order.base.clone(),
+                quote: order.quote.clone(),
+                ..Default::default()
+            });
+        match order.order_type {
+            OrderType::Buy => book.bid_orders.push(resting),
+            OrderType::Sell => book.ask_orders.push(resting),
+        }
+        Ok(resting.id)
+    }
+
+    async fn cancel_order(&mut self, order_id: OrderId) -> Result<()> {
+        for book in self.order_books.values_mut() {
+            if let Some(index) = book.bid_orders.iter().position(|o| o.id == order_id) {
+                let order = book.bid_orders.remove(index);
+                self.assets.credit(&book.quote, order.amount);
+                return Ok(());
             }
+            if let Some(index) = book.ask_orders.iter().position(|o| o.id == order_id) {
+                let order = book.ask_orders.remove(index);
+                self.assets.credit(&book.base, order.amount);
+                return Ok(());
+            }
+        }
+        bail!("unknown order id {}", order_id)
+    }
+
+    async fn open_orders(&self) -> Result<Vec<Pair>> {
+        Ok(self
+            .order_books
+            .values()
+            .filter(|book| !book.bid_orders.is_empty() || !book.ask_orders.is_empty())
+            .cloned()
+            .collect())
+    }
+
+    async fn poll_fills(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Fill>> {
+        let quote = match self.quotes.get(correlation_id, correlation_time) {
+            Some(quote) => quote,
+            None => return Ok(vec![]),
+        };
+        let book = match self.order_books.get_mut(quote.pair_id) {
+            Some(book) => book,
+            None => return Ok(vec![]),
+        };
+        let (low, high) = quote
+            .candle
+            .map(|candle| (candle.low, candle.high))
+            .unwrap_or((quote.price, quote.price));
+        let (bought, bids): (Vec<Order>, Vec<Order>) = book
+            .bid_orders
+            .drain(..)
+            .partition(|o| o.correlation_id != correlation_id && o.price >= low);
+        let (sold, asks): (Vec<Order>, Vec<Order>) = book
+            .ask_orders
+            .drain(..)
+            .partition(|o| o.correlation_id != correlation_id && o.price <= high);
+        book.bid_orders = bids;
+        book.ask_orders = asks;
+        let (base, quote_name) = (book.base.clone(), book.quote.clone());
+
+        let filled = bought
+            .into_iter()
+            .map(|order| (OrderType::Buy, order))
+            .chain(sold.into_iter().map(|order| (OrderType::Sell, order)));
+        let mut fills = vec![];
+        for (order_type, order) in filled {
+            // the reserved amount pays for fees in the spent currency too
+            let rate = self.rate(Liquidity::Maker, quote.time);
+            let spent = self.without_fee(order_type, rate, order.amount);
+            let (quantity, notional) = match order_type {
+                OrderType::Buy if order.price > 0.0 => (spent / order.price, spent),
+                OrderType::Buy => (0.0, spent),
+                OrderType::Sell => (spent, spent * order.price),
+            };
+            let settlement =
+                self.settlement(rate, order_type, &base, &quote_name, quantity, notional);
+            let (amount, fee) = match settlement {
+                Ok(settlement) => settlement,
+                Err(rejected) => {
+                    log::warn!("keeping order {} open: {}", order.id, rejected);
+                    let book = self
+                        .order_books
+                        .get_mut(quote.pair_id)
+                        .expect("book of filled order");
+                    match order_type {
+                        OrderType::Buy => book.bid_orders.push(order),
+                        OrderType::Sell => book.ask_orders.push(order),
+                    }
+                    continue;
+                }
+            };
+            self.settle(
+                order_type,
+                &base,
+                &quote_name,
+                amount,
+                &fee,
+                notional,
+                quote.time,
+            );
+            fills.push(Fill {
+                order_id: order.id,
+                base: base.clone(),
+                quote: quote_name.clone(),
+                order_type,
+                amount,
+                price: order.price,
+                fee,
+                state: OrderState::Filled,
+            });
+        }
+        Ok(fills)
+    }
+
+    async fn poll_liquidations(
+        &mut self,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<Liquidation>> {
+        let margin = match self.options.margin {
+            Some(margin) => margin,
+            None => return Ok(vec![]),
+        };
+        let quote = match self.quotes.get(correlation_id, correlation_time) {
+            Some(quote) => quote,
+            None => return Ok(vec![]),
+        };
+        self.accrue_interest(margin, quote.time);
+        let (base, quote_name) = split_pair_id(quote.pair_id);
+        let (low, high) = quote
+            .candle
+            .map(|candle| (candle.low, candle.high))
+            .unwrap_or((quote.price, quote.price));
+        // Shorts are hurt most by the high of the candle, longs by its low.
+        let price = if self.assets.loan(base) > 0.0 {
+            high
+        } else {
+            low
+        };
+        if !margin.is_liquidated(Exposure::of(&self.assets, base, quote_name, price)) {
+            return Ok(vec![]);
+        }
+
+        let amount = self.assets.amount(base) - self.assets.loan(base);
+        let owed = self.assets.loan(base);
+        self.assets.debit(quote_name, owed * price);
+        self.assets.credit(base, owed);
+        self.assets.repay(base);
+        let held = self.assets.amount(base);
+        self.assets.debit(base, held);
+        self.assets.credit(quote_name, held * price);
+        self.assets.repay(quote_name);
+        Ok(vec![Liquidation {
+            base: base.into(),
+            quote: quote_name.into(),
+            amount,
+            price,
+            equity: self.assets.amount(quote_name) - self.assets.loan(quote_name),
+        }])
+    }
+
+    // Market orders are filled right away, so only resting limit orders can be
+    // asked for.
+    async fn poll_order(&mut self, last: &Fill) -> Result<Fill> {
+        let resting = self.order_books.values().any(|book| {
+            book.bid_orders
+                .iter()
+                .chain(&book.ask_orders)
+                .any(|order| order.id == last.order_id)
+        });
+        if !resting {
+            bail!("unknown order id {}", last.order_id)
         }
+        Ok(Fill {
+            state: OrderState::Placed,
+            ..last.clone()
+        })
     }
 
     async fn fetch_assets(&self) -> Result<Assets> {
@@ -131,8 +585,15 @@ impl Exchange for ExchangeSimulation {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::exchange::{Asset, OrderType};
-    use crate::messaging::message::{Msg, MsgData, MsgMetaData, PriceUpdated};
+    use crate::exchange::{
+        fees::{FeeCurrency, FeeSchedule, FeeTier},
+        fill::FillModel,
+        latency::Latency,
+        margin::{MarginOptions, DAY_MILLIS},
+        rules::TradingRules,
+        Asset, OrderType,
+    };
+    use crate::messaging::message::{Candle, Msg, MsgData, MsgMetaData, PriceUpdated};
     use pretty_assertions::assert_eq;
 
     #[async_std::test]
@@ -175,13 +636,10 @@ mod tests {
 
     #[async_std::test]
     async fn fetch_assets_should_return_given_assets() {
-        let expected_assets = Assets {
-            quote: Some(Asset {
-                amount: 40.0,
-                name: "USDT".into(),
-            }),
-            base: None,
-        };
+        let expected_assets = Assets::from(vec![Asset {
+            amount: 40.0,
+            name: "USDT".into(),
+        }]);
         let exchange = ExchangeSimulation::new(
             vec![],
             expected_assets.clone(),
@@ -195,13 +653,10 @@ mod tests {
 
     #[async_std::test]
     async fn fetch_assets_should_return_different_given_assets() {
-        let expected_assets = Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.00001,
-                name: "BTC".into(),
-            }),
-        };
+        let expected_assets = Assets::from(vec![Asset {
+            amount: 0.00001,
+            name: "BTC".into(),
+        }]);
         let exchange = ExchangeSimulation::new(
             vec![],
             expected_assets.clone(),
@@ -228,13 +683,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -247,7 +699,7 @@ mod tests {
             correlation_id: message_id,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(40.0, actual_amount)
     }
 
@@ -266,13 +718,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -286,7 +735,7 @@ mod tests {
             correlation_id: message_id,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(20.0, actual_amount)
     }
 
@@ -305,13 +754,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: None,
-                base: Some(Asset {
-                    amount: 40.0,
-                    name: "BTC".into(),
-                }),
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "BTC".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -325,7 +771,7 @@ mod tests {
             correlation_id: message_id,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(80.0, actual_amount)
     }
 
@@ -342,15 +788,12 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: None,
-                base: Some(Asset {
-                    amount: 40.0,
-                    name: "BTC".into(),
-                }),
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "BTC".into(),
+            }]),
             ExchangeOptions {
-                fee: 0.1,
+                fees: FeeSchedule::flat(0.1),
                 ..Default::default()
             },
         );
@@ -362,7 +805,7 @@ mod tests {
             order_type: OrderType::Sell,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(36.0, actual_amount)
     }
 
@@ -379,15 +822,12 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: None,
-                base: Some(Asset {
-                    amount: 40.0,
-                    name: "BTC".into(),
-                }),
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "BTC".into(),
+            }]),
             ExchangeOptions {
-                fee: 0.2,
+                fees: FeeSchedule::flat(0.2),
                 ..Default::default()
             },
         );
@@ -399,7 +839,7 @@ mod tests {
             order_type: OrderType::Sell,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(16.0, actual_amount)
     }
 
@@ -431,13 +871,10 @@ mod tests {
                     },
                 },
             ],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -451,7 +888,7 @@ mod tests {
             correlation_id: message_id,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(80.0, actual_amount)
     }
 
@@ -468,13 +905,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -489,16 +923,16 @@ mod tests {
         exchange.place_market_order(&order).await.unwrap();
         let actual_assets = exchange.fetch_assets().await.unwrap();
         assert_eq!(
-            Assets {
-                quote: Some(Asset {
+            Assets::from(vec![
+                Asset {
                     amount: 0.0,
-                    name: "USDT".into(),
-                }),
-                base: Some(Asset {
+                    name: "USDT".into()
+                },
+                Asset {
                     amount: 20.0,
-                    name: "BTC".into(),
-                }),
-            },
+                    name: "BTC".into()
+                }
+            ]),
             actual_assets
         )
     }
@@ -516,13 +950,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -537,16 +968,16 @@ mod tests {
         exchange.place_market_order(&order).await.unwrap();
         let actual_assets = exchange.fetch_assets().await.unwrap();
         assert_eq!(
-            Assets {
-                quote: Some(Asset {
+            Assets::from(vec![
+                Asset {
                     amount: 0.0,
-                    name: "USDT".into(),
-                }),
-                base: Some(Asset {
+                    name: "USDT".into()
+                },
+                Asset {
                     amount: 40.0,
-                    name: "BTC".into(),
-                }),
-            },
+                    name: "BTC".into()
+                }
+            ]),
             actual_assets
         )
     }
@@ -564,13 +995,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: None,
-                base: Some(Asset {
-                    amount: 40.0,
-                    name: "BTC".into(),
-                }),
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "BTC".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -585,16 +1013,16 @@ mod tests {
         exchange.place_market_order(&order).await.unwrap();
         let actual_assets = exchange.fetch_assets().await.unwrap();
         assert_eq!(
-            Assets {
-                quote: Some(Asset {
+            Assets::from(vec![
+                Asset {
                     amount: 80.0,
-                    name: "USDT".into(),
-                }),
-                base: Some(Asset {
+                    name: "USDT".into()
+                },
+                Asset {
                     amount: 0.0,
-                    name: "BTC".into(),
-                }),
-            },
+                    name: "BTC".into()
+                }
+            ]),
             actual_assets
         )
     }
@@ -612,13 +1040,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "BTC".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -633,16 +1058,16 @@ mod tests {
         exchange.place_market_order(&order).await.unwrap();
         let actual_assets = exchange.fetch_assets().await.unwrap();
         assert_eq!(
-            Assets {
-                quote: Some(Asset {
+            Assets::from(vec![
+                Asset {
                     amount: 40.0,
-                    name: "USDT".into(),
-                }),
-                base: Some(Asset {
+                    name: "USDT".into()
+                },
+                Asset {
                     amount: 0.0,
-                    name: "BTC".into(),
-                }),
-            },
+                    name: "BTC".into()
+                }
+            ]),
             actual_assets
         )
     }
@@ -660,13 +1085,10 @@ mod tests {
                     ..Default::default()
                 },
             }],
-            Assets {
-                quote: Some(Asset {
-                    amount: 0.0,
-                    name: "USDT".into(),
-                }),
-                base: None,
-            },
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
             ExchangeOptions {
                 ..Default::default()
             },
@@ -678,7 +1100,1232 @@ mod tests {
             order_type: OrderType::Buy,
             ..Default::default()
         };
-        let actual_amount = exchange.place_market_order(&order).await.unwrap();
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
         assert_eq!(0.0, actual_amount)
     }
+
+    async fn buy_one_quote_from_example_data(fill_model: FillModel) -> Amount {
+        let mut exchange = ExchangeSimulation::new_from_file(
+            "example_data_5min_interval.json",
+            Asset {
+                amount: 1.0,
+                name: "USDT".into(),
+            },
+            ExchangeOptions {
+                fill_model,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let first = exchange.event_stream().await.next().unwrap();
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 1.0,
+            order_type: OrderType::Buy,
+            correlation_id: first.metadata.correlation_id,
+            correlation_time: first.metadata.correlation_time,
+        };
+        exchange.place_market_order(&order).await.unwrap().amount
+    }
+
+    #[async_std::test]
+    async fn new_from_file_should_read_ohlcv_candles() {
+        let exchange = ExchangeSimulation::new_from_file(
+            "example_data_5min_interval.json",
+            Asset {
+                ..Default::default()
+            },
+            ExchangeOptions {
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let first = exchange.event_stream().await.next().unwrap();
+        let expected = Some(Candle {
+            open: 47600.5,
+            high: 47657.9,
+            low: 47563.6,
+            close: 47605.14,
+            volume_base: 2.36672254,
+            volume_quote: 112723.07688967,
+        });
+        match first.data {
+            MsgData::LivePriceUpdated(price_updated) => assert_eq!(expected, price_updated.candle),
+            _ => panic!("expected live price update"),
+        }
+    }
+
+    #[async_std::test]
+    async fn new_from_file_should_report_position_of_parse_errors() {
+        let path = std::env::temp_dir().join("simulation_broken.json");
+        std::fs::write(&path, "[{\"time\": 1, \"close\": 1.0},\n{\"time\": 2}]").unwrap();
+        let actual = ExchangeSimulation::new_from_file(
+            &path.to_string_lossy(),
+            Asset {
+                ..Default::default()
+            },
+            ExchangeOptions {
+                ..Default::default()
+            },
+        )
+        .err()
+        .unwrap();
+        match actual {
+            LoadError::Parse { line, .. } => assert_eq!(2, line),
+            _ => panic!("expected parse error"),
+        }
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_close_of_example_data() {
+        let actual_amount = buy_one_quote_from_example_data(FillModel::Close).await;
+        assert_eq!(1.0 / 47605.14, actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_next_open_of_example_data() {
+        let actual_amount = buy_one_quote_from_example_data(FillModel::NextOpen).await;
+        assert_eq!(1.0 / 47558.9, actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_volume_weighted_price_of_example_data() {
+        let actual_amount = buy_one_quote_from_example_data(FillModel::VolumeWeighted).await;
+        assert_eq!(1.0 / (112723.07688967 / 2.36672254), actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_high_of_example_data() {
+        let actual_amount = buy_one_quote_from_example_data(FillModel::WorstOfHighLow).await;
+        assert_eq!(1.0 / 47657.9, actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_low_of_example_data_when_selling() {
+        let mut exchange = ExchangeSimulation::new_from_file(
+            "example_data_5min_interval.json",
+            Asset {
+                amount: 2.0,
+                name: "BTC".into(),
+            },
+            ExchangeOptions {
+                fill_model: FillModel::WorstOfHighLow,
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let first = exchange.event_stream().await.next().unwrap();
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 2.0,
+            order_type: OrderType::Sell,
+            correlation_id: first.metadata.correlation_id,
+            correlation_time: first.metadata.correlation_time,
+        };
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
+        assert_eq!(2.0 * 47563.6, actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_next_open_of_same_pair() {
+        let message_id = Uuid::from_u128(0);
+        let mut exchange = ExchangeSimulation::new(
+            vec![
+                Msg {
+                    data: MsgData::LivePriceUpdated(PriceUpdated {
+                        pair_id: "BTC/USDT",
+                        price: 1.0,
+                        ..Default::default()
+                    }),
+                    metadata: MsgMetaData {
+                        correlation_id: message_id,
+                        ..Default::default()
+                    },
+                },
+                Msg {
+                    data: MsgData::LivePriceUpdated(PriceUpdated {
+                        pair_id: "ETH/USDT",
+                        price: 8.0,
+                        ..Default::default()
+                    }),
+                    metadata: MsgMetaData {
+                        correlation_id: Uuid::from_u128(1),
+                        ..Default::default()
+                    },
+                },
+                Msg {
+                    data: MsgData::LivePriceUpdated(PriceUpdated {
+                        pair_id: "BTC/USDT",
+                        price: 4.0,
+                        ..Default::default()
+                    }),
+                    metadata: MsgMetaData {
+                        correlation_id: Uuid::from_u128(2),
+                        ..Default::default()
+                    },
+                },
+            ],
+            Assets::from(vec![Asset {
+                amount: 40.0,
+                name: "USDT".into(),
+            }]),
+            ExchangeOptions {
+                fill_model: FillModel::NextOpen,
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 40.0,
+            order_type: OrderType::Buy,
+            correlation_id: message_id,
+            correlation_time: 0,
+        };
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
+        assert_eq!(10.0, actual_amount)
+    }
+
+    fn new_candle_msg(id: u128, low: f64, high: f64) -> Msg {
+        Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id: "BTC/USDT",
+                price: (low + high) / 2.0,
+                candle: Some(Candle {
+                    open: low,
+                    high,
+                    low,
+                    close: high,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            metadata: MsgMetaData {
+                correlation_id: Uuid::from_u128(id),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn new_limit_simulation(assets: Assets) -> ExchangeSimulation {
+        ExchangeSimulation::new(
+            vec![
+                new_candle_msg(0, 9.0, 11.0),
+                new_candle_msg(1, 7.0, 9.0),
+                new_candle_msg(2, 10.0, 14.0),
+            ],
+            assets,
+            ExchangeOptions {
+                ..Default::default()
+            },
+        )
+    }
+
+    fn new_limit_order(order_type: OrderType, price: f64, amount: f64) -> LimitOrder {
+        LimitOrder {
+            correlation_id: Uuid::from_u128(0),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type,
+            price,
+            amount,
+        }
+    }
+
+    fn usdt(amount: f64) -> Asset {
+        Asset {
+            amount,
+            name: "USDT".into(),
+        }
+    }
+
+    fn btc(amount: f64) -> Asset {
+        Asset {
+            amount,
+            name: "BTC".into(),
+        }
+    }
+
+    #[async_std::test]
+    async fn place_limit_order_should_reserve_quote() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 30.0))
+            .await
+            .unwrap();
+        let actual_assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(Assets::from(vec![usdt(10.0)]), actual_assets)
+    }
+
+    #[async_std::test]
+    async fn place_limit_order_should_fail_with_insufficient_funds() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(20.0)]));
+        let actual = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 30.0))
+            .await;
+        assert!(actual.is_err())
+    }
+
+    #[async_std::test]
+    async fn open_orders_should_return_resting_orders() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0), btc(2.0)]));
+        let bid_id = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 40.0))
+            .await
+            .unwrap();
+        let ask_id = exchange
+            .place_limit_order(&new_limit_order(OrderType::Sell, 12.0, 2.0))
+            .await
+            .unwrap();
+        let actual = exchange.open_orders().await.unwrap();
+        let expected = vec![Pair {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            bid_orders: vec![Order {
+                id: bid_id,
+                correlation_id: Uuid::from_u128(0),
+                price: 8.0,
+                amount: 40.0,
+            }],
+            ask_orders: vec![Order {
+                id: ask_id,
+                correlation_id: Uuid::from_u128(0),
+                price: 12.0,
+                amount: 2.0,
+            }],
+        }];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn cancel_order_should_refund_reserved_quote_and_remove_order() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        let order_id = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 30.0))
+            .await
+            .unwrap();
+        exchange.cancel_order(order_id).await.unwrap();
+        assert_eq!(40.0, exchange.fetch_assets().await.unwrap().amount("USDT"));
+        assert_eq!(Vec::<Pair>::new(), exchange.open_orders().await.unwrap())
+    }
+
+    #[async_std::test]
+    async fn new_from_events_should_fill_at_prices_of_any_source() {
+        let source = EventSource::Events(vec![new_candle_msg(0, 9.0, 11.0)]);
+        let mut exchange = ExchangeSimulation::new_from_events(
+            source.event_stream().await,
+            Assets::from(vec![usdt(20.0)]),
+            ExchangeOptions {
+                ..Default::default()
+            },
+        );
+        let mut events = exchange.event_stream().await;
+        assert_eq!(Some(new_candle_msg(0, 9.0, 11.0)), events.next());
+        let fill = exchange
+            .place_market_order(&MarketOrder {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                order_type: OrderType::Buy,
+                amount: 20.0,
+                ..Default::default()
+            })
+            .await
+            .unwrap();
+        assert_eq!(2.0, fill.amount);
+        assert_eq!(0, exchange.event_stream().await.count())
+    }
+
+    #[async_std::test]
+    async fn poll_order_should_report_resting_limit_order_as_placed() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        let order_id = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 30.0))
+            .await
+            .unwrap();
+        let last = Fill {
+            order_id,
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount: 0.0,
+            price: 8.0,
+            fee: Fee::default(),
+            state: OrderState::Placed,
+        };
+        let actual = exchange.poll_order(&last).await.unwrap();
+        assert_eq!(OrderState::Placed, actual.state);
+        exchange.cancel_order(order_id).await.unwrap();
+        assert!(exchange.poll_order(&last).await.is_err())
+    }
+
+    #[async_std::test]
+    async fn cancel_order_should_fail_for_unknown_order() {
+        let mut exchange = new_limit_simulation(Assets {
+            ..Default::default()
+        });
+        assert!(exchange.cancel_order(Uuid::from_u128(5)).await.is_err())
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_not_fill_in_candle_of_placement() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 10.0, 40.0))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(0), 0).await.unwrap();
+        assert_eq!(Vec::<Fill>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_fill_bid_when_later_low_crosses_limit() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        let order_id = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 40.0))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(1), 0).await.unwrap();
+        let expected = vec![Fill {
+            order_id,
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount: 5.0,
+            price: 8.0,
+            fee: Fee {
+                currency: "USDT".into(),
+                amount: 0.0,
+            },
+            state: OrderState::Filled,
+        }];
+        assert_eq!(expected, actual);
+        assert_eq!(5.0, exchange.fetch_assets().await.unwrap().amount("BTC"))
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_keep_bid_resting_when_low_stays_above_limit() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(40.0)]));
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 40.0))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(2), 0).await.unwrap();
+        assert_eq!(Vec::<Fill>::new(), actual);
+        assert_eq!(1, exchange.open_orders().await.unwrap()[0].bid_orders.len())
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_fill_ask_when_later_high_crosses_limit() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![btc(2.0)]));
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Sell, 12.0, 2.0))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(2), 0).await.unwrap();
+        assert_eq!(24.0, actual[0].amount);
+        assert_eq!(24.0, exchange.fetch_assets().await.unwrap().amount("USDT"));
+        assert_eq!(Vec::<Pair>::new(), exchange.open_orders().await.unwrap())
+    }
+
+    #[async_std::test]
+    async fn new_from_files_should_merge_pairs_ordered_by_time() {
+        let exchange = ExchangeSimulation::new_from_files(
+            &[
+                ("BTC/USDT", "example_data_5min_interval.json"),
+                ("ETH/USDT", "example_data_5min_interval.json"),
+            ],
+            Assets {
+                ..Default::default()
+            },
+            ExchangeOptions {
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        let actual: Vec<(PairId, u128)> = exchange
+            .event_stream()
+            .await
+            .take(4)
+            .map(|msg| {
+                (
+                    msg.metadata.correlation_pair_id,
+                    msg.metadata.correlation_time,
+                )
+            })
+            .collect();
+        let expected = vec![
+            ("BTC/USDT", 1638786180000),
+            ("ETH/USDT", 1638786180000),
+            ("BTC/USDT", 1638786480000),
+            ("ETH/USDT", 1638786480000),
+        ];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_keep_balances_of_other_currencies() {
+        let mut exchange = ExchangeSimulation::new(
+            vec![Msg {
+                data: MsgData::LivePriceUpdated(PriceUpdated {
+                    pair_id: "ETH/BTC",
+                    price: 0.5,
+                    ..Default::default()
+                }),
+                metadata: MsgMetaData {
+                    ..Default::default()
+                },
+            }],
+            Assets::from(vec![usdt(100.0), btc(2.0)]),
+            ExchangeOptions {
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "ETH".into(),
+            quote: "BTC".into(),
+            amount: 1.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        };
+        exchange.place_market_order(&order).await.unwrap();
+        let actual_assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(
+            Assets::from(vec![
+                usdt(100.0),
+                btc(1.0),
+                Asset {
+                    amount: 2.0,
+                    name: "ETH".into(),
+                }
+            ]),
+            actual_assets
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fail_for_pair_other_than_price_update() {
+        let mut exchange = ExchangeSimulation::new(
+            vec![Msg {
+                data: MsgData::LivePriceUpdated(PriceUpdated {
+                    pair_id: "ETH/BTC",
+                    price: 0.5,
+                    ..Default::default()
+                }),
+                metadata: MsgMetaData {
+                    ..Default::default()
+                },
+            }],
+            Assets::from(vec![usdt(100.0)]),
+            ExchangeOptions {
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 100.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        };
+        assert!(exchange.place_market_order(&order).await.is_err())
+    }
+
+    fn new_ruled_simulation(assets: Assets, rules: TradingRules) -> ExchangeSimulation {
+        ExchangeSimulation::new(
+            vec![Msg {
+                data: MsgData::LivePriceUpdated(PriceUpdated {
+                    pair_id: "BTC/USDT",
+                    price: 3.0,
+                    ..Default::default()
+                }),
+                metadata: MsgMetaData {
+                    ..Default::default()
+                },
+            }],
+            assets,
+            ExchangeOptions {
+                trading_rules: HashMap::from([("BTC/USDT".to_string(), rules)]),
+                ..Default::default()
+            },
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_round_bought_quantity_down_to_lot_size() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![usdt(10.0)]),
+            TradingRules {
+                lot_size: Some(0.5),
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 10.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        };
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
+        assert_eq!(3.0, actual_amount);
+        assert_eq!(
+            Assets::from(vec![usdt(1.0), btc(3.0)]),
+            exchange.fetch_assets().await.unwrap()
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_round_sold_quantity_down_to_lot_size() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![btc(2.7)]),
+            TradingRules {
+                lot_size: Some(1.0),
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 2.7,
+            order_type: OrderType::Sell,
+            ..Default::default()
+        };
+        let actual_amount = exchange.place_market_order(&order).await.unwrap().amount;
+        assert_eq!(6.0, actual_amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_order_below_min_notional() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![usdt(4.0)]),
+            TradingRules {
+                min_notional: Some(5.0),
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 4.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        };
+        let actual = exchange.place_market_order(&order).await.unwrap_err();
+        assert!(actual.downcast_ref::<OrderRejected>().is_some());
+        assert_eq!(
+            Assets::from(vec![usdt(4.0)]),
+            exchange.fetch_assets().await.unwrap()
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_order_above_max_order_size() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![btc(20.0)]),
+            TradingRules {
+                max_order_size: Some(10.0),
+                ..Default::default()
+            },
+        );
+        let order = MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 20.0,
+            order_type: OrderType::Sell,
+            ..Default::default()
+        };
+        let actual = exchange.place_market_order(&order).await.unwrap_err();
+        assert!(actual.downcast_ref::<OrderRejected>().is_some())
+    }
+
+    #[async_std::test]
+    async fn place_limit_order_should_reject_price_off_tick_size() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![usdt(40.0)]),
+            TradingRules {
+                tick_size: Some(0.5),
+                ..Default::default()
+            },
+        );
+        let actual = exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 2.3, 10.0))
+            .await
+            .unwrap_err();
+        assert!(actual.downcast_ref::<OrderRejected>().is_some())
+    }
+
+    #[async_std::test]
+    async fn place_limit_order_should_reserve_rounded_quantity() {
+        let mut exchange = new_ruled_simulation(
+            Assets::from(vec![usdt(40.0)]),
+            TradingRules {
+                lot_size: Some(1.0),
+                tick_size: Some(0.5),
+                ..Default::default()
+            },
+        );
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 2.5, 11.0))
+            .await
+            .unwrap();
+        assert_eq!(30.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    fn new_fee_simulation(assets: Assets, fees: FeeSchedule) -> ExchangeSimulation {
+        ExchangeSimulation::new(
+            vec![new_candle_msg(0, 10.0, 10.0), new_candle_msg(1, 7.0, 9.0)],
+            assets,
+            ExchangeOptions {
+                fees,
+                ..Default::default()
+            },
+        )
+    }
+
+    fn maker_taker_fees() -> FeeSchedule {
+        FeeSchedule {
+            maker: 0.001,
+            taker: 0.01,
+            ..Default::default()
+        }
+    }
+
+    fn market_buy(amount: f64) -> MarketOrder {
+        MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_charge_taker_fee_in_quote() {
+        let mut exchange = new_fee_simulation(Assets::from(vec![usdt(101.0)]), maker_taker_fees());
+        let actual = exchange
+            .place_market_order(&market_buy(101.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(10.0, actual.amount);
+        assert_eq!("USDT", actual.fee.currency);
+        assert!((actual.fee.amount - 1.0).abs() < 1e-9);
+        assert_eq!(10.0, assets.amount("BTC"));
+        assert!(assets.amount("USDT").abs() < 1e-9)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_quote_fee_beyond_balance() {
+        let mut exchange = new_fee_simulation(Assets::from(vec![usdt(100.0)]), maker_taker_fees());
+        let actual = exchange.place_market_order(&market_buy(101.0)).await;
+        assert!(actual.is_err());
+        assert_eq!(100.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_charge_maker_fee_for_resting_orders() {
+        let mut exchange = new_fee_simulation(Assets::from(vec![usdt(80.08)]), maker_taker_fees());
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 80.08))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(1), 0).await.unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert!((actual[0].amount - 10.0).abs() < 1e-9);
+        assert!((actual[0].fee.amount - 0.08).abs() < 1e-9);
+        assert!((assets.amount("BTC") - 10.0).abs() < 1e-9);
+        assert!(assets.amount("USDT").abs() < 1e-9)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_use_tier_reached_by_traded_volume() {
+        let mut exchange = new_fee_simulation(
+            Assets::from(vec![usdt(202.0)]),
+            FeeSchedule {
+                tiers: vec![FeeTier {
+                    min_volume: 100.0,
+                    maker: 0.0005,
+                    taker: 0.005,
+                }],
+                ..maker_taker_fees()
+            },
+        );
+        exchange
+            .place_market_order(&market_buy(101.0))
+            .await
+            .unwrap();
+        let actual = exchange
+            .place_market_order(&market_buy(100.5))
+            .await
+            .unwrap();
+        assert!((actual.fee.amount - 0.5).abs() < 1e-9)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_charge_fee_in_base() {
+        let mut exchange = new_fee_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            FeeSchedule {
+                currency: FeeCurrency::Base,
+                ..maker_taker_fees()
+            },
+        );
+        let actual = exchange
+            .place_market_order(&market_buy(100.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert!((actual.amount - 9.9).abs() < 1e-9);
+        assert_eq!("BTC", actual.fee.currency);
+        assert!((actual.fee.amount - 0.1).abs() < 1e-9);
+        assert!((assets.amount("BTC") - 9.9).abs() < 1e-9);
+        assert_eq!(0.0, assets.amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_charge_base_fee_on_top_of_sold_quantity() {
+        let mut exchange = new_fee_simulation(
+            Assets::from(vec![Asset {
+                amount: 10.1,
+                name: "BTC".into(),
+            }]),
+            FeeSchedule {
+                currency: FeeCurrency::Base,
+                ..maker_taker_fees()
+            },
+        );
+        let actual = exchange
+            .place_market_order(&MarketOrder {
+                order_type: OrderType::Sell,
+                ..market_buy(10.1)
+            })
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(100.0, actual.amount);
+        assert!((actual.fee.amount - 0.1).abs() < 1e-9);
+        assert!(assets.amount("BTC").abs() < 1e-9);
+        assert_eq!(100.0, assets.amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_charge_fee_in_separate_currency() {
+        let mut exchange = new_fee_simulation(
+            Assets::from(vec![
+                usdt(100.0),
+                Asset {
+                    amount: 1.0,
+                    name: "BNB".into(),
+                },
+            ]),
+            FeeSchedule {
+                currency: FeeCurrency::Separate {
+                    currency: "BNB".into(),
+                    price: 20.0,
+                },
+                ..maker_taker_fees()
+            },
+        );
+        let actual = exchange
+            .place_market_order(&market_buy(100.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(10.0, actual.amount);
+        assert_eq!(10.0, assets.amount("BTC"));
+        assert!((assets.amount("BNB") - 0.95).abs() < 1e-9)
+    }
+
+    fn separate_fees(price: f64) -> FeeSchedule {
+        FeeSchedule {
+            currency: FeeCurrency::Separate {
+                currency: "BNB".into(),
+                price,
+            },
+            ..maker_taker_fees()
+        }
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_separate_fee_beyond_balance() {
+        let mut exchange = new_fee_simulation(
+            Assets::from(vec![
+                usdt(100.0),
+                Asset {
+                    amount: 0.01,
+                    name: "BNB".into(),
+                },
+            ]),
+            separate_fees(20.0),
+        );
+        let actual = exchange.place_market_order(&market_buy(100.0)).await;
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert!(actual.is_err());
+        assert_eq!(100.0, assets.amount("USDT"));
+        assert_eq!(0.01, assets.amount("BNB"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_separate_fee_without_price() {
+        let mut exchange = new_fee_simulation(Assets::from(vec![usdt(100.0)]), separate_fees(0.0));
+        let actual = exchange.place_market_order(&market_buy(100.0)).await;
+        assert!(actual.is_err());
+        assert_eq!(100.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn poll_fills_should_keep_order_open_until_separate_fee_is_affordable() {
+        let mut exchange = new_fee_simulation(Assets::from(vec![usdt(80.0)]), separate_fees(20.0));
+        exchange
+            .place_limit_order(&new_limit_order(OrderType::Buy, 8.0, 80.0))
+            .await
+            .unwrap();
+        let actual = exchange.poll_fills(Uuid::from_u128(1), 0).await.unwrap();
+        let open = exchange.open_orders().await.unwrap();
+        assert!(actual.is_empty());
+        assert_eq!(1, open[0].bid_orders.len())
+    }
+
+    fn new_timed_msg(id: u128, pair_id: PairId, datetime: Timestamp, price: f64) -> Msg {
+        Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id,
+                datetime,
+                price,
+                ..Default::default()
+            }),
+            metadata: MsgMetaData {
+                correlation_id: Uuid::from_u128(id),
+                ..Default::default()
+            },
+        }
+    }
+
+    fn new_latency_simulation(latency: Latency) -> ExchangeSimulation {
+        ExchangeSimulation::new(
+            vec![
+                new_timed_msg(0, "BTC/USDT", 0, 10.0),
+                new_timed_msg(1, "ETH/USDT", 500, 1.0),
+                new_timed_msg(2, "BTC/USDT", 1000, 20.0),
+                new_timed_msg(3, "BTC/USDT", 2000, 40.0),
+            ],
+            Assets::from(vec![usdt(80.0)]),
+            ExchangeOptions {
+                latency,
+                ..Default::default()
+            },
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_signal_price_without_latency() {
+        let mut exchange = new_latency_simulation(Latency::None);
+        let actual = exchange
+            .place_market_order(&market_buy(80.0))
+            .await
+            .unwrap();
+        assert_eq!(8.0, actual.amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_first_price_after_fixed_latency() {
+        let mut exchange = new_latency_simulation(Latency::Fixed(500));
+        let actual = exchange
+            .place_market_order(&market_buy(80.0))
+            .await
+            .unwrap();
+        assert_eq!(4.0, actual.amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_at_price_exactly_at_latency() {
+        let mut exchange = new_latency_simulation(Latency::Fixed(2000));
+        let actual = exchange
+            .place_market_order(&market_buy(80.0))
+            .await
+            .unwrap();
+        assert_eq!(2.0, actual.amount)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_without_price_after_latency() {
+        let mut exchange = new_latency_simulation(Latency::Fixed(5000));
+        let actual = exchange
+            .place_market_order(&market_buy(80.0))
+            .await
+            .unwrap_err();
+        assert!(actual.downcast_ref::<OrderRejected>().is_some());
+        assert_eq!(80.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_fill_same_prices_for_same_seed() {
+        let latency = Latency::Random {
+            min: 0,
+            max: 2000,
+            seed: 3,
+        };
+        let mut first = new_latency_simulation(latency);
+        let mut second = new_latency_simulation(latency);
+        for _ in 0..5 {
+            let expected = first.place_market_order(&market_buy(1.0)).await.unwrap();
+            let actual = second.place_market_order(&market_buy(1.0)).await.unwrap();
+            assert_eq!(expected.amount, actual.amount)
+        }
+    }
+
+    fn new_margin_msg(id: u128, datetime: Timestamp, low: f64, high: f64) -> Msg {
+        let mut msg = new_candle_msg(id, low, high);
+        if let MsgData::LivePriceUpdated(price_updated) = &mut msg.data {
+            price_updated.datetime = datetime;
+        }
+        msg
+    }
+
+    fn new_margin_simulation(assets: Assets, events: Vec<Msg>) -> ExchangeSimulation {
+        ExchangeSimulation::new(
+            events,
+            assets,
+            ExchangeOptions {
+                margin: Some(MarginOptions {
+                    leverage: 3.0,
+                    daily_interest: 0.01,
+                    maintenance_margin: 0.1,
+                }),
+                ..Default::default()
+            },
+        )
+    }
+
+    fn market_sell(amount: f64) -> MarketOrder {
+        MarketOrder {
+            order_type: OrderType::Sell,
+            ..market_buy(amount)
+        }
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_borrow_quote_up_to_leverage() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![new_margin_msg(0, 0, 10.0, 10.0)],
+        );
+        exchange
+            .place_market_order(&market_buy(300.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(30.0, assets.amount("BTC"));
+        assert_eq!(0.0, assets.amount("USDT"));
+        assert_eq!(200.0, assets.loan("USDT"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_borrowing_above_leverage() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![new_margin_msg(0, 0, 10.0, 10.0)],
+        );
+        let actual = exchange
+            .place_market_order(&market_buy(301.0))
+            .await
+            .unwrap_err();
+        assert!(actual.downcast_ref::<OrderRejected>().is_some());
+        assert_eq!(
+            Assets::from(vec![usdt(100.0)]),
+            exchange.fetch_assets().await.unwrap()
+        )
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_open_short_by_borrowing_base() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![new_margin_msg(0, 0, 10.0, 10.0)],
+        );
+        let actual = exchange
+            .place_market_order(&market_sell(10.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(100.0, actual.amount);
+        assert_eq!(200.0, assets.amount("USDT"));
+        assert_eq!(0.0, assets.amount("BTC"));
+        assert_eq!(10.0, assets.loan("BTC"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_repay_short_when_buying_back() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![new_margin_msg(0, 0, 10.0, 10.0)],
+        );
+        exchange
+            .place_market_order(&market_sell(10.0))
+            .await
+            .unwrap();
+        exchange
+            .place_market_order(&market_buy(100.0))
+            .await
+            .unwrap();
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(100.0, assets.amount("USDT"));
+        assert_eq!(0.0, assets.amount("BTC"));
+        assert_eq!(HashMap::new(), assets.loans)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_sell_without_margin_and_base() {
+        let mut exchange =
+            new_fee_simulation(Assets::from(vec![usdt(100.0)]), FeeSchedule::flat(0.0));
+        let actual = exchange
+            .place_market_order(&market_sell(1.0))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&OrderRejected::new("insufficient BTC")),
+            actual.downcast_ref::<OrderRejected>()
+        );
+        let assets = exchange.fetch_assets().await.unwrap();
+        assert_eq!(100.0, assets.amount("USDT"));
+        assert_eq!(0.0, assets.amount("BTC"));
+        assert_eq!(HashMap::new(), assets.loans)
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_buy_without_margin_and_quote() {
+        let mut exchange =
+            new_fee_simulation(Assets::from(vec![usdt(100.0)]), FeeSchedule::flat(0.0));
+        let actual = exchange
+            .place_market_order(&market_buy(150.0))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&OrderRejected::new("insufficient USDT")),
+            actual.downcast_ref::<OrderRejected>()
+        );
+        assert_eq!(100.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn place_market_order_should_reject_unknown_correlation_id() {
+        let mut exchange = new_limit_simulation(Assets::from(vec![usdt(100.0)]));
+        let order = MarketOrder {
+            correlation_id: Uuid::from_u128(99),
+            ..market_buy(10.0)
+        };
+        let actual = exchange.place_market_order(&order).await.unwrap_err();
+        assert_eq!(
+            Some(&OrderRejected::new(format!(
+                "no price for correlation {}",
+                Uuid::from_u128(99)
+            ))),
+            actual.downcast_ref::<OrderRejected>()
+        );
+        assert_eq!(100.0, exchange.fetch_assets().await.unwrap().amount("USDT"))
+    }
+
+    #[async_std::test]
+    async fn poll_liquidations_should_close_short_when_high_breaches_maintenance_margin() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![
+                new_margin_msg(0, 0, 10.0, 10.0),
+                new_margin_msg(1, 0, 12.0, 19.0),
+            ],
+        );
+        exchange
+            .place_market_order(&market_sell(10.0))
+            .await
+            .unwrap();
+        let actual = exchange
+            .poll_liquidations(Uuid::from_u128(1), 0)
+            .await
+            .unwrap();
+        let expected = vec![Liquidation {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: -10.0,
+            price: 19.0,
+            equity: 10.0,
+        }];
+        assert_eq!(expected, actual);
+        assert_eq!(HashMap::new(), exchange.fetch_assets().await.unwrap().loans)
+    }
+
+    #[async_std::test]
+    async fn poll_liquidations_should_close_long_when_low_breaches_maintenance_margin() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![
+                new_margin_msg(0, 0, 10.0, 10.0),
+                new_margin_msg(1, 0, 7.0, 9.0),
+            ],
+        );
+        exchange
+            .place_market_order(&market_buy(300.0))
+            .await
+            .unwrap();
+        let actual = exchange
+            .poll_liquidations(Uuid::from_u128(1), 0)
+            .await
+            .unwrap();
+        let expected = vec![Liquidation {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 30.0,
+            price: 7.0,
+            equity: 10.0,
+        }];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn poll_liquidations_should_keep_healthy_positions() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![
+                new_margin_msg(0, 0, 10.0, 10.0),
+                new_margin_msg(1, 0, 9.0, 11.0),
+            ],
+        );
+        exchange
+            .place_market_order(&market_sell(10.0))
+            .await
+            .unwrap();
+        let actual = exchange
+            .poll_liquidations(Uuid::from_u128(1), 0)
+            .await
+            .unwrap();
+        assert_eq!(Vec::<Liquidation>::new(), actual);
+        assert_eq!(10.0, exchange.fetch_assets().await.unwrap().loan("BTC"))
+    }
+
+    #[async_std::test]
+    async fn poll_liquidations_should_accrue_interest_on_loans() {
+        let mut exchange = new_margin_simulation(
+            Assets::from(vec![usdt(100.0)]),
+            vec![
+                new_margin_msg(0, 0, 10.0, 10.0),
+                new_margin_msg(1, 2 * DAY_MILLIS, 10.0, 10.0),
+            ],
+        );
+        exchange
+            .poll_liquidations(Uuid::from_u128(0), 0)
+            .await
+            .unwrap();
+        exchange
+            .place_market_order(&market_sell(10.0))
+            .await
+            .unwrap();
+        exchange
+            .poll_liquidations(Uuid::from_u128(1), 2 * DAY_MILLIS)
+            .await
+            .unwrap();
+        let actual = exchange.fetch_assets().await.unwrap().loan("BTC");
+        assert!((actual - 10.2).abs() < 1e-9)
+    }
 }
@@ -0,0 +1,265 @@
+use super::{split_pair_id, Amount, Assets, OrderType};
+use crate::messaging::message::{PairId, Price};
+use std::collections::{HashMap, VecDeque};
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum SizingPolicy {
+    #[default]
+    AllIn,
+    FixedNotional(Amount),
+    FixedFraction(f64),
+    VolatilityTarget {
+        target: f64,
+        window: usize,
+    },
+    FractionalKelly {
+        fraction: f64,
+        win_rate: f64,
+        payoff_ratio: f64,
+    },
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct PositionSizer {
+    policy: SizingPolicy,
+    prices: HashMap<PairId, VecDeque<Price>>,
+}
+
+impl PositionSizer {
+    pub fn new(policy: SizingPolicy) -> Self {
+        PositionSizer {
+            policy,
+            ..Default::default()
+        }
+    }
+
+    pub fn observe(&mut self, pair_id: PairId, price: Price) {
+        if let SizingPolicy::VolatilityTarget { window, .. } = self.policy {
+            let prices = self.prices.entry(pair_id).or_default();
+            prices.push_back(price);
+            if prices.len() > window + 1 {
+                prices.pop_front();
+            }
+        }
+    }
+
+    pub fn order_amount(
+        &self,
+        order_type: OrderType,
+        pair_id: PairId,
+        assets: &Assets,
+        price: Price,
+    ) -> Amount {
+        let (base, quote) = split_pair_id(pair_id);
+        let notional = match self.policy {
+            SizingPolicy::AllIn => {
+                return match order_type {
+                    OrderType::Buy => assets.amount(quote),
+                    OrderType::Sell => assets.amount(base),
+                }
+            }
+            _ => self.notional(pair_id, assets, price),
+        };
+        match order_type {
+            OrderType::Buy => notional.min(assets.amount(quote)),
+            OrderType::Sell if price > 0.0 => (notional / price).min(assets.amount(base)),
+            OrderType::Sell => 0.0,
+        }
+    }
+
+    pub fn short_amount(&self, pair_id: PairId, assets: &Assets, price: Price) -> Amount {
+        if price > 0.0 {
+            self.notional(pair_id, assets, price) / price
+        } else {
+            0.0
+        }
+    }
+
+    fn notional(&self, pair_id: PairId, assets: &Assets, price: Price) -> Amount {
+        let (base, quote) = split_pair_id(pair_id);
+        let equity = assets.amount(quote) + assets.amount(base) * price;
+        match self.policy {
+            SizingPolicy::AllIn => equity,
+            SizingPolicy::FixedNotional(notional) => notional,
+            SizingPolicy::FixedFraction(fraction) => fraction * equity,
+            SizingPolicy::VolatilityTarget { target, .. } => match self.volatility(pair_id) {
+                Some(volatility) if volatility > 0.0 => equity * f64::min(target / volatility, 1.0),
+                Some(_) => equity,
+                None => 0.0,
+            },
+            SizingPolicy::FractionalKelly {
+                fraction,
+                win_rate,
+                payoff_ratio,
+            } => {
+                // without a win/loss history the edge is unknown, so nothing is traded
+                let share = fraction * (win_rate - (1.0 - win_rate) / payoff_ratio);
+                if share.is_finite() {
+                    equity * share.clamp(0.0, 1.0)
+                } else {
+                    0.0
+                }
+            }
+        }
+    }
+
+    fn volatility(&self, pair_id: PairId) -> Option<f64> {
+        let window = match self.policy {
+            SizingPolicy::VolatilityTarget { window, .. } => window,
+            _ => return None,
+        };
+        let prices = self.prices.get(pair_id)?;
+        if window < 2 || prices.len() <= window {
+            return None;
+        }
+        let returns: Vec<f64> = prices
+            .iter()
+            .zip(prices.iter().skip(1))
+            .map(|(previous, current)| (current / previous).ln())
+            .collect();
+        let mean = returns.iter().sum::<f64>() / returns.len() as f64;
+        let variance =
+            returns.iter().map(|r| (r - mean).powi(2)).sum::<f64>() / (returns.len() - 1) as f64;
+        Some(variance.sqrt())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::exchange::Asset;
+    use pretty_assertions::assert_eq;
+
+    fn assets(quote: Amount, base: Amount) -> Assets {
+        Assets::from(vec![
+            Asset {
+                amount: quote,
+                name: "USDT".into(),
+            },
+            Asset {
+                amount: base,
+                name: "BTC".into(),
+            },
+        ])
+    }
+
+    #[test]
+    fn all_in_should_buy_whole_quote_balance() {
+        let sizer = PositionSizer::new(SizingPolicy::AllIn);
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(40.0, 1.0), 10.0);
+        assert_eq!(40.0, actual)
+    }
+
+    #[test]
+    fn all_in_should_sell_whole_base_balance() {
+        let sizer = PositionSizer::new(SizingPolicy::AllIn);
+        let actual = sizer.order_amount(OrderType::Sell, "BTC/USDT", &assets(40.0, 1.0), 10.0);
+        assert_eq!(1.0, actual)
+    }
+
+    #[test]
+    fn fixed_notional_should_be_capped_by_available_quote() {
+        let sizer = PositionSizer::new(SizingPolicy::FixedNotional(50.0));
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(40.0, 0.0), 10.0);
+        assert_eq!(40.0, actual)
+    }
+
+    #[test]
+    fn fixed_notional_should_sell_part_of_base() {
+        let sizer = PositionSizer::new(SizingPolicy::FixedNotional(5.0));
+        let actual = sizer.order_amount(OrderType::Sell, "BTC/USDT", &assets(0.0, 2.0), 10.0);
+        assert_eq!(0.5, actual)
+    }
+
+    #[test]
+    fn fixed_fraction_should_use_fraction_of_equity() {
+        let sizer = PositionSizer::new(SizingPolicy::FixedFraction(0.25));
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(60.0, 4.0), 10.0);
+        assert_eq!(25.0, actual)
+    }
+
+    #[test]
+    fn volatility_target_should_not_trade_without_enough_prices() {
+        let mut sizer = PositionSizer::new(SizingPolicy::VolatilityTarget {
+            target: 0.01,
+            window: 3,
+        });
+        sizer.observe("BTC/USDT", 10.0);
+        sizer.observe("BTC/USDT", 11.0);
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 11.0);
+        assert_eq!(0.0, actual)
+    }
+
+    #[test]
+    fn volatility_target_should_scale_down_by_realized_volatility() {
+        let mut sizer = PositionSizer::new(SizingPolicy::VolatilityTarget {
+            target: 0.05,
+            window: 2,
+        });
+        for price in [100.0, 100.0, 100.0 * 0.2_f64.exp(), 100.0] {
+            sizer.observe("BTC/USDT", price);
+        }
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 100.0);
+        assert!((actual - 100.0 * 0.05 / 0.08_f64.sqrt()).abs() < 1e-9)
+    }
+
+    #[test]
+    fn fractional_kelly_should_size_by_edge() {
+        let sizer = PositionSizer::new(SizingPolicy::FractionalKelly {
+            fraction: 0.5,
+            win_rate: 0.6,
+            payoff_ratio: 2.0,
+        });
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 10.0);
+        assert_eq!(20.0, actual)
+    }
+
+    #[test]
+    fn short_amount_should_sell_whole_quote_balance_worth_of_base() {
+        let sizer = PositionSizer::new(SizingPolicy::AllIn);
+        let actual = sizer.short_amount("BTC/USDT", &assets(40.0, 0.0), 10.0);
+        assert_eq!(4.0, actual)
+    }
+
+    #[test]
+    fn short_amount_should_use_fixed_notional() {
+        let sizer = PositionSizer::new(SizingPolicy::FixedNotional(20.0));
+        let actual = sizer.short_amount("BTC/USDT", &assets(40.0, 0.0), 10.0);
+        assert_eq!(2.0, actual)
+    }
+
+    #[test]
+    fn fractional_kelly_should_not_trade_without_edge() {
+        let sizer = PositionSizer::new(SizingPolicy::FractionalKelly {
+            fraction: 0.5,
+            win_rate: 0.3,
+            payoff_ratio: 1.0,
+        });
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 10.0);
+        assert_eq!(0.0, actual)
+    }
+
+    #[test]
+    fn fractional_kelly_should_not_trade_without_history() {
+        for (win_rate, payoff_ratio) in [(1.0, 0.0), (f64::NAN, 2.0), (0.6, f64::NAN)] {
+            let sizer = PositionSizer::new(SizingPolicy::FractionalKelly {
+                fraction: 0.5,
+                win_rate,
+                payoff_ratio,
+            });
+            let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 10.0);
+            assert_eq!(0.0, actual)
+        }
+    }
+
+    #[test]
+    fn fractional_kelly_should_stake_at_most_whole_equity() {
+        let sizer = PositionSizer::new(SizingPolicy::FractionalKelly {
+            fraction: 2.0,
+            win_rate: 0.9,
+            payoff_ratio: f64::INFINITY,
+        });
+        let actual = sizer.order_amount(OrderType::Buy, "BTC/USDT", &assets(100.0, 0.0), 10.0);
+        assert_eq!(100.0, actual)
+    }
+}
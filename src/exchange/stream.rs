@@ -0,0 +1,414 @@
+use super::{
+    fill::Quote,
+    loader::{self, DataFile},
+    MarketDataSource,
+};
+use crate::{
+    messaging::message::{MessageId, Msg, MsgData, MsgMetaData, PairId, PriceUpdated, Timestamp},
+    tools::time::{TimeProvider, TimeProviderImpl},
+};
+use async_trait::async_trait;
+use crossbeam::channel;
+use std::{
+    collections::VecDeque,
+    iter::{self, Peekable},
+    thread,
+};
+use uuid::Uuid;
+
+pub type Events = Box<dyn Iterator<Item = Msg> + Send + Sync>;
+
+// How far past a quote the cursor reads ahead for the next quote of its pair,
+// so a pair which stops trading doesn't buffer the rest of the stream.
+const LOOKAHEAD: Timestamp = 24 * 60 * 60 * 1000;
+
+fn candle_events(file: &DataFile) -> impl Iterator<Item = Msg> + Send + Sync {
+    let pair_id = file.pair_id;
+    let klines = loader::read_klines(file).unwrap_or_else(|err| {
+        log::error!("{}", err);
+        Box::new(iter::empty())
+    });
+    let mut time = TimeProviderImpl {};
+    klines
+        .map_while(|kline| kline.map_err(|err| log::error!("{}", err)).ok())
+        .enumerate()
+        .map(move |(index, kline)| {
+            let message_id = Uuid::new_v3(
+                &Uuid::NAMESPACE_OID,
+                format!("{}/{}/{}", pair_id, index, kline.time).as_bytes(),
+            );
+            Msg {
+                data: MsgData::LivePriceUpdated(PriceUpdated {
+                    datetime: kline.time,
+                    pair_id,
+                    price: kline.close,
+                    candle: kline.candle,
+                }),
+                metadata: MsgMetaData {
+                    id: message_id,
+                    correlation_id: message_id,
+                    causation_id: message_id,
+                    creation_time: time.now(),
+                    correlation_time: kline.time,
+                    correlation_price: kline.close,
+                    correlation_pair_id: pair_id,
+                },
+            }
+        })
+}
+
+struct MergedEvents<I: Iterator<Item = Msg>> {
+    sources: Vec<Peekable<I>>,
+}
+
+impl<I: Iterator<Item = Msg>> Iterator for MergedEvents<I> {
+    type Item = Msg;
+
+    fn next(&mut self) -> Option<Msg> {
+        let mut earliest: Option<(usize, Timestamp)> = None;
+        for (index, source) in self.sources.iter_mut().enumerate() {
+            if let Some(event) = source.peek() {
+                let time = event.metadata.correlation_time;
+                if earliest.is_none_or(|(_, earliest)| time < earliest) {
+                    earliest = Some((index, time));
+                }
+            }
+        }
+        let (index, _) = earliest?;
+        self.sources[index].next()
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum EventSource {
+    Events(Vec<Msg>),
+    Files(Vec<DataFile>),
+}
+
+impl EventSource {
+    pub fn events(&self) -> Events {
+        match self {
+            EventSource::Events(events) => Box::new(events.clone().into_iter()),
+            EventSource::Files(files) => {
+                let sources = files
+                    .iter()
+                    .map(|file| candle_events(file).peekable())
+                    .collect();
+                Box::new(MergedEvents { sources }.chain(iter::once(Msg {
+                    data: MsgData::Shutdown,
+                    metadata: MsgMetaData {
+                        ..Default::default()
+                    },
+                })))
+            }
+        }
+    }
+
+    pub fn quotes(&self) -> QuoteCursor {
+        QuoteCursor {
+            events: self.events(),
+            window: VecDeque::new(),
+            evict: matches!(self, EventSource::Files(_)),
+            lookahead: LOOKAHEAD,
+        }
+    }
+}
+
+#[async_trait]
+impl MarketDataSource for EventSource {
+    async fn event_stream(&self) -> Events {
+        self.events()
+    }
+}
+
+// Messages sent to the channel, e.g. by a LiveFeed.
+#[async_trait]
+impl MarketDataSource for channel::Receiver<Msg> {
+    async fn event_stream(&self) -> Events {
+        Box::new(self.clone().into_iter())
+    }
+}
+
+// Splits a stream, e.g. of a LiveFeed, into the events for the chain and a
+// cursor over the same quotes. The cursor never waits for quotes which did not
+// arrive yet, as every event reaches it before it reaches the chain.
+pub fn tee(events: Events) -> (Events, QuoteCursor) {
+    let (quotes_sender, quotes_receiver) = channel::unbounded();
+    let (events_sender, events_receiver) = channel::unbounded();
+    thread::spawn(move || {
+        for event in events {
+            quotes_sender.send(event.clone()).ok();
+            if events_sender.send(event).is_err() {
+                break;
+            }
+        }
+    });
+    let quotes = QuoteCursor {
+        events: Box::new(iter::from_fn(move || quotes_receiver.try_recv().ok())),
+        window: VecDeque::new(),
+        evict: true,
+        lookahead: LOOKAHEAD,
+    };
+    (Box::new(events_receiver.into_iter()), quotes)
+}
+
+// Reads the price updates a second time, in step with the event stream, so fills
+// can look up quotes by correlation id. Streamed sources only keep the quotes
+// from the last looked up one onwards.
+pub struct QuoteCursor {
+    events: Events,
+    window: VecDeque<(MessageId, Quote)>,
+    evict: bool,
+    lookahead: Timestamp,
+}
+
+impl QuoteCursor {
+    // Quotes after the time of the correlation are not searched, so an unknown
+    // or evicted id doesn't read the rest of the stream.
+    pub fn get(&mut self, correlation_id: MessageId, time: Timestamp) -> Option<Quote> {
+        let index = self.find(|id, _| id == correlation_id, |quote| quote.time > time)?;
+        let index = if self.evict {
+            self.window.drain(..index);
+            0
+        } else {
+            index
+        };
+        Some(self.complete(index))
+    }
+
+    pub fn first_after(&mut self, pair_id: PairId, time: Timestamp) -> Option<Quote> {
+        let horizon = time.saturating_add(self.lookahead);
+        let index = self.find(
+            |_, quote| quote.pair_id == pair_id && quote.time >= time,
+            |quote| quote.time > horizon,
+        )?;
+        Some(self.complete(index))
+    }
+
+    fn find(
+        &mut self,
+        matches: impl Fn(MessageId, &Quote) -> bool,
+        is_past: impl Fn(&Quote) -> bool,
+    ) -> Option<usize> {
+        if let Some(index) = self
+            .window
+            .iter()
+            .position(|(id, quote)| matches(*id, quote))
+        {
+            return Some(index);
+        }
+        while self.pull() {
+            let (id, quote) = self.window.back()?;
+            if matches(*id, quote) {
+                return Some(self.window.len() - 1);
+            }
+            if is_past(quote) {
+                return None;
+            }
+        }
+        None
+    }
+
+    // Without a quote of the pair within the lookahead the next open stays unknown.
+    fn complete(&mut self, index: usize) -> Quote {
+        let horizon = self.window[index].1.time.saturating_add(self.lookahead);
+        while self.window[index].1.next_open.is_none()
+            && self
+                .window
+                .back()
+                .is_some_and(|(_, quote)| quote.time <= horizon)
+            && self.pull()
+        {}
+        self.window[index].1
+    }
+
+    fn pull(&mut self) -> bool {
+        for event in self.events.by_ref() {
+            if let MsgData::LivePriceUpdated(price_updated) = &event.data {
+                let open = price_updated
+                    .candle
+                    .map(|candle| candle.open)
+                    .unwrap_or(price_updated.price);
+                if let Some((_, previous)) = self
+                    .window
+                    .iter_mut()
+                    .rev()
+                    .find(|(_, quote)| quote.pair_id == price_updated.pair_id)
+                {
+                    previous.next_open = Some(open);
+                }
+                self.window.push_back((
+                    event.metadata.correlation_id,
+                    Quote {
+                        pair_id: price_updated.pair_id,
+                        time: price_updated.datetime,
+                        price: price_updated.price,
+                        candle: price_updated.candle,
+                        next_open: None,
+                    },
+                ));
+                return true;
+            }
+        }
+        false
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::fs;
+
+    fn write_file(name: &str, content: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::write(&path, content).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn price_msg(id: u128, pair_id: PairId, datetime: Timestamp, price: f64) -> Msg {
+        Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id,
+                datetime,
+                price,
+                ..Default::default()
+            }),
+            metadata: MsgMetaData {
+                correlation_id: Uuid::from_u128(id),
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn events_should_merge_files_ordered_by_time() {
+        let btc = write_file(
+            "stream_merge_btc.jsonl",
+            "{\"time\": 1, \"close\": 1.0}\n{\"time\": 3, \"close\": 3.0}\n",
+        );
+        let eth = write_file(
+            "stream_merge_eth.jsonl",
+            "{\"time\": 1, \"close\": 10.0}\n{\"time\": 2, \"close\": 20.0}\n",
+        );
+        let source = EventSource::Files(vec![
+            DataFile::new("BTC/USDT", btc),
+            DataFile::new("ETH/USDT", eth),
+        ]);
+        let actual: Vec<(PairId, Timestamp)> = source
+            .events()
+            .map(|msg| {
+                (
+                    msg.metadata.correlation_pair_id,
+                    msg.metadata.correlation_time,
+                )
+            })
+            .collect();
+        let expected = vec![
+            ("BTC/USDT", 1),
+            ("ETH/USDT", 1),
+            ("ETH/USDT", 2),
+            ("BTC/USDT", 3),
+            ("", 0),
+        ];
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn events_should_keep_ids_between_reads() {
+        let file = write_file(
+            "stream_ids.jsonl",
+            "{\"time\": 1, \"close\": 1.0}\n{\"time\": 2, \"close\": 2.0}\n",
+        );
+        let source = EventSource::Files(vec![DataFile::new("BTC/USDT", file)]);
+        let expected: Vec<MessageId> = source.events().map(|msg| msg.metadata.id).collect();
+        let actual: Vec<MessageId> = source.events().map(|msg| msg.metadata.id).collect();
+        assert_eq!(expected, actual)
+    }
+
+    #[test]
+    fn quotes_should_find_next_open_of_same_pair() {
+        let source = EventSource::Events(vec![
+            price_msg(0, "BTC/USDT", 0, 10.0),
+            price_msg(1, "ETH/USDT", 0, 1.0),
+            price_msg(2, "BTC/USDT", 1, 12.0),
+        ]);
+        let actual = source.quotes().get(Uuid::from_u128(0), 0).unwrap();
+        assert_eq!(Some(12.0), actual.next_open)
+    }
+
+    #[test]
+    fn quotes_should_find_first_quote_at_or_after_time() {
+        let source = EventSource::Events(vec![
+            price_msg(0, "BTC/USDT", 0, 10.0),
+            price_msg(1, "ETH/USDT", 5, 1.0),
+            price_msg(2, "BTC/USDT", 5, 12.0),
+        ]);
+        let actual = source.quotes().first_after("BTC/USDT", 3).unwrap();
+        assert_eq!(12.0, actual.price)
+    }
+
+    #[test]
+    fn tee_should_find_quotes_of_forwarded_events() {
+        let (sender, receiver) = channel::unbounded();
+        let (mut events, mut quotes) = tee(Box::new(receiver.into_iter()));
+        assert_eq!(None, quotes.get(Uuid::from_u128(0), 0));
+        sender.send(price_msg(0, "BTC/USDT", 0, 10.0)).unwrap();
+        events.next().unwrap();
+        assert_eq!(10.0, quotes.get(Uuid::from_u128(0), 0).unwrap().price)
+    }
+
+    #[async_std::test]
+    async fn receiver_should_stream_sent_messages() {
+        let (sender, receiver) = channel::unbounded();
+        sender.send(price_msg(0, "BTC/USDT", 0, 10.0)).unwrap();
+        drop(sender);
+        let actual: Vec<Msg> = receiver.event_stream().await.collect();
+        assert_eq!(vec![price_msg(0, "BTC/USDT", 0, 10.0)], actual)
+    }
+
+    #[test]
+    fn quotes_should_drop_earlier_quotes_of_streamed_files() {
+        let file = write_file(
+            "stream_evict.jsonl",
+            "{\"time\": 1, \"close\": 1.0}\n{\"time\": 2, \"close\": 2.0}\n{\"time\": 3, \"close\": 3.0}\n",
+        );
+        let source = EventSource::Files(vec![DataFile::new("BTC/USDT", file)]);
+        let ids: Vec<MessageId> = source.events().map(|msg| msg.metadata.id).collect();
+        let mut quotes = source.quotes();
+        quotes.get(ids[1], 2).unwrap();
+        assert_eq!(2, quotes.window.len());
+        assert_eq!(None, quotes.get(ids[0], 1))
+    }
+
+    #[test]
+    fn quotes_should_stop_searching_unknown_id_after_its_time() {
+        let file = write_file(
+            "stream_unknown.jsonl",
+            "{\"time\": 1, \"close\": 1.0}\n{\"time\": 2, \"close\": 2.0}\n{\"time\": 3, \"close\": 3.0}\n",
+        );
+        let source = EventSource::Files(vec![DataFile::new("BTC/USDT", file)]);
+        let ids: Vec<MessageId> = source.events().map(|msg| msg.metadata.id).collect();
+        let mut quotes = source.quotes();
+        assert_eq!(None, quotes.get(Uuid::from_u128(99), 1));
+        assert_eq!(2, quotes.window.len());
+        assert_eq!(3.0, quotes.get(ids[2], 3).unwrap().price)
+    }
+
+    #[test]
+    fn quotes_should_read_ahead_of_sparse_pair_only_up_to_lookahead() {
+        let mut events = vec![price_msg(0, "ETH/USDT", 0, 1.0)];
+        events.extend((1..=100).map(|time| price_msg(time, "BTC/USDT", time, 10.0)));
+        let mut quotes = QuoteCursor {
+            events: Box::new(events.into_iter()),
+            window: VecDeque::new(),
+            evict: true,
+            lookahead: 10,
+        };
+        let actual = quotes.get(Uuid::from_u128(0), 0).unwrap();
+        assert_eq!(None, actual.next_open);
+        assert_eq!(12, quotes.window.len());
+        assert_eq!(None, quotes.first_after("ETH/USDT", 1));
+        assert_eq!(13, quotes.window.len())
+    }
+}
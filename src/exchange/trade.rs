@@ -1,119 +1,373 @@
 use crate::messaging::{
-    message::MessageId, message::Msg, message::MsgData, message::Order, processor::Actor,
+    message::MessageId, message::Msg, message::MsgData, message::MsgKind, message::Order,
+    message::OrderUpdate, message::Rejection, message::Timestamp, processor::Actor,
+    processor::Subscriptions,
 };
 use anyhow::Result;
 use async_trait::async_trait;
 
-use super::{Asset, Exchange, MarketOrder, OrderType};
+use super::{
+    sizing::{PositionSizer, SizingPolicy},
+    split_pair_id, Amount, Assets, Fill, MarketOrder, OrderExecutor, OrderRejected, OrderState,
+    OrderType,
+};
 
 #[derive(Debug, PartialEq)]
 pub struct Trader<E>
 where
-    E: Exchange,
+    E: OrderExecutor,
 {
     pub exchange: E,
+    sizer: PositionSizer,
+    shorting: bool,
+    // orders which are not final yet, with their last reported state
+    pending: Vec<(MarketOrder, Fill)>,
+    // balances as fetched after the last order event
+    assets: Assets,
+}
+
+// Balances received from fills are compared with the fetched ones up to this
+// relative difference.
+const RECONCILE_TOLERANCE: f64 = 1e-9;
+
+// A fetched balance which differs from what the fills imply, e.g. because of a
+// missed fill or an unreported fee.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Discrepancy {
+    pub currency: String,
+    pub expected: Amount,
+    pub actual: Amount,
 }
 
 impl<E> Trader<E>
 where
-    E: Exchange,
+    E: OrderExecutor,
 {
     pub fn new(exchange: E) -> Self {
-        Trader { exchange }
+        Trader {
+            exchange,
+            sizer: PositionSizer::new(SizingPolicy::AllIn),
+            shorting: false,
+            pending: vec![],
+            assets: Assets {
+                ..Default::default()
+            },
+        }
+    }
+
+    pub fn with_sizing(mut self, policy: SizingPolicy) -> Self {
+        self.sizer = PositionSizer::new(policy);
+        self
+    }
+
+    pub fn with_shorting(mut self) -> Self {
+        self.shorting = true;
+        self
+    }
+
+    fn is_pending(&self, pair_id: &str) -> bool {
+        let (base, quote) = split_pair_id(pair_id);
+        self.pending
+            .iter()
+            .any(|(order, _)| order.base == base && order.quote == quote)
+    }
+
+    async fn execute(
+        &mut self,
+        base: &str,
+        quote: &str,
+        amount: Amount,
+        order_type: OrderType,
+        correlation_id: MessageId,
+        correlation_time: Timestamp,
+    ) -> Result<Vec<MsgData>> {
+        if amount <= 0.0 {
+            return Ok(vec![]);
+        }
+        let order = MarketOrder {
+            base: base.into(),
+            quote: quote.into(),
+            amount,
+            order_type,
+            correlation_id,
+            correlation_time,
+        };
+        let received = received_currency(&order).to_string();
+        let res = match self.exchange.place_market_order(&order).await {
+            Ok(fill) => {
+                let amount = fill.amount;
+                (self.update(order, fill), amount)
+            }
+            Err(err) => match err.downcast_ref::<OrderRejected>() {
+                Some(rejected) => (
+                    MsgData::Rejected(Rejection {
+                        base: order.base,
+                        quote: order.quote,
+                        amount: order.amount,
+                        reason: rejected.reason.clone(),
+                    }),
+                    0.0,
+                ),
+                None => return Err(err),
+            },
+        };
+        self.reconcile(vec![(received, res.1)]).await?;
+        Ok(vec![res.0])
+    }
+
+    async fn poll_orders(&mut self) -> Result<Vec<MsgData>> {
+        let mut res = vec![];
+        let mut received = vec![];
+        for (order, last) in self.pending.clone() {
+            let fill = self.exchange.poll_order(&last).await?;
+            if fill.state == last.state && fill.amount == last.amount {
+                continue;
+            }
+            if !last.state.can_become(fill.state) {
+                log::warn!(
+                    "ignoring order {} going from {:?} to {:?}",
+                    fill.order_id,
+                    last.state,
+                    fill.state
+                );
+                continue;
+            }
+            received.push((
+                received_currency(&order).to_string(),
+                fill.amount - last.amount,
+            ));
+            res.push(self.update(order, fill));
+        }
+        if !res.is_empty() {
+            self.reconcile(received).await?;
+        }
+        Ok(res)
+    }
+
+    // Fetches the balances after an order event and logs where they don't
+    // match the amounts received since the previous fetch.
+    async fn reconcile(&mut self, received: Vec<(String, Amount)>) -> Result<()> {
+        let assets = self.exchange.fetch_assets().await?;
+        for discrepancy in discrepancies(&self.assets, &assets, &received) {
+            log::warn!(
+                "{} balance is {} but fills imply {}",
+                discrepancy.currency,
+                discrepancy.actual,
+                discrepancy.expected
+            );
+        }
+        self.assets = assets;
+        Ok(())
+    }
+
+    fn update(&mut self, order: MarketOrder, fill: Fill) -> MsgData {
+        self.pending
+            .retain(|(_, last)| last.order_id != fill.order_id);
+        let progress = OrderUpdate {
+            order_id: fill.order_id,
+            base: order.base.clone(),
+            quote: order.quote.clone(),
+            amount: order.amount,
+            filled: fill.amount,
+            fee: fill.fee.clone(),
+        };
+        let res = match fill.state {
+            OrderState::Placed => MsgData::OrderPlaced(progress),
+            OrderState::PartiallyFilled => MsgData::OrderPartiallyFilled(progress),
+            OrderState::Filled => filled(fill.clone()),
+            OrderState::Rejected => MsgData::Rejected(Rejection {
+                base: order.base.clone(),
+                quote: order.quote.clone(),
+                amount: order.amount,
+                reason: "rejected after placement".into(),
+            }),
+            OrderState::Cancelled => MsgData::OrderCancelled(progress),
+        };
+        if !fill.state.is_final() {
+            self.pending.push((order, fill));
+        }
+        res
     }
 }
 
 #[async_trait]
 impl<E> Actor for Trader<E>
 where
-    E: Exchange + Send + Sync,
+    E: OrderExecutor + Send + Sync,
 {
     async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
         let res = match msg.data {
+            // Balances are not settled while an order of the pair is open.
+            MsgData::Buy | MsgData::Sell if self.is_pending(msg.metadata.correlation_pair_id) => {
+                log::info!(
+                    "skipping {:?} of {}, an order is still open",
+                    msg.data,
+                    msg.metadata.correlation_pair_id
+                );
+                vec![]
+            }
             MsgData::Buy => {
-                let assets = self.exchange.fetch_assets().await?;
-                execute(
-                    &mut self.exchange,
-                    assets.quote,
+                let (base, quote) = split_pair_id(msg.metadata.correlation_pair_id);
+                self.assets = self.exchange.fetch_assets().await?;
+                let amount = self.sizer.order_amount(
+                    OrderType::Buy,
+                    msg.metadata.correlation_pair_id,
+                    &self.assets,
+                    msg.metadata.correlation_price,
+                );
+                self.execute(
+                    base,
+                    quote,
+                    amount,
                     OrderType::Buy,
                     msg.metadata.correlation_id,
+                    msg.metadata.correlation_time,
                 )
                 .await?
             }
             MsgData::Sell => {
-                let assets = self.exchange.fetch_assets().await?;
-                execute(
-                    &mut self.exchange,
-                    assets.base,
+                let (base, quote) = split_pair_id(msg.metadata.correlation_pair_id);
+                self.assets = self.exchange.fetch_assets().await?;
+                let assets = &self.assets;
+                let is_flat = assets.amount(base) <= 0.0 && assets.loan(base) <= 0.0;
+                let amount = if self.shorting && is_flat {
+                    self.sizer.short_amount(
+                        msg.metadata.correlation_pair_id,
+                        assets,
+                        msg.metadata.correlation_price,
+                    )
+                } else {
+                    self.sizer.order_amount(
+                        OrderType::Sell,
+                        msg.metadata.correlation_pair_id,
+                        assets,
+                        msg.metadata.correlation_price,
+                    )
+                };
+                self.execute(
+                    base,
+                    quote,
+                    amount,
                     OrderType::Sell,
                     msg.metadata.correlation_id,
+                    msg.metadata.correlation_time,
                 )
                 .await?
             }
+            MsgData::LivePriceUpdated(ref price_updated) => {
+                self.sizer
+                    .observe(price_updated.pair_id, price_updated.price);
+                let mut res: Vec<MsgData> = self
+                    .exchange
+                    .poll_fills(msg.metadata.correlation_id, msg.metadata.correlation_time)
+                    .await?
+                    .into_iter()
+                    .map(filled)
+                    .collect();
+                res.extend(
+                    self.exchange
+                        .poll_liquidations(
+                            msg.metadata.correlation_id,
+                            msg.metadata.correlation_time,
+                        )
+                        .await?
+                        .into_iter()
+                        .map(MsgData::Liquidated),
+                );
+                res.extend(self.poll_orders().await?);
+                res
+            }
             _ => vec![],
         };
         Ok(res)
     }
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions::Only(vec![MsgKind::Buy, MsgKind::Sell, MsgKind::LivePriceUpdated])
+    }
 }
 
-async fn execute<'a, E>(
-    exchange: &'a mut E,
-    asset: Option<Asset>,
-    order_type: OrderType,
-    correlation_id: MessageId,
-) -> Result<Vec<MsgData>>
-where
-    E: Exchange,
-{
-    if let Some(asset) = asset {
-        if asset.amount > 0.0 {
-            let order = MarketOrder {
-                base: "BTC".into(),
-                quote: "USDT".into(),
-                amount: asset.amount,
-                order_type,
-                correlation_id,
-            };
-            return exchange.place_market_order(&order).await.map(|amount| {
-                match order.order_type {
-                    OrderType::Buy => vec![MsgData::Bought(Order {
-                        amount,
-                        quote: order.quote,
-                        base: order.base,
-                    })],
-                    OrderType::Sell => vec![MsgData::Sold(Order {
-                        amount,
-                        quote: order.quote,
-                        base: order.base,
-                    })],
-                }
-            });
+fn received_currency(order: &MarketOrder) -> &str {
+    match order.order_type {
+        OrderType::Buy => &order.base,
+        OrderType::Sell => &order.quote,
+    }
+}
+
+// Balances net of loans, so repaying a loan with a fill is no discrepancy.
+fn discrepancies(
+    before: &Assets,
+    after: &Assets,
+    received: &[(String, Amount)],
+) -> Vec<Discrepancy> {
+    let net = |assets: &Assets, currency: &str| assets.amount(currency) - assets.loan(currency);
+    let mut expected: Vec<(&str, Amount)> = vec![];
+    for (currency, amount) in received {
+        match expected.iter_mut().find(|(name, _)| name == currency) {
+            Some((_, total)) => *total += amount,
+            None => expected.push((currency, net(before, currency) + amount)),
         }
     }
-    Ok(vec![])
+    expected
+        .into_iter()
+        .filter_map(|(currency, expected)| {
+            let actual = net(after, currency);
+            let tolerance = RECONCILE_TOLERANCE * expected.abs().max(1.0);
+            ((actual - expected).abs() > tolerance).then(|| Discrepancy {
+                currency: currency.into(),
+                expected,
+                actual,
+            })
+        })
+        .collect()
+}
+
+fn filled(fill: Fill) -> MsgData {
+    let order = Order {
+        base: fill.base,
+        quote: fill.quote,
+        amount: fill.amount,
+        price: fill.price,
+        fee: fill.fee,
+    };
+    match fill.order_type {
+        OrderType::Buy => MsgData::Bought(order),
+        OrderType::Sell => MsgData::Sold(order),
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::exchange::{tests::MockExchange, Assets};
+    use crate::exchange::{
+        simulation::ExchangeSimulation, sizing::SizingPolicy, tests::MockExchange, Asset, Assets,
+        ExchangeOptions, Fill,
+    };
+    use crate::messaging::message::{Fee, Liquidation, Rejection};
 
     use super::*;
-    use crate::messaging::message::MsgMetaData;
+    use crate::messaging::message::{MsgMetaData, PriceUpdated};
     use pretty_assertions::assert_eq;
     use uuid::Uuid;
 
+    fn signal(data: MsgData) -> Msg {
+        Msg {
+            data,
+            metadata: MsgMetaData {
+                correlation_pair_id: "BTC/USDT",
+                ..Default::default()
+            },
+        }
+    }
+
     #[async_std::test]
     async fn should_buy_max_amount_of_quote() {
-        let exchange = MockExchange::new(Assets {
-            quote: Some(Asset {
-                amount: 40.0,
-                name: "USDT".into(),
-            }),
-            base: None,
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 40.0,
+            name: "USDT".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
 
         let expected = vec![MarketOrder {
             base: "BTC".into(),
@@ -128,16 +382,13 @@ mod tests {
 
     #[async_std::test]
     async fn should_buy_different_max_amount_of_quote() {
-        let exchange = MockExchange::new(Assets {
-            quote: Some(Asset {
-                amount: 50.0,
-                name: "USDT".into(),
-            }),
-            base: None,
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 50.0,
+            name: "USDT".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
 
         let expected = vec![MarketOrder {
             base: "BTC".into(),
@@ -157,7 +408,7 @@ mod tests {
         });
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
 
         let expected: Vec<MarketOrder> = vec![];
         let actual = &trader.exchange.recorded_orders;
@@ -166,16 +417,13 @@ mod tests {
 
     #[async_std::test]
     async fn should_not_buy_quote_when_zero() {
-        let exchange = MockExchange::new(Assets {
-            quote: Some(Asset {
-                amount: 0.0,
-                name: "USDT".into(),
-            }),
-            base: None,
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0,
+            name: "USDT".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
 
         let expected: Vec<MarketOrder> = vec![];
         let actual = &trader.exchange.recorded_orders;
@@ -184,37 +432,32 @@ mod tests {
 
     #[async_std::test]
     async fn should_emit_bought_order_event() {
-        let exchange = MockExchange::new(Assets {
-            quote: Some(Asset {
-                amount: 50.0,
-                name: "USDT".into(),
-            }),
-            base: None,
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 50.0,
+            name: "USDT".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        let actual = trader.act(&Msg::with_data(MsgData::Buy)).await.unwrap();
+        let actual = trader.act(&signal(MsgData::Buy)).await.unwrap();
 
         let expected = vec![MsgData::Bought(Order {
             base: "BTC".into(),
             quote: "USDT".into(),
             amount: 45.0,
+            ..Default::default()
         })];
         assert_eq!(expected, actual)
     }
 
     #[async_std::test]
     async fn should_sell_max_amount_of_base() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.0000001,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0000001,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Sell)).await.unwrap();
+        trader.act(&signal(MsgData::Sell)).await.unwrap();
 
         let expected = vec![MarketOrder {
             base: "BTC".into(),
@@ -229,16 +472,13 @@ mod tests {
 
     #[async_std::test]
     async fn should_sell_different_max_amount_of_base() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.0002,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0002,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Sell)).await.unwrap();
+        trader.act(&signal(MsgData::Sell)).await.unwrap();
 
         let expected = vec![MarketOrder {
             base: "BTC".into(),
@@ -258,7 +498,7 @@ mod tests {
         });
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Sell)).await.unwrap();
+        trader.act(&signal(MsgData::Sell)).await.unwrap();
 
         let expected: Vec<MarketOrder> = vec![];
         let actual = &trader.exchange.recorded_orders;
@@ -267,16 +507,13 @@ mod tests {
 
     #[async_std::test]
     async fn should_not_sell_when_base_zero() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.0,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        trader.act(&Msg::with_data(MsgData::Sell)).await.unwrap();
+        trader.act(&signal(MsgData::Sell)).await.unwrap();
 
         let expected: Vec<MarketOrder> = vec![];
         let actual = &trader.exchange.recorded_orders;
@@ -285,13 +522,10 @@ mod tests {
 
     #[async_std::test]
     async fn should_set_correlation_id() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.0002,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0002,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
         let uuid = Uuid::from_u128(0);
 
@@ -300,6 +534,7 @@ mod tests {
                 data: MsgData::Sell,
                 metadata: MsgMetaData {
                     correlation_id: uuid,
+                    correlation_pair_id: "BTC/USDT",
                     ..Default::default()
                 },
             })
@@ -320,13 +555,10 @@ mod tests {
 
     #[async_std::test]
     async fn should_set_different_correlation_id() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 0.0002,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 0.0002,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
         let uuid = Uuid::from_u128(1);
 
@@ -335,6 +567,7 @@ mod tests {
                 data: MsgData::Sell,
                 metadata: MsgMetaData {
                     correlation_id: uuid,
+                    correlation_pair_id: "BTC/USDT",
                     ..Default::default()
                 },
             })
@@ -355,22 +588,564 @@ mod tests {
 
     #[async_std::test]
     async fn should_emit_sold_order_event() {
-        let exchange = MockExchange::new(Assets {
-            quote: None,
-            base: Some(Asset {
-                amount: 20.0,
-                name: "BTC".into(),
-            }),
-        });
+        let exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 20.0,
+            name: "BTC".into(),
+        }]));
         let mut trader = Trader::new(exchange);
 
-        let actual = trader.act(&Msg::with_data(MsgData::Sell)).await.unwrap();
+        let actual = trader.act(&signal(MsgData::Sell)).await.unwrap();
 
         let expected = vec![MsgData::Sold(Order {
             base: "BTC".into(),
             quote: "USDT".into(),
             amount: 18.0,
+            ..Default::default()
+        })];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn should_emit_filled_limit_orders_on_price_update() {
+        let mut exchange = MockExchange::new(Assets {
+            ..Default::default()
+        });
+        exchange.fills = vec![
+            Fill {
+                order_id: Uuid::from_u128(1),
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                order_type: OrderType::Buy,
+                amount: 2.0,
+                price: 0.0,
+                fee: Fee::default(),
+                state: OrderState::Filled,
+            },
+            Fill {
+                order_id: Uuid::from_u128(2),
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                order_type: OrderType::Sell,
+                amount: 30.0,
+                price: 15.0,
+                fee: Fee {
+                    currency: "USDT".into(),
+                    amount: 0.3,
+                },
+                state: OrderState::Filled,
+            },
+        ];
+        let mut trader = Trader::new(exchange);
+
+        let actual = trader
+            .act(&Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+                ..Default::default()
+            })))
+            .await
+            .unwrap();
+
+        let expected = vec![
+            MsgData::Bought(Order {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                amount: 2.0,
+                ..Default::default()
+            }),
+            MsgData::Sold(Order {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                amount: 30.0,
+                price: 15.0,
+                fee: Fee {
+                    currency: "USDT".into(),
+                    amount: 0.3,
+                },
+            }),
+        ];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn should_route_order_to_pair_of_triggering_price_update() {
+        let exchange = MockExchange::new(Assets::from(vec![
+            Asset {
+                amount: 30.0,
+                name: "USDT".into(),
+            },
+            Asset {
+                amount: 2.0,
+                name: "BTC".into(),
+            },
+        ]));
+        let mut trader = Trader::new(exchange);
+
+        trader
+            .act(&Msg {
+                data: MsgData::Buy,
+                metadata: MsgMetaData {
+                    correlation_pair_id: "ETH/BTC",
+                    ..Default::default()
+                },
+            })
+            .await
+            .unwrap();
+
+        let expected = vec![MarketOrder {
+            base: "ETH".into(),
+            quote: "BTC".into(),
+            amount: 2.0,
+            order_type: OrderType::Buy,
+            ..Default::default()
+        }];
+        let actual = &trader.exchange.recorded_orders;
+        assert_eq!(&expected, actual)
+    }
+
+    fn priced_signal(data: MsgData, price: f64) -> Msg {
+        Msg {
+            data,
+            metadata: MsgMetaData {
+                correlation_pair_id: "BTC/USDT",
+                correlation_price: price,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn new_sizing_exchange() -> MockExchange {
+        MockExchange::new(Assets::from(vec![
+            Asset {
+                amount: 60.0,
+                name: "USDT".into(),
+            },
+            Asset {
+                amount: 4.0,
+                name: "BTC".into(),
+            },
+        ]))
+    }
+
+    #[async_std::test]
+    async fn should_scale_into_position_with_fixed_notional() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::FixedNotional(25.0));
+
+        trader
+            .act(&priced_signal(MsgData::Buy, 10.0))
+            .await
+            .unwrap();
+
+        let actual: Vec<f64> = trader
+            .exchange
+            .recorded_orders
+            .iter()
+            .map(|order| order.amount)
+            .collect();
+        assert_eq!(vec![25.0], actual)
+    }
+
+    #[async_std::test]
+    async fn should_partially_reduce_position_with_fixed_notional() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::FixedNotional(25.0));
+
+        trader
+            .act(&priced_signal(MsgData::Sell, 10.0))
+            .await
+            .unwrap();
+
+        let expected = vec![MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 2.5,
+            order_type: OrderType::Sell,
+            ..Default::default()
+        }];
+        assert_eq!(expected, trader.exchange.recorded_orders)
+    }
+
+    #[async_std::test]
+    async fn should_buy_fixed_fraction_of_equity() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::FixedFraction(0.5));
+
+        trader
+            .act(&priced_signal(MsgData::Buy, 10.0))
+            .await
+            .unwrap();
+
+        assert_eq!(50.0, trader.exchange.recorded_orders[0].amount)
+    }
+
+    #[async_std::test]
+    async fn should_size_by_volatility_of_observed_price_updates() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::VolatilityTarget {
+                target: 0.05,
+                window: 2,
+            });
+        for price in [10.0, 10.0 * 0.2_f64.exp(), 10.0] {
+            trader
+                .act(&Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+                    pair_id: "BTC/USDT",
+                    price,
+                    ..Default::default()
+                })))
+                .await
+                .unwrap();
+        }
+
+        trader
+            .act(&priced_signal(MsgData::Buy, 10.0))
+            .await
+            .unwrap();
+
+        let expected = 100.0 * 0.05 / 0.08_f64.sqrt();
+        assert!((trader.exchange.recorded_orders[0].amount - expected).abs() < 1e-9)
+    }
+
+    #[async_std::test]
+    async fn should_buy_fractional_kelly_share_of_equity() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::FractionalKelly {
+                fraction: 0.5,
+                win_rate: 0.6,
+                payoff_ratio: 2.0,
+            });
+
+        trader
+            .act(&priced_signal(MsgData::Buy, 10.0))
+            .await
+            .unwrap();
+
+        assert_eq!(20.0, trader.exchange.recorded_orders[0].amount)
+    }
+
+    #[async_std::test]
+    async fn should_not_order_when_policy_sizes_to_zero() {
+        let mut trader =
+            Trader::new(new_sizing_exchange()).with_sizing(SizingPolicy::FractionalKelly {
+                fraction: 0.5,
+                win_rate: 0.2,
+                payoff_ratio: 1.0,
+            });
+
+        trader
+            .act(&priced_signal(MsgData::Buy, 10.0))
+            .await
+            .unwrap();
+
+        assert_eq!(Vec::<MarketOrder>::new(), trader.exchange.recorded_orders)
+    }
+
+    #[async_std::test]
+    async fn should_emit_rejection_if_exchange_rejects_order() {
+        let mut exchange = MockExchange::new(Assets::from(vec![Asset {
+            amount: 5.0,
+            name: "USDT".into(),
+        }]));
+        exchange.rejection = Some(OrderRejected::new("notional below minimum"));
+        let mut trader = Trader::new(exchange);
+
+        let actual = trader.act(&signal(MsgData::Buy)).await.unwrap();
+
+        let expected = vec![MsgData::Rejected(Rejection {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 5.0,
+            reason: "notional below minimum".into(),
+        })];
+        assert_eq!(expected, actual)
+    }
+
+    #[async_std::test]
+    async fn should_emit_rejection_if_simulation_cannot_fund_order() {
+        let price = Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id: "BTC/USDT",
+                price: 10.0,
+                ..Default::default()
+            }),
+            metadata: MsgMetaData {
+                correlation_id: Uuid::from_u128(1),
+                correlation_price: 10.0,
+                correlation_pair_id: "BTC/USDT",
+                ..Default::default()
+            },
+        };
+        let exchange = ExchangeSimulation::new(
+            vec![price.clone()],
+            Assets::from(vec![Asset {
+                amount: 100.0,
+                name: "USDT".into(),
+            }]),
+            ExchangeOptions {
+                ..Default::default()
+            },
+        );
+        // a short needs margin, which the spot account doesn't have
+        let mut trader = Trader::new(exchange).with_shorting();
+
+        let actual = trader
+            .act(&Msg {
+                data: MsgData::Sell,
+                ..price
+            })
+            .await
+            .unwrap();
+
+        let expected = vec![MsgData::Rejected(Rejection {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 10.0,
+            reason: "insufficient BTC".into(),
         })];
         assert_eq!(expected, actual)
     }
+
+    fn usdt_exchange(amount: f64) -> MockExchange {
+        MockExchange::new(Assets::from(vec![Asset {
+            amount,
+            name: "USDT".into(),
+        }]))
+    }
+
+    #[async_std::test]
+    async fn should_not_sell_when_flat_without_shorting() {
+        let mut trader = Trader::new(usdt_exchange(40.0));
+
+        trader
+            .act(&priced_signal(MsgData::Sell, 10.0))
+            .await
+            .unwrap();
+
+        assert_eq!(Vec::<MarketOrder>::new(), trader.exchange.recorded_orders)
+    }
+
+    #[async_std::test]
+    async fn should_open_short_on_sell_when_flat() {
+        let mut trader = Trader::new(usdt_exchange(40.0)).with_shorting();
+
+        trader
+            .act(&priced_signal(MsgData::Sell, 10.0))
+            .await
+            .unwrap();
+
+        let expected = vec![MarketOrder {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 4.0,
+            order_type: OrderType::Sell,
+            ..Default::default()
+        }];
+        assert_eq!(expected, trader.exchange.recorded_orders)
+    }
+
+    #[async_std::test]
+    async fn should_not_add_to_short_on_sell() {
+        let mut exchange = usdt_exchange(80.0);
+        exchange.assets.borrow("BTC", 4.0);
+        exchange.assets.debit("BTC", 4.0);
+        let mut trader = Trader::new(exchange).with_shorting();
+
+        trader
+            .act(&priced_signal(MsgData::Sell, 10.0))
+            .await
+            .unwrap();
+
+        assert_eq!(Vec::<MarketOrder>::new(), trader.exchange.recorded_orders)
+    }
+
+    #[async_std::test]
+    async fn should_emit_liquidations_on_price_update() {
+        let mut exchange = usdt_exchange(0.0);
+        exchange.liquidations = vec![Liquidation {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: -4.0,
+            price: 19.0,
+            equity: 4.0,
+        }];
+        let mut trader = Trader::new(exchange);
+
+        let actual = trader
+            .act(&Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+                ..Default::default()
+            })))
+            .await
+            .unwrap();
+
+        let expected = vec![MsgData::Liquidated(Liquidation {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: -4.0,
+            price: 19.0,
+            equity: 4.0,
+        })];
+        assert_eq!(expected, actual)
+    }
+
+    fn pending_exchange() -> MockExchange {
+        let mut exchange = usdt_exchange(50.0);
+        exchange.placed_state = OrderState::Placed;
+        exchange
+    }
+
+    fn price_update() -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            ..Default::default()
+        }))
+    }
+
+    fn order_update(state: OrderState, amount: f64) -> Fill {
+        Fill {
+            order_id: Uuid::from_u128(1),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            order_type: OrderType::Buy,
+            amount,
+            price: 0.0,
+            fee: Fee::default(),
+            state,
+        }
+    }
+
+    fn progress(filled: f64) -> OrderUpdate {
+        OrderUpdate {
+            order_id: Uuid::from_u128(1),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount: 50.0,
+            filled,
+            ..Default::default()
+        }
+    }
+
+    #[async_std::test]
+    async fn should_emit_placed_order_when_not_filled_right_away() {
+        let mut trader = Trader::new(pending_exchange());
+
+        let actual = trader.act(&signal(MsgData::Buy)).await.unwrap();
+
+        assert_eq!(vec![MsgData::OrderPlaced(progress(0.0))], actual)
+    }
+
+    #[async_std::test]
+    async fn should_emit_order_progress_on_price_update() {
+        let mut exchange = pending_exchange();
+        exchange.order_updates = vec![
+            order_update(OrderState::PartiallyFilled, 2.0),
+            order_update(OrderState::Filled, 4.5),
+        ];
+        let mut trader = Trader::new(exchange);
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
+
+        let partially_filled = trader.act(&price_update()).await.unwrap();
+        let filled = trader.act(&price_update()).await.unwrap();
+        let after_filled = trader.act(&price_update()).await.unwrap();
+
+        assert_eq!(
+            vec![MsgData::OrderPartiallyFilled(progress(2.0))],
+            partially_filled
+        );
+        assert_eq!(
+            vec![MsgData::Bought(Order {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                amount: 4.5,
+                ..Default::default()
+            })],
+            filled
+        );
+        assert_eq!(Vec::<MsgData>::new(), after_filled)
+    }
+
+    #[async_std::test]
+    async fn should_not_emit_unchanged_order_state() {
+        let mut trader = Trader::new(pending_exchange());
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
+
+        let actual = trader.act(&price_update()).await.unwrap();
+
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn should_emit_cancelled_order_with_partial_fill() {
+        let mut exchange = pending_exchange();
+        exchange.order_updates = vec![order_update(OrderState::Cancelled, 1.0)];
+        let mut trader = Trader::new(exchange);
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
+
+        let actual = trader.act(&price_update()).await.unwrap();
+
+        assert_eq!(vec![MsgData::OrderCancelled(progress(1.0))], actual)
+    }
+
+    #[async_std::test]
+    async fn should_ignore_order_going_back_to_earlier_state() {
+        let mut exchange = pending_exchange();
+        exchange.order_updates = vec![
+            order_update(OrderState::PartiallyFilled, 2.0),
+            order_update(OrderState::Placed, 0.0),
+        ];
+        let mut trader = Trader::new(exchange);
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
+        trader.act(&price_update()).await.unwrap();
+
+        let actual = trader.act(&price_update()).await.unwrap();
+
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn should_not_place_order_while_order_of_pair_is_open() {
+        let mut trader = Trader::new(pending_exchange());
+
+        trader.act(&signal(MsgData::Buy)).await.unwrap();
+        let actual = trader.act(&signal(MsgData::Sell)).await.unwrap();
+
+        assert_eq!(Vec::<MsgData>::new(), actual);
+        assert_eq!(1, trader.exchange.recorded_orders.len())
+    }
+
+    fn btc_usdt(btc: f64, usdt: f64) -> Assets {
+        Assets::from(vec![
+            Asset {
+                amount: btc,
+                name: "BTC".into(),
+            },
+            Asset {
+                amount: usdt,
+                name: "USDT".into(),
+            },
+        ])
+    }
+
+    #[test]
+    fn discrepancies_should_accept_balances_implied_by_fills() {
+        let actual = discrepancies(
+            &btc_usdt(1.0, 50.0),
+            &btc_usdt(1.5, 0.0),
+            &[("BTC".into(), 0.2), ("BTC".into(), 0.3)],
+        );
+        assert_eq!(Vec::<Discrepancy>::new(), actual)
+    }
+
+    #[test]
+    fn discrepancies_should_report_balance_differing_from_fills() {
+        let actual = discrepancies(
+            &btc_usdt(1.0, 50.0),
+            &btc_usdt(1.2, 0.0),
+            &[("BTC".into(), 0.5)],
+        );
+        assert_eq!(
+            vec![Discrepancy {
+                currency: "BTC".into(),
+                expected: 1.5,
+                actual: 1.2,
+            }],
+            actual
+        )
+    }
 }
@@ -1,10 +1,27 @@
+use anyhow::{anyhow, bail, Result};
 use async_std;
+use chrono::{NaiveDate, TimeZone, Utc};
 use crossbeam::channel::unbounded;
-use exchange::{simulation::ExchangeSimulation, trade::Trader, Asset, Exchange, ExchangeOptions};
-use messaging::processor::ActorChain;
+use exchange::{
+    download::{CandleRange, Downloader},
+    feed::{Channel, LiveFeed, OKX_PUBLIC_URL},
+    fees::FeeSchedule,
+    loader::DataFile,
+    paper::PaperExchange,
+    simulation::ExchangeSimulation,
+    trade::Trader,
+    Asset, Assets, ExchangeOptions, Subscription,
+};
+use messaging::{message::Timestamp, processor::ActorChain};
+use std::{env, io, time::Duration};
 use strategy::{crossover::Crossover, sliding_average::SlidingAverage};
-use tools::{time::TimeProviderImpl, uuid::UuidProvider};
-use view::graph;
+use tools::{
+    middleware::{RateLimit, Retry, Timeout},
+    networking::Client,
+    time::TimeProviderImpl,
+    uuid::{Uuid, UuidProvider},
+};
+use view::{dump::MessageDump, graph::Graph, summary::Summary};
 
 mod exchange;
 mod messaging;
@@ -12,9 +29,26 @@ mod strategy;
 mod tools;
 mod view;
 
+const USAGE: &str = "usage: buyTheBoop [feed <BASE/QUOTE> [<bar>]]
+       buyTheBoop download <BASE/QUOTE> <bar> <from> <to> <file>
+       buyTheBoop paper <BASE/QUOTE> <state file>";
+
 #[async_std::main]
-async fn main() {
+async fn main() -> Result<()> {
     tools::logging::setup();
+    let args: Vec<String> = env::args().skip(1).collect();
+    let args: Vec<&str> = args.iter().map(String::as_str).collect();
+    match args.as_slice() {
+        [] => backtest().await,
+        ["feed", pair] => feed(pair, Channel::Tickers).await,
+        ["feed", pair, bar] => feed(pair, Channel::Candles(bar.to_string())).await,
+        ["download", pair, bar, from, to, path] => download(pair, bar, from, to, path).await,
+        ["paper", pair, state_path] => paper(pair, state_path).await,
+        _ => bail!(USAGE),
+    }
+}
+
+async fn backtest() -> Result<()> {
     let exchange = ExchangeSimulation::new_from_file(
         "example_data_5min_interval.json",
         Asset {
@@ -22,24 +56,131 @@ async fn main() {
             name: "USDT".into(),
         },
         ExchangeOptions {
-            fee: 0.0008,
+            fees: FeeSchedule::flat(0.0008),
             ..Default::default()
         },
-    );
+    )?;
 
-    // TODO: Move exchange into ActorChain as source
-    let (sender, in_receiver) = unbounded();
-    for event in exchange.event_stream().await {
-        sender.send(event).expect("open channel");
-    }
+    ActorChain::from_source(TimeProviderImpl::new(), UuidProvider::new(), &exchange)
+        .await
+        .add(SlidingAverage::new(300_000, 1140 * 60 * 1000))
+        .add(Crossover::new(0.005))
+        .add(Trader::new(exchange))
+        .sink(Graph::new("result.svg", 0.008))
+        .sink(MessageDump::create("messages.jsonl")?)
+        .sink(Summary::new(io::stdout()))
+        .run()
+        .await;
+    Ok(())
+}
+
+// Runs the strategy on live okx prices and prints every message, nothing is
+// traded.
+async fn feed(pair: &str, channel: Channel) -> Result<()> {
+    let (sender, receiver) = unbounded();
+    LiveFeed::new(
+        OKX_PUBLIC_URL,
+        vec![subscription(pair)?],
+        TimeProviderImpl::new(),
+        UuidProvider::new(),
+    )
+    .with_channel(channel)
+    .start(sender);
 
-    let out_r = ActorChain::new(TimeProviderImpl::new(), UuidProvider::new(), in_receiver)
+    ActorChain::from_source(TimeProviderImpl::new(), UuidProvider::new(), &receiver)
+        .await
+        .add(SlidingAverage::new(300_000, 1140 * 60 * 1000))
+        .add(Crossover::new(0.005))
+        .sink(MessageDump::new(io::stdout()))
+        .run()
+        .await;
+    Ok(())
+}
+
+// Trades the strategy on live okx prices with simulated fills. A session started
+// again with the same state file continues with its balances.
+async fn paper(pair: &str, state_path: &str) -> Result<()> {
+    let (sender, receiver) = unbounded();
+    LiveFeed::new(
+        OKX_PUBLIC_URL,
+        vec![subscription(pair)?],
+        TimeProviderImpl::new(),
+        UuidProvider::new(),
+    )
+    .start(sender);
+    let exchange = PaperExchange::new(
+        receiver,
+        state_path,
+        Assets::from(vec![Asset {
+            amount: 1000.0,
+            name: "USDT".into(),
+        }]),
+        ExchangeOptions {
+            fees: FeeSchedule::flat(0.0008),
+            ..Default::default()
+        },
+        TimeProviderImpl::new(),
+    )?;
+    log::info!(
+        "{}: continuing after {} trades",
+        state_path,
+        exchange.trades().len()
+    );
+
+    ActorChain::from_source(TimeProviderImpl::new(), UuidProvider::new(), &exchange)
+        .await
         .add(SlidingAverage::new(300_000, 1140 * 60 * 1000))
         .add(Crossover::new(0.005))
         .add(Trader::new(exchange))
-        .start()
+        .sink(MessageDump::create("paper_messages.jsonl")?)
+        .sink(Summary::new(io::stdout()))
+        .run()
         .await;
+    Ok(())
+}
+
+// Downloads okx candles from the start of one day up to the start of another
+// into the file, continuing after the candles it already has.
+async fn download(pair: &str, bar: &str, from: &str, to: &str, path: &str) -> Result<()> {
+    let (base, quote) = split_pair(pair)?;
+    let range = CandleRange {
+        base: base.into(),
+        quote: quote.into(),
+        bar: bar.into(),
+        start: day_start(from)?,
+        end: day_start(to)?,
+    };
+    // pair ids are 'static, the one pair is leaked once
+    let file = DataFile::new(Box::leak(pair.to_string().into_boxed_str()), path);
+    // okx allows 20 history requests per 2 seconds
+    let client = Retry::new(RateLimit::new(
+        Timeout::new(Client::new(), Duration::from_secs(10)),
+        10.0,
+    )?)
+    .with_max_retries(5);
+    let added = Downloader::new(client).download(&range, &file).await?;
+    println!("added {} candles to {}", added, path);
+    Ok(())
+}
+
+fn day_start(date: &str) -> Result<Timestamp> {
+    let day = NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|err| anyhow!("{} is not a date like 2021-12-31: {}", date, err))?;
+    let midnight = day.and_hms_opt(0, 0, 0).expect("midnight is a valid time");
+    Timestamp::try_from(Utc.from_utc_datetime(&midnight).timestamp_millis())
+        .map_err(|_| anyhow!("{} is before 1970", date))
+}
+
+fn split_pair(pair: &str) -> Result<(&str, &str)> {
+    pair.split_once('/')
+        .ok_or_else(|| anyhow!("{} is not a pair like BTC/USDT", pair))
+}
 
-    // TODO: Move graph into ActorChain
-    graph::draw_graph(out_r, 0.008);
+fn subscription(pair: &str) -> Result<Subscription> {
+    let (base, quote) = split_pair(pair)?;
+    Ok(Subscription {
+        id: Uuid::new_v4(),
+        bid_currency: base.into(),
+        ask_currency: quote.into(),
+    })
 }
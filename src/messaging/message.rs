@@ -1,3 +1,4 @@
+use serde::Serialize;
 use uuid::Uuid;
 
 pub type Timestamp = u128;
@@ -6,42 +7,158 @@ pub type Price = f64;
 pub type PairId = &'static str;
 pub type MessageId = Uuid;
 
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Copy, Clone, Default, Serialize)]
+pub struct Candle {
+    pub open: Price,
+    pub high: Price,
+    pub low: Price,
+    pub close: Price,
+    pub volume_base: f64,
+    pub volume_quote: f64,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize)]
 pub struct PriceUpdated {
     pub pair_id: PairId,
     pub datetime: Timestamp,
     pub price: Price,
+    pub candle: Option<Candle>,
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Fee {
+    pub currency: String,
+    pub amount: f64,
 }
 
-#[derive(PartialEq, Debug, Clone)]
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
 pub struct Order {
     pub base: String,
     pub quote: String,
     pub amount: f64,
+    pub price: Price,
+    pub fee: Fee,
+}
+
+impl Order {
+    // The fee if paid in the base and if paid in the quote.
+    pub fn base_and_quote_fee(&self) -> (f64, f64) {
+        if self.fee.currency == self.base {
+            (self.fee.amount, 0.0)
+        } else if self.fee.currency == self.quote {
+            (0.0, self.fee.amount)
+        } else {
+            (0.0, 0.0)
+        }
+    }
+
+    // The base a sell gave up for its amount of quote, fees included. Unknown
+    // without a price.
+    pub fn sold_base(&self) -> Option<f64> {
+        if self.price <= 0.0 {
+            return None;
+        }
+        let (base_fee, quote_fee) = self.base_and_quote_fee();
+        Some((self.amount + quote_fee) / self.price + base_fee)
+    }
+}
+
+// Progress of an order which is not filled yet, amounts as ordered and as
+// received so far.
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct OrderUpdate {
+    pub order_id: Uuid,
+    pub base: String,
+    pub quote: String,
+    pub amount: f64,
+    pub filled: f64,
+    pub fee: Fee,
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize)]
+pub struct Rejection {
+    pub base: String,
+    pub quote: String,
+    pub amount: f64,
+    pub reason: String,
+}
+
+#[derive(PartialEq, Debug, Clone, Default, Serialize)]
+pub struct Liquidation {
+    pub base: String,
+    pub quote: String,
+    pub amount: f64,
+    pub price: Price,
+    pub equity: f64,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub enum MsgData {
     LivePriceUpdated(PriceUpdated),
     AveragePriceUpdated(PriceUpdated),
+    CandleClosed(PriceUpdated),
+    OrderPlaced(OrderUpdate),
+    OrderPartiallyFilled(OrderUpdate),
+    OrderCancelled(OrderUpdate),
     Bought(Order),
     Sold(Order),
+    Rejected(Rejection),
+    Liquidated(Liquidation),
     Buy,
     Sell,
     Shutdown,
 }
 
-#[derive(Debug, Default, PartialEq, Clone)]
+// The variant of a MsgData without its content, e.g. to subscribe to.
+#[derive(Debug, PartialEq, Eq, Hash, Copy, Clone)]
+pub enum MsgKind {
+    LivePriceUpdated,
+    AveragePriceUpdated,
+    CandleClosed,
+    OrderPlaced,
+    OrderPartiallyFilled,
+    OrderCancelled,
+    Bought,
+    Sold,
+    Rejected,
+    Liquidated,
+    Buy,
+    Sell,
+    Shutdown,
+}
+
+impl MsgData {
+    pub fn kind(&self) -> MsgKind {
+        match self {
+            MsgData::LivePriceUpdated(_) => MsgKind::LivePriceUpdated,
+            MsgData::AveragePriceUpdated(_) => MsgKind::AveragePriceUpdated,
+            MsgData::CandleClosed(_) => MsgKind::CandleClosed,
+            MsgData::OrderPlaced(_) => MsgKind::OrderPlaced,
+            MsgData::OrderPartiallyFilled(_) => MsgKind::OrderPartiallyFilled,
+            MsgData::OrderCancelled(_) => MsgKind::OrderCancelled,
+            MsgData::Bought(_) => MsgKind::Bought,
+            MsgData::Sold(_) => MsgKind::Sold,
+            MsgData::Rejected(_) => MsgKind::Rejected,
+            MsgData::Liquidated(_) => MsgKind::Liquidated,
+            MsgData::Buy => MsgKind::Buy,
+            MsgData::Sell => MsgKind::Sell,
+            MsgData::Shutdown => MsgKind::Shutdown,
+        }
+    }
+}
+
+#[derive(Debug, Default, PartialEq, Clone, Serialize)]
 pub struct MsgMetaData {
     pub id: MessageId,
     pub correlation_time: Timestamp,
     pub creation_time: AccurateTimestamp,
     pub correlation_id: MessageId,
     pub correlation_price: Price,
+    pub correlation_pair_id: PairId,
     pub causation_id: MessageId,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+#[derive(Debug, PartialEq, Clone, Serialize)]
 pub struct Msg {
     pub data: MsgData,
     pub metadata: MsgMetaData,
@@ -1,10 +1,19 @@
-use crate::messaging::message::{Msg, MsgData, MsgMetaData};
+use crate::exchange::MarketDataSource;
+use crate::messaging::message::{MessageId, Msg, MsgData, MsgKind, MsgMetaData, Timestamp};
 use crate::tools::{time::TimeProvider, uuid::IdProvider};
 use anyhow::Result;
 use async_std::task;
 use async_trait::async_trait;
 use crossbeam::channel;
-use crossbeam::channel::unbounded;
+use crossbeam::channel::{bounded, unbounded};
+use std::collections::{HashMap, VecDeque};
+use std::thread;
+
+// Number of source events read ahead of the first actor.
+const SOURCE_BUFFER: usize = 1024;
+
+type Source = Box<dyn Iterator<Item = Msg> + Send>;
+type Sinks = Vec<Box<dyn Sink + Send>>;
 
 struct Processor<I, T>
 where
@@ -13,7 +22,8 @@ where
 {
     input: channel::Receiver<Msg>,
     output: channel::Sender<Msg>,
-    is_filter: bool,
+    subscriptions: Subscriptions,
+    routing: Routing,
     actor: Box<dyn Actor + Send>,
     id_provider: I,
     time_provider: T,
@@ -31,10 +41,23 @@ where
                 self.output.send(e).expect("open channel");
                 break;
             };
-            let mut msgs: Vec<Msg> = self
-                .actor
-                .act(&e)
-                .await?
+            if !self.subscriptions.contains(e.data.kind()) {
+                self.output.send(e).expect("open channel");
+                continue;
+            }
+            let mut data = self.actor.act(&e).await?;
+            let keep_input = match self.routing {
+                Routing::PassThrough => true,
+                Routing::Filter => match data.iter().position(|msg| *msg == e.data) {
+                    Some(index) => {
+                        data.remove(index);
+                        true
+                    }
+                    None => false,
+                },
+                Routing::Replace => false,
+            };
+            let mut msgs: Vec<Msg> = data
                 .into_iter()
                 .map(|msg| Msg {
                     data: msg,
@@ -45,10 +68,11 @@ where
                         correlation_id: e.metadata.correlation_id,
                         causation_id: e.metadata.id,
                         correlation_price: e.metadata.correlation_price,
+                        correlation_pair_id: e.metadata.correlation_pair_id,
                     },
                 })
                 .collect();
-            if !self.is_filter {
+            if keep_input {
                 msgs.insert(0, e)
             }
             for msg in msgs {
@@ -59,9 +83,53 @@ where
     }
 }
 
+// Messages an actor acts on, all others bypass it.
+#[derive(Debug, PartialEq, Clone, Default)]
+pub enum Subscriptions {
+    #[default]
+    All,
+    Only(Vec<MsgKind>),
+}
+
+impl Subscriptions {
+    pub fn contains(&self, kind: MsgKind) -> bool {
+        match self {
+            Subscriptions::All => true,
+            Subscriptions::Only(kinds) => kinds.contains(&kind),
+        }
+    }
+}
+
+// What happens to a message the actor acted on.
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub enum Routing {
+    // forwarded ahead of the actor's messages
+    #[default]
+    PassThrough,
+    // only forwarded if the actor returns its data unchanged
+    Filter,
+    // dropped, only the actor's messages are forwarded
+    Replace,
+}
+
 #[async_trait]
 pub trait Actor {
     async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>>;
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions::All
+    }
+
+    fn routing(&self) -> Routing {
+        Routing::PassThrough
+    }
+}
+
+// Receives every message leaving the chain, including the Shutdown, and is
+// finished right after it, e.g. to write a file.
+pub trait Sink {
+    fn receive(&mut self, msg: &Msg) -> Result<()>;
+    fn finish(&mut self) -> Result<()>;
 }
 
 pub struct ActorChain<I, T>
@@ -75,6 +143,9 @@ where
     previous_receiver_channel: channel::Receiver<Msg>,
     receiver_channel: channel::Receiver<Msg>,
     sender_channel: channel::Sender<Msg>,
+    source: Option<(Source, channel::Sender<Msg>)>,
+    sinks: Sinks,
+    upstream: Option<Box<Merge<I, T>>>,
 }
 
 impl<I: 'static, T: 'static> ActorChain<I, T>
@@ -91,14 +162,39 @@ where
             previous_receiver_channel: channel,
             receiver_channel: receiver,
             sender_channel: sender,
+            source: None,
+            sinks: vec![],
+            upstream: None,
         }
     }
+
+    // The chain pulls the events itself once started. A Shutdown follows the
+    // last event of a source which ends without one.
+    pub fn from_events(
+        time_provider: T,
+        id_provider: I,
+        events: impl Iterator<Item = Msg> + Send + 'static,
+    ) -> Self {
+        let (sender, receiver) = bounded(SOURCE_BUFFER);
+        let mut chain = ActorChain::new(time_provider, id_provider, receiver);
+        chain.source = Some((Box::new(events), sender));
+        chain
+    }
+
+    pub async fn from_source(
+        time_provider: T,
+        id_provider: I,
+        source: &(impl MarketDataSource + Sync),
+    ) -> Self {
+        ActorChain::from_events(time_provider, id_provider, source.event_stream().await)
+    }
+
     pub fn add<A: Actor + Send + 'static>(mut self, actor: A) -> Self {
         let processor = Processor {
             input: self.previous_receiver_channel,
             output: self.sender_channel,
-            // TODO: Fix this to be either removed, inside actor, or configurable from outside
-            is_filter: false,
+            subscriptions: actor.subscriptions(),
+            routing: actor.routing(),
             actor: Box::new(actor),
             id_provider: self.id_provider.clone(),
             time_provider: self.time_provider.clone(),
@@ -110,13 +206,226 @@ where
         self.receiver_channel = new_receiver;
         self
     }
+
+    pub fn sink<S: Sink + Send + 'static>(mut self, sink: S) -> Self {
+        self.sinks.push(Box::new(sink));
+        self
+    }
+
+    // Every following message goes to all branches, see FanOut.
+    pub fn fan_out(self) -> FanOut<I, T> {
+        FanOut {
+            chain: self,
+            branches: vec![],
+        }
+    }
+
+    // Messages reach the sinks before they are forwarded to the returned
+    // receiver, which closes once the sinks are finished.
     pub async fn start(self) -> channel::Receiver<Msg> {
+        self.spawn()
+    }
+
+    // Runs the chain until the Shutdown went through all sinks.
+    pub async fn run(self) {
+        for _ in self.start().await.iter() {}
+    }
+
+    fn spawn(self) -> channel::Receiver<Msg> {
+        if let Some(upstream) = self.upstream {
+            upstream.spawn();
+        }
+        if let Some((events, sender)) = self.source {
+            // live sources block until the next event, so they get a thread of their own
+            thread::spawn(move || pull(events, sender));
+        }
         for processor in self.processors {
             task::spawn(async move {
                 processor.start().await.unwrap();
             });
         }
-        self.previous_receiver_channel
+        if self.sinks.is_empty() {
+            return self.previous_receiver_channel;
+        }
+        let (sender, receiver) = unbounded();
+        let input = self.previous_receiver_channel;
+        let sinks = self.sinks;
+        thread::spawn(move || feed(input, sinks, sender));
+        receiver
+    }
+}
+
+// Branches of a chain, e.g. strategies with a Trader and account each, which
+// all get the same messages. Merged back they form a chain again, whose
+// messages are ordered by correlation time and id so runs stay reproducible.
+pub struct FanOut<I, T>
+where
+    I: IdProvider,
+    T: TimeProvider,
+{
+    chain: ActorChain<I, T>,
+    branches: Vec<(channel::Sender<Msg>, ActorChain<I, T>)>,
+}
+
+impl<I: 'static, T: 'static> FanOut<I, T>
+where
+    I: IdProvider + Clone + Send,
+    T: TimeProvider + Clone + Send,
+{
+    pub fn branch(mut self, build: impl FnOnce(ActorChain<I, T>) -> ActorChain<I, T>) -> Self {
+        let (sender, receiver) = unbounded();
+        let branch = ActorChain::new(
+            self.chain.time_provider.clone(),
+            self.chain.id_provider.clone(),
+            receiver,
+        );
+        self.branches.push((sender, build(branch)));
+        self
+    }
+
+    pub fn merge(self) -> ActorChain<I, T> {
+        let (sender, receiver) = unbounded();
+        let mut chain = ActorChain::new(
+            self.chain.time_provider.clone(),
+            self.chain.id_provider.clone(),
+            receiver,
+        );
+        chain.upstream = Some(Box::new(Merge {
+            fan_out: self,
+            output: sender,
+        }));
+        chain
+    }
+}
+
+struct Merge<I, T>
+where
+    I: IdProvider,
+    T: TimeProvider,
+{
+    fan_out: FanOut<I, T>,
+    output: channel::Sender<Msg>,
+}
+
+impl<I: 'static, T: 'static> Merge<I, T>
+where
+    I: IdProvider + Clone + Send,
+    T: TimeProvider + Clone + Send,
+{
+    fn spawn(self) {
+        let input = self.fan_out.chain.spawn();
+        let (inputs, outputs): (Vec<_>, Vec<_>) = self
+            .fan_out
+            .branches
+            .into_iter()
+            .map(|(sender, branch)| (sender, branch.spawn()))
+            .unzip();
+        let output = self.output;
+        let (shared_sender, shared) = unbounded();
+        thread::spawn(move || split(input, inputs, shared_sender));
+        thread::spawn(move || merge(outputs, shared, output));
+    }
+}
+
+fn pull(events: Source, sender: channel::Sender<Msg>) {
+    for event in events {
+        let is_shutdown = matches!(event.data, MsgData::Shutdown);
+        if sender.send(event).is_err() || is_shutdown {
+            return;
+        }
+    }
+    sender.send(Msg::with_data(MsgData::Shutdown)).ok();
+}
+
+// The merge is told about every message before the branches get it, so it
+// knows which messages they share.
+fn split(
+    input: channel::Receiver<Msg>,
+    branches: Vec<channel::Sender<Msg>>,
+    shared: channel::Sender<(Order, MessageId)>,
+) {
+    for msg in input.iter() {
+        let is_shutdown = matches!(msg.data, MsgData::Shutdown);
+        shared.send((order(&msg), msg.metadata.id)).ok();
+        for branch in branches.iter() {
+            branch.send(msg.clone()).ok();
+        }
+        if is_shutdown {
+            return;
+        }
+    }
+}
+
+type Order = (Timestamp, MessageId);
+
+fn order(msg: &Msg) -> Order {
+    (msg.metadata.correlation_time, msg.metadata.correlation_id)
+}
+
+// Messages of one correlation are emitted in branch order, before those of
+// later correlations. A message from before the fan out is only emitted by the
+// first branch passing it on, everything created by the branches is emitted.
+fn merge(
+    branches: Vec<channel::Receiver<Msg>>,
+    shared: channel::Receiver<(Order, MessageId)>,
+    output: channel::Sender<Msg>,
+) {
+    let mut shutdown = None;
+    let mut next = |branch: &channel::Receiver<Msg>| match branch.recv() {
+        Ok(msg) if matches!(msg.data, MsgData::Shutdown) => {
+            shutdown.get_or_insert(msg);
+            None
+        }
+        msg => msg.ok(),
+    };
+    let mut peeked: Vec<Option<Msg>> = branches.iter().map(&mut next).collect();
+    let mut sources: VecDeque<(Order, MessageId)> = VecDeque::new();
+    while let Some(current) = peeked.iter().flatten().map(order).min() {
+        let mut emitted_by: HashMap<MessageId, usize> = HashMap::new();
+        for (index, (branch, peeked)) in branches.iter().zip(peeked.iter_mut()).enumerate() {
+            while peeked.as_ref().is_some_and(|msg| order(msg) == current) {
+                let msg = peeked.take().expect("peeked message");
+                *peeked = next(branch);
+                sources.extend(shared.try_iter());
+                while sources.front().is_some_and(|(order, _)| *order < current) {
+                    sources.pop_front();
+                }
+                let is_shared = sources
+                    .iter()
+                    .take_while(|(order, _)| *order == current)
+                    .any(|(_, id)| *id == msg.metadata.id);
+                if !is_shared || *emitted_by.entry(msg.metadata.id).or_insert(index) == index {
+                    output.send(msg).ok();
+                }
+            }
+        }
+    }
+    output
+        .send(shutdown.unwrap_or_else(|| Msg::with_data(MsgData::Shutdown)))
+        .ok();
+}
+
+// A failing sink is logged and kept, so it can't stop trading.
+fn feed(input: channel::Receiver<Msg>, mut sinks: Sinks, output: channel::Sender<Msg>) {
+    for msg in input.iter() {
+        for sink in sinks.iter_mut() {
+            if let Err(err) = sink.receive(&msg) {
+                log::error!("sink failed: {}", err);
+            }
+        }
+        let is_shutdown = matches!(msg.data, MsgData::Shutdown);
+        if is_shutdown {
+            for sink in sinks.iter_mut() {
+                if let Err(err) = sink.finish() {
+                    log::error!("finishing sink failed: {}", err);
+                }
+            }
+        }
+        // the sinks keep receiving even if nobody reads the output
+        output.send(msg).ok();
+        if is_shutdown {
+            return;
+        }
     }
 }
 
@@ -127,6 +436,7 @@ mod tests {
         messaging::message::MsgMetaData,
         tools::{time::tests::MockTimeProvider, uuid::tests::MockUuidProvider},
     };
+    use std::sync::{Arc, Mutex};
     use uuid::Uuid;
 
     use pretty_assertions::assert_eq;
@@ -140,8 +450,72 @@ mod tests {
         }
     }
 
+    // Emits the same messages for every message it receives.
+    struct Emit {
+        data: Vec<MsgData>,
+        routing: Routing,
+    }
+
+    #[async_trait]
+    impl Actor for Emit {
+        async fn act(&mut self, _: &Msg) -> Result<Vec<MsgData>> {
+            Ok(self.data.clone())
+        }
+
+        fn routing(&self) -> Routing {
+            self.routing
+        }
+    }
+
+    fn emit(data: Vec<MsgData>, routing: Routing) -> Emit {
+        Emit { data, routing }
+    }
+
+    fn correlated(data: MsgData, id: u128, time: Timestamp) -> Msg {
+        Msg {
+            data,
+            metadata: MsgMetaData {
+                id: Uuid::from_u128(id),
+                correlation_id: Uuid::from_u128(id),
+                correlation_time: time,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[derive(Clone, Default)]
+    struct MockSink {
+        received: Arc<Mutex<Vec<Msg>>>,
+        finished: Arc<Mutex<usize>>,
+    }
+
+    impl Sink for MockSink {
+        fn receive(&mut self, msg: &Msg) -> Result<()> {
+            self.received.lock().unwrap().push(msg.clone());
+            Ok(())
+        }
+
+        fn finish(&mut self) -> Result<()> {
+            *self.finished.lock().unwrap() += 1;
+            Ok(())
+        }
+    }
+
+    // Keeps sells, adding a buy after each, and drops everything else.
+    pub struct SellFilter {}
+
+    #[async_trait]
+    impl Actor for SellFilter {
+        async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
+            match msg.data {
+                MsgData::Sell => Ok(vec![MsgData::Sell, MsgData::Buy]),
+                _ => Ok(vec![]),
+            }
+        }
+    }
+
     fn new_processor(
-        is_filter: bool,
+        routing: Routing,
     ) -> (
         Processor<MockUuidProvider, MockTimeProvider>,
         channel::Sender<Msg>,
@@ -153,7 +527,8 @@ mod tests {
             Processor {
                 input: in_r,
                 output: out_s,
-                is_filter,
+                subscriptions: Subscriptions::All,
+                routing,
                 actor: Box::new(MockActor {}),
                 id_provider: MockUuidProvider::new(),
                 time_provider: MockTimeProvider::new(),
@@ -165,7 +540,7 @@ mod tests {
 
     #[async_std::test]
     async fn processor_should_exit_if_shutdown_received() {
-        let (processor, in_s, _out_r) = new_processor(false);
+        let (processor, in_s, _out_r) = new_processor(Routing::PassThrough);
         in_s.send(Msg::with_data(MsgData::Shutdown)).unwrap();
         processor.start().await.unwrap();
         assert!(true);
@@ -173,7 +548,7 @@ mod tests {
 
     #[async_std::test]
     async fn processor_should_emit_received_shutdown() {
-        let (processor, in_s, out_r) = new_processor(false);
+        let (processor, in_s, out_r) = new_processor(Routing::PassThrough);
         let expected_msg = Msg::with_data(MsgData::Shutdown);
 
         in_s.send(expected_msg.clone()).unwrap();
@@ -184,8 +559,8 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn processor_should_output_input_events_if_not_filtered() {
-        let (processor, in_s, out_r) = new_processor(false);
+    async fn processor_should_output_input_events_if_passed_through() {
+        let (processor, in_s, out_r) = new_processor(Routing::PassThrough);
         let expected_msg = Msg::with_data(MsgData::Sell);
 
         in_s.send(expected_msg.clone()).unwrap();
@@ -200,8 +575,8 @@ mod tests {
     }
 
     #[async_std::test]
-    async fn processor_should_not_output_input_events_if_filtered() {
-        let (processor, in_s, out_r) = new_processor(true);
+    async fn processor_should_not_output_input_events_if_replaced() {
+        let (processor, in_s, out_r) = new_processor(Routing::Replace);
         let msg = Msg::with_data(MsgData::Sell);
 
         in_s.send(msg).unwrap();
@@ -213,9 +588,60 @@ mod tests {
         assert_eq!(expected_msg, actual_msg);
     }
 
+    #[async_std::test]
+    async fn processor_should_bypass_unsubscribed_events() {
+        let (mut processor, in_s, out_r) = new_processor(Routing::Replace);
+        processor.subscriptions = Subscriptions::Only(vec![MsgKind::Sell]);
+
+        in_s.send(Msg::with_data(MsgData::Buy)).unwrap();
+        in_s.send(Msg::with_data(MsgData::Sell)).unwrap();
+        in_s.send(Msg::with_data(MsgData::Shutdown)).unwrap();
+        processor.start().await.unwrap();
+
+        let messages: Vec<MsgData> = out_r.iter().map(|msg| msg.data).collect();
+        assert_eq!(
+            vec![MsgData::Buy, MsgData::Buy, MsgData::Shutdown],
+            messages
+        );
+    }
+
+    #[async_std::test]
+    async fn processor_should_only_output_input_events_kept_by_filter() {
+        let (mut processor, in_s, out_r) = new_processor(Routing::Filter);
+        processor.actor = Box::new(SellFilter {});
+        let sell = Msg {
+            data: MsgData::Sell,
+            metadata: MsgMetaData {
+                id: uuid::Uuid::from_u128(8),
+                ..Default::default()
+            },
+        };
+
+        in_s.send(Msg::with_data(MsgData::Buy)).unwrap();
+        in_s.send(sell.clone()).unwrap();
+        in_s.send(Msg::with_data(MsgData::Shutdown)).unwrap();
+        processor.start().await.unwrap();
+
+        let messages: Vec<Msg> = out_r.iter().collect();
+        assert_eq!(
+            vec![
+                sell,
+                Msg {
+                    data: MsgData::Buy,
+                    metadata: MsgMetaData {
+                        causation_id: uuid::Uuid::from_u128(8),
+                        ..Default::default()
+                    },
+                },
+                Msg::with_data(MsgData::Shutdown)
+            ],
+            messages
+        );
+    }
+
     #[async_std::test]
     async fn processor_adds_metadata_for_new_msg() {
-        let (processor, in_s, out_r) = new_processor(true);
+        let (processor, in_s, out_r) = new_processor(Routing::Replace);
         let msg = Msg {
             data: MsgData::Sell,
             metadata: MsgMetaData {
@@ -223,6 +649,7 @@ mod tests {
                 causation_id: uuid::Uuid::from_u128(7),
                 correlation_id: uuid::Uuid::from_u128(7),
                 correlation_price: 1.0,
+                correlation_pair_id: "BTC/USDT",
                 correlation_time: 1,
                 creation_time: 0,
             },
@@ -239,6 +666,7 @@ mod tests {
                 causation_id: uuid::Uuid::from_u128(8),
                 correlation_id: uuid::Uuid::from_u128(7),
                 correlation_price: 1.0,
+                correlation_pair_id: "BTC/USDT",
                 correlation_time: 1,
                 creation_time: 0,
             },
@@ -249,7 +677,7 @@ mod tests {
 
     #[async_std::test]
     async fn processor_adds_different_metadata_for_new_msg() {
-        let (processor, in_s, out_r) = new_processor(true);
+        let (processor, in_s, out_r) = new_processor(Routing::Replace);
         let msg = Msg {
             data: MsgData::Sell,
             metadata: MsgMetaData {
@@ -257,6 +685,7 @@ mod tests {
                 causation_id: uuid::Uuid::from_u128(6),
                 correlation_id: uuid::Uuid::from_u128(6),
                 correlation_price: 1.0,
+                correlation_pair_id: "ETH/USDT",
                 creation_time: 0,
                 correlation_time: 1,
             },
@@ -271,6 +700,7 @@ mod tests {
                 causation_id: uuid::Uuid::from_u128(7),
                 correlation_id: uuid::Uuid::from_u128(6),
                 correlation_price: 1.0,
+                correlation_pair_id: "ETH/USDT",
                 creation_time: 0,
                 correlation_time: 1,
             },
@@ -357,4 +787,218 @@ mod tests {
             messages
         );
     }
+
+    #[async_std::test]
+    async fn actor_chain_pulls_events_of_source() {
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![Msg::with_data(MsgData::Sell)].into_iter(),
+        )
+        .add(MockActor {})
+        .start()
+        .await;
+
+        let messages: Vec<Msg> = output.iter().collect();
+        assert_eq!(
+            vec![
+                Msg::with_data(MsgData::Sell),
+                Msg::with_data(MsgData::Buy),
+                Msg::with_data(MsgData::Shutdown)
+            ],
+            messages
+        );
+    }
+
+    #[async_std::test]
+    async fn actor_chain_stops_at_shutdown_of_source() {
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![
+                Msg::with_data(MsgData::Shutdown),
+                Msg::with_data(MsgData::Sell),
+            ]
+            .into_iter(),
+        )
+        .start()
+        .await;
+
+        let messages: Vec<Msg> = output.iter().collect();
+        assert_eq!(vec![Msg::with_data(MsgData::Shutdown)], messages);
+    }
+
+    #[async_std::test]
+    async fn actor_chain_forwards_live_source_until_it_ends() {
+        let (sender, receiver) = unbounded();
+        let output =
+            ActorChain::from_source(MockTimeProvider::new(), MockUuidProvider::new(), &receiver)
+                .await
+                .start()
+                .await;
+
+        sender.send(Msg::with_data(MsgData::Sell)).unwrap();
+        assert_eq!(Msg::with_data(MsgData::Sell), output.recv().unwrap());
+        assert!(output.try_recv().is_err());
+        drop(sender);
+        drop(receiver);
+        assert_eq!(Msg::with_data(MsgData::Shutdown), output.recv().unwrap());
+    }
+
+    #[async_std::test]
+    async fn actor_chain_feeds_every_message_to_all_sinks() {
+        let (first, second) = (MockSink::default(), MockSink::default());
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![Msg::with_data(MsgData::Sell)].into_iter(),
+        )
+        .add(MockActor {})
+        .sink(first.clone())
+        .sink(second.clone())
+        .start()
+        .await;
+
+        let messages: Vec<Msg> = output.iter().collect();
+        let expected = vec![
+            Msg::with_data(MsgData::Sell),
+            Msg::with_data(MsgData::Buy),
+            Msg::with_data(MsgData::Shutdown),
+        ];
+        assert_eq!(expected, messages);
+        assert_eq!(expected, *first.received.lock().unwrap());
+        assert_eq!(expected, *second.received.lock().unwrap());
+    }
+
+    #[async_std::test]
+    async fn actor_chain_finishes_sinks_once_at_shutdown() {
+        let (sender, receiver) = unbounded();
+        let sink = MockSink::default();
+        let output = ActorChain::new(MockTimeProvider::new(), MockUuidProvider::new(), receiver)
+            .sink(sink.clone())
+            .start()
+            .await;
+
+        sender.send(Msg::with_data(MsgData::Sell)).unwrap();
+        output.recv().unwrap();
+        assert_eq!(0, *sink.finished.lock().unwrap());
+        sender.send(Msg::with_data(MsgData::Shutdown)).unwrap();
+        sender.send(Msg::with_data(MsgData::Sell)).unwrap();
+        output.recv().unwrap();
+        assert_eq!(1, *sink.finished.lock().unwrap());
+        assert!(output.recv().is_err());
+    }
+
+    #[async_std::test]
+    async fn actor_chain_runs_until_sinks_are_finished() {
+        let sink = MockSink::default();
+        ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![Msg::with_data(MsgData::Sell)].into_iter(),
+        )
+        .sink(sink.clone())
+        .run()
+        .await;
+        assert_eq!(2, sink.received.lock().unwrap().len());
+        assert_eq!(1, *sink.finished.lock().unwrap());
+    }
+
+    #[async_std::test]
+    async fn fan_out_should_merge_branches_ordered_by_correlation() {
+        let sink = MockSink::default();
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![
+                correlated(MsgData::Sell, 1, 10),
+                correlated(MsgData::Sell, 2, 20),
+            ]
+            .into_iter(),
+        )
+        .fan_out()
+        .branch(|chain| {
+            chain
+                .add(emit(vec![MsgData::Buy], Routing::PassThrough))
+                .sink(sink.clone())
+        })
+        .branch(|chain| chain.add(emit(vec![MsgData::Sell], Routing::PassThrough)))
+        .merge()
+        .start()
+        .await;
+
+        let messages: Vec<(MsgData, Timestamp)> = output
+            .iter()
+            .map(|msg| (msg.data, msg.metadata.correlation_time))
+            .collect();
+        assert_eq!(
+            vec![
+                (MsgData::Sell, 10),
+                (MsgData::Buy, 10),
+                (MsgData::Sell, 10),
+                (MsgData::Sell, 20),
+                (MsgData::Buy, 20),
+                (MsgData::Sell, 20),
+                (MsgData::Shutdown, 0),
+            ],
+            messages
+        );
+        assert_eq!(5, sink.received.lock().unwrap().len());
+    }
+
+    #[async_std::test]
+    async fn fan_out_should_merge_branches_which_drop_messages() {
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![
+                correlated(MsgData::Sell, 1, 10),
+                correlated(MsgData::Sell, 2, 20),
+            ]
+            .into_iter(),
+        )
+        .fan_out()
+        .branch(|chain| chain.add(emit(vec![], Routing::Replace)))
+        .branch(|chain| chain.add(emit(vec![MsgData::Buy], Routing::Replace)))
+        .merge()
+        .add(MockActor {})
+        .start()
+        .await;
+
+        let messages: Vec<(MsgData, Timestamp)> = output
+            .iter()
+            .map(|msg| (msg.data, msg.metadata.correlation_time))
+            .collect();
+        assert_eq!(
+            vec![
+                (MsgData::Buy, 10),
+                (MsgData::Buy, 10),
+                (MsgData::Buy, 20),
+                (MsgData::Buy, 20),
+                (MsgData::Shutdown, 0),
+            ],
+            messages
+        );
+    }
+
+    #[async_std::test]
+    async fn fan_out_should_keep_equal_messages_of_different_branches() {
+        let output = ActorChain::from_events(
+            MockTimeProvider::new(),
+            MockUuidProvider::new(),
+            vec![correlated(MsgData::Sell, 1, 10)].into_iter(),
+        )
+        .fan_out()
+        .branch(|chain| chain.add(emit(vec![MsgData::Buy], Routing::PassThrough)))
+        .branch(|chain| chain.add(emit(vec![MsgData::Buy], Routing::PassThrough)))
+        .merge()
+        .start()
+        .await;
+
+        let messages: Vec<MsgData> = output.iter().map(|msg| msg.data).collect();
+        assert_eq!(
+            vec![MsgData::Sell, MsgData::Buy, MsgData::Buy, MsgData::Shutdown],
+            messages
+        );
+    }
 }
@@ -1,13 +1,19 @@
-use crate::messaging::message::{Msg, MsgData, Price};
-use crate::messaging::processor::Actor;
+use crate::messaging::message::{Msg, MsgData, MsgKind, PairId, Price};
+use crate::messaging::processor::{Actor, Subscriptions};
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Clone, Default)]
+struct Latest {
+    average: Option<Price>,
+    live: Option<Price>,
+}
 
 #[derive(Debug, PartialEq, Clone, Default)]
 pub struct Crossover {
     offset: f64,
-    latest_average: Option<Price>,
-    latest_live: Option<Price>,
+    latest: HashMap<PairId, Latest>,
 }
 
 impl Crossover {
@@ -24,17 +30,17 @@ impl Actor for Crossover {
     async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
         let res = match &msg.data {
             MsgData::LivePriceUpdated(e) => {
-                let result = self
-                    .latest_average
+                let offset = self.offset;
+                let latest = self.latest.entry(e.pair_id).or_default();
+                let result = latest
+                    .average
                     .map(|avg| {
-                        if e.price > avg * (1.0 + self.offset)
-                            && (self.latest_live.is_none()
-                                || self.latest_live < Some(avg * (1.0 + self.offset)))
+                        if e.price > avg * (1.0 + offset)
+                            && (latest.live.is_none() || latest.live < Some(avg * (1.0 + offset)))
                         {
                             vec![MsgData::Buy]
-                        } else if e.price < avg * (1.0 - self.offset)
-                            && (self.latest_live.is_none()
-                                || self.latest_live > Some(avg * (1.0 - self.offset)))
+                        } else if e.price < avg * (1.0 - offset)
+                            && (latest.live.is_none() || latest.live > Some(avg * (1.0 - offset)))
                         {
                             vec![MsgData::Sell]
                         } else {
@@ -42,19 +48,26 @@ impl Actor for Crossover {
                         }
                     })
                     .unwrap_or(vec![]);
-                if self.latest_average.is_some() {
-                    self.latest_live = Some(e.price);
+                if latest.average.is_some() {
+                    latest.live = Some(e.price);
                 }
                 result
             }
             MsgData::AveragePriceUpdated(e) => {
-                self.latest_average = Some(e.price);
+                self.latest.entry(e.pair_id).or_default().average = Some(e.price);
                 vec![]
             }
             _ => vec![],
         };
         Ok(res)
     }
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions::Only(vec![
+            MsgKind::LivePriceUpdated,
+            MsgKind::AveragePriceUpdated,
+        ])
+    }
 }
 
 #[cfg(test)]
@@ -488,4 +501,35 @@ mod tests {
         let expected: Vec<MsgData> = vec![MsgData::Sell];
         assert_eq!(expected, actual)
     }
+
+    #[async_std::test]
+    async fn actor_should_track_crossings_of_each_pair_separately() {
+        let mut aggr = Crossover::new(0.0);
+        let update = |data: fn(PriceUpdated) -> MsgData, pair_id, price| {
+            Msg::with_data(data(PriceUpdated {
+                pair_id,
+                price,
+                ..Default::default()
+            }))
+        };
+        aggr.act(&update(MsgData::AveragePriceUpdated, "BTC-USDT", 100.0))
+            .await
+            .unwrap();
+        aggr.act(&update(MsgData::AveragePriceUpdated, "ETH-USDT", 10.0))
+            .await
+            .unwrap();
+        aggr.act(&update(MsgData::LivePriceUpdated, "BTC-USDT", 90.0))
+            .await
+            .unwrap();
+        let eth = aggr
+            .act(&update(MsgData::LivePriceUpdated, "ETH-USDT", 11.0))
+            .await
+            .unwrap();
+        let btc = aggr
+            .act(&update(MsgData::LivePriceUpdated, "BTC-USDT", 110.0))
+            .await
+            .unwrap();
+        assert_eq!(vec![MsgData::Buy], eth);
+        assert_eq!(vec![MsgData::Buy], btc)
+    }
 }
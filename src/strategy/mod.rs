@@ -1,2 +1,3 @@
 pub mod crossover;
+pub mod risk;
 pub mod sliding_average;
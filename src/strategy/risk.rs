@@ -0,0 +1,409 @@
+use crate::exchange::Amount;
+use crate::messaging::message::{Msg, MsgData, MsgKind, MsgMetaData, Price, PriceUpdated};
+use crate::messaging::processor::{Actor, Routing, Subscriptions};
+use anyhow::Result;
+use async_trait::async_trait;
+use std::collections::HashMap;
+
+#[derive(Debug, PartialEq, Copy, Clone, Default)]
+pub struct ExitRules {
+    pub stop_loss: Option<f64>,
+    pub take_profit: Option<f64>,
+    pub trailing_stop: Option<f64>,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+struct Position {
+    entry: Price,
+    peak: Price,
+    size: Amount,
+}
+
+// Wraps the Trader, so entries are taken from its fills and exits are sent to
+// it right away: a rejected or skipped buy opens no position and an exit is
+// placed before the next message.
+pub struct RiskManager<A> {
+    trader: A,
+    rules: ExitRules,
+    positions: HashMap<String, Position>,
+}
+
+impl<A: Actor + Send> RiskManager<A> {
+    pub fn new(rules: ExitRules, trader: A) -> Self {
+        RiskManager {
+            trader,
+            rules,
+            positions: HashMap::new(),
+        }
+    }
+
+    fn is_triggered(&self, position: &Position, low: Price, high: Price) -> bool {
+        let stop_loss = self
+            .rules
+            .stop_loss
+            .is_some_and(|stop| low <= position.entry * (1.0 - stop));
+        let take_profit = self
+            .rules
+            .take_profit
+            .is_some_and(|take| high >= position.entry * (1.0 + take));
+        let trailing_stop = self
+            .rules
+            .trailing_stop
+            .is_some_and(|trail| low <= position.peak * (1.0 - trail));
+        stop_loss || take_profit || trailing_stop
+    }
+
+    // Buying more of a pair moves the entry to the average price, selling part
+    // of it only reduces its size.
+    fn track(&mut self, data: &[MsgData]) {
+        for msg in data {
+            match msg {
+                MsgData::Bought(order) if order.amount > 0.0 => {
+                    let pair_id = format!("{}/{}", order.base, order.quote);
+                    let position = self.positions.entry(pair_id).or_insert(Position {
+                        entry: order.price,
+                        peak: order.price,
+                        size: 0.0,
+                    });
+                    let size = position.size + order.amount;
+                    position.entry =
+                        (position.entry * position.size + order.price * order.amount) / size;
+                    position.peak = position.peak.max(order.price);
+                    position.size = size;
+                }
+                MsgData::Sold(order) => {
+                    let pair_id = format!("{}/{}", order.base, order.quote);
+                    let position = match self.positions.get_mut(&pair_id) {
+                        Some(position) => position,
+                        None => continue,
+                    };
+                    match order.sold_base() {
+                        Some(sold) if sold < position.size * (1.0 - 1e-9) => {
+                            position.size -= sold;
+                        }
+                        _ => {
+                            self.positions.remove(&pair_id);
+                        }
+                    }
+                }
+                MsgData::Liquidated(liquidation) => {
+                    self.positions
+                        .remove(&format!("{}/{}", liquidation.base, liquidation.quote));
+                }
+                _ => (),
+            }
+        }
+    }
+
+    fn is_exit(&mut self, price: &PriceUpdated) -> bool {
+        let (low, high) = price
+            .candle
+            .map(|candle| (candle.low, candle.high))
+            .unwrap_or((price.price, price.price));
+        match self.positions.get(price.pair_id).copied() {
+            Some(position) if self.is_triggered(&position, low, high) => {
+                // forgotten even if the sell fails, so it isn't sent on every price
+                self.positions.remove(price.pair_id);
+                true
+            }
+            Some(position) => {
+                self.positions.insert(
+                    price.pair_id.into(),
+                    Position {
+                        peak: position.peak.max(high),
+                        ..position
+                    },
+                );
+                false
+            }
+            None => false,
+        }
+    }
+}
+
+#[async_trait]
+impl<A: Actor + Send> Actor for RiskManager<A> {
+    async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
+        let mut res = self.trader.act(msg).await?;
+        self.track(&res);
+        if let MsgData::LivePriceUpdated(price) = &msg.data {
+            if self.is_exit(price) {
+                let sell = Msg {
+                    data: MsgData::Sell,
+                    metadata: MsgMetaData {
+                        correlation_pair_id: price.pair_id,
+                        correlation_price: price.price,
+                        ..msg.metadata.clone()
+                    },
+                };
+                let exit = self.trader.act(&sell).await?;
+                self.track(&exit);
+                res.push(MsgData::Sell);
+                res.extend(exit);
+            }
+        }
+        Ok(res)
+    }
+
+    fn subscriptions(&self) -> Subscriptions {
+        match self.trader.subscriptions() {
+            Subscriptions::Only(mut kinds) if !kinds.contains(&MsgKind::LivePriceUpdated) => {
+                kinds.push(MsgKind::LivePriceUpdated);
+                Subscriptions::Only(kinds)
+            }
+            subscriptions => subscriptions,
+        }
+    }
+
+    fn routing(&self) -> Routing {
+        self.trader.routing()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::message::{Candle, Order, Rejection};
+    use pretty_assertions::assert_eq;
+
+    // Fills buys at the price of the signal unless told to reject them.
+    #[derive(Default)]
+    struct MockTrader {
+        reject: bool,
+        signals: Vec<MsgData>,
+    }
+
+    fn order(amount: f64, price: Price) -> Order {
+        Order {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount,
+            price,
+            ..Default::default()
+        }
+    }
+
+    #[async_trait]
+    impl Actor for MockTrader {
+        async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
+            let price = msg.metadata.correlation_price;
+            let res = match msg.data {
+                MsgData::Buy if self.reject => vec![MsgData::Rejected(Rejection {
+                    base: "BTC".into(),
+                    quote: "USDT".into(),
+                    amount: 1.0,
+                    reason: "insufficient USDT".into(),
+                })],
+                MsgData::Buy => vec![MsgData::Bought(order(1.0, price))],
+                MsgData::Sell => vec![MsgData::Sold(order(price, price))],
+                _ => vec![],
+            };
+            if matches!(msg.data, MsgData::Buy | MsgData::Sell) {
+                self.signals.push(msg.data.clone());
+            }
+            Ok(res)
+        }
+    }
+
+    fn new_actor(rules: ExitRules) -> RiskManager<MockTrader> {
+        RiskManager::new(rules, MockTrader::default())
+    }
+
+    fn signal(data: MsgData, price: Price) -> Msg {
+        Msg {
+            data,
+            metadata: MsgMetaData {
+                correlation_pair_id: "BTC/USDT",
+                correlation_price: price,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn buy(price: Price) -> Msg {
+        signal(MsgData::Buy, price)
+    }
+
+    fn live(price: Price) -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id: "BTC/USDT",
+            price,
+            ..Default::default()
+        }))
+    }
+
+    fn live_candle(low: Price, high: Price) -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id: "BTC/USDT",
+            price: (low + high) / 2.0,
+            candle: Some(Candle {
+                low,
+                high,
+                ..Default::default()
+            }),
+            ..Default::default()
+        }))
+    }
+
+    fn exit(price: Price) -> Vec<MsgData> {
+        vec![MsgData::Sell, MsgData::Sold(order(price, price))]
+    }
+
+    #[async_std::test]
+    async fn actor_should_emit_nothing_without_position() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        let actual = actor.act(&live(1.0)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_pass_on_messages_of_trader() {
+        let mut actor = new_actor(ExitRules {
+            ..Default::default()
+        });
+        let actual = actor.act(&buy(10.0)).await.unwrap();
+        assert_eq!(vec![MsgData::Bought(order(1.0, 10.0))], actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_if_stop_loss_hit() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live(8.9)).await.unwrap();
+        assert_eq!(exit(8.9), actual);
+        assert_eq!(vec![MsgData::Buy, MsgData::Sell], actor.trader.signals)
+    }
+
+    #[async_std::test]
+    async fn actor_should_emit_nothing_if_stop_loss_not_hit() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live(9.5)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_if_take_profit_hit() {
+        let mut actor = new_actor(ExitRules {
+            take_profit: Some(0.2),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live(12.5)).await.unwrap();
+        assert_eq!(exit(12.5), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_if_trailing_stop_hit_from_peak() {
+        let mut actor = new_actor(ExitRules {
+            trailing_stop: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&live(20.0)).await.unwrap();
+        let actual = actor.act(&live(17.9)).await.unwrap();
+        assert_eq!(exit(17.9), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_if_candle_low_hits_stop_loss() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live_candle(8.5, 12.0)).await.unwrap();
+        assert_eq!(exit(10.25), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_if_candle_high_hits_take_profit() {
+        let mut actor = new_actor(ExitRules {
+            take_profit: Some(0.2),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live_candle(9.0, 12.0)).await.unwrap();
+        assert_eq!(exit(10.5), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_sell_only_once() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&live(8.0)).await.unwrap();
+        let actual = actor.act(&live(7.0)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_forget_position_after_strategy_sell() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&signal(MsgData::Sell, 10.0)).await.unwrap();
+        let actual = actor.act(&live(5.0)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_not_open_position_for_rejected_buy() {
+        let mut actor = RiskManager::new(
+            ExitRules {
+                stop_loss: Some(0.1),
+                ..Default::default()
+            },
+            MockTrader {
+                reject: true,
+                ..Default::default()
+            },
+        );
+        actor.act(&buy(10.0)).await.unwrap();
+        let actual = actor.act(&live(5.0)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), actual);
+        assert_eq!(vec![MsgData::Buy], actor.trader.signals)
+    }
+
+    #[async_std::test]
+    async fn actor_should_average_entry_when_scaling_in() {
+        let mut actor = new_actor(ExitRules {
+            take_profit: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&buy(20.0)).await.unwrap();
+        // 10% above the average entry of 15, but not above the last buy at 20
+        let not_hit = actor.act(&live(16.0)).await.unwrap();
+        let actual = actor.act(&live(16.6)).await.unwrap();
+        assert_eq!(Vec::<MsgData>::new(), not_hit);
+        assert_eq!(exit(16.6), actual)
+    }
+
+    #[async_std::test]
+    async fn actor_should_keep_rest_of_position_after_partial_sell() {
+        let mut actor = new_actor(ExitRules {
+            stop_loss: Some(0.1),
+            ..Default::default()
+        });
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&buy(10.0)).await.unwrap();
+        actor.act(&signal(MsgData::Sell, 10.0)).await.unwrap();
+        let actual = actor.act(&live(8.9)).await.unwrap();
+        assert_eq!(exit(8.9), actual);
+        assert!(actor.positions.is_empty())
+    }
+}
@@ -1,13 +1,19 @@
-use crate::messaging::message::{Msg, MsgData, PriceUpdated};
-use crate::messaging::processor::Actor;
+use crate::messaging::message::{Msg, MsgData, MsgKind, PairId, PriceUpdated};
+use crate::messaging::processor::{Actor, Subscriptions};
 use anyhow::Result;
 use async_trait::async_trait;
+use std::collections::HashMap;
 
 #[derive(Debug, PartialEq, Clone, Default)]
-pub struct SlidingAverage {
-    pub window_millis: u128,
+struct Window {
     latest_average: Option<f64>,
     counted_price_points: u16,
+}
+
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct SlidingAverage {
+    pub window_millis: u128,
+    windows: HashMap<PairId, Window>,
     min_price_points: u16,
 }
 
@@ -15,8 +21,7 @@ impl SlidingAverage {
     pub fn new(interval_millis: u128, window_millis: u128) -> Self {
         SlidingAverage {
             window_millis,
-            latest_average: None,
-            counted_price_points: 0,
+            windows: HashMap::new(),
             min_price_points: (window_millis / interval_millis) as u16,
         }
     }
@@ -27,12 +32,13 @@ impl Actor for SlidingAverage {
     async fn act(&mut self, msg: &Msg) -> Result<Vec<MsgData>> {
         let res = match &msg.data {
             MsgData::LivePriceUpdated(e) => {
-                let latest_average = self.latest_average.unwrap_or(e.price);
+                let window = self.windows.entry(e.pair_id).or_default();
+                let latest_average = window.latest_average.unwrap_or(e.price);
                 let current_average = (e.price - latest_average)
                     * (2.0 / (self.min_price_points + 1) as f64)
                     + latest_average;
-                self.latest_average = Some(current_average);
-                if self.counted_price_points >= self.min_price_points {
+                window.latest_average = Some(current_average);
+                if window.counted_price_points >= self.min_price_points {
                     vec![MsgData::AveragePriceUpdated(PriceUpdated {
                         pair_id: e.pair_id,
                         datetime: e.datetime,
@@ -40,7 +46,7 @@ impl Actor for SlidingAverage {
                         ..Default::default()
                     })]
                 } else {
-                    self.counted_price_points += 1;
+                    window.counted_price_points += 1;
                     vec![]
                 }
             }
@@ -48,6 +54,10 @@ impl Actor for SlidingAverage {
         };
         Ok(res)
     }
+
+    fn subscriptions(&self) -> Subscriptions {
+        Subscriptions::Only(vec![MsgKind::LivePriceUpdated])
+    }
 }
 
 #[cfg(test)]
@@ -133,4 +143,28 @@ mod tests {
         let expected_e: Vec<MsgData> = vec![];
         assert_eq!(expected_e, actual_e)
     }
+
+    #[async_std::test]
+    async fn actor_should_average_each_pair_separately() {
+        let mut actor = SlidingAverage::new(SECOND, SECOND);
+        let price = |pair_id, datetime, price| {
+            Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id,
+                datetime,
+                price,
+                ..Default::default()
+            }))
+        };
+        actor.act(&price("BTC-USDT", 0, 100.0)).await.unwrap();
+        let first_eth = actor.act(&price("ETH-USDT", 0, 10.0)).await.unwrap();
+        let actual = actor.act(&price("ETH-USDT", SECOND, 20.0)).await.unwrap();
+        let expected = vec![MsgData::AveragePriceUpdated(PriceUpdated {
+            pair_id: "ETH-USDT",
+            datetime: SECOND,
+            price: 20.0,
+            ..Default::default()
+        })];
+        assert_eq!(Vec::<MsgData>::new(), first_eth);
+        assert_eq!(expected, actual)
+    }
 }
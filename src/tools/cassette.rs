@@ -0,0 +1,283 @@
+use super::networking::{HttpClient, Request, Response};
+use anyhow::{anyhow, Result};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use std::{fs, sync::Mutex};
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Interaction {
+    pub request: Request,
+    pub response: Response,
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Cassette {
+    pub interactions: Vec<Interaction>,
+}
+
+impl Cassette {
+    pub fn load(path: &str) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+        serde_json::from_str(&content).map_err(|err| anyhow!("{}: {}", path, err))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)
+            .map_err(|err| anyhow!("{}: {}", path, err))
+    }
+}
+
+// Only the selected headers are kept and compared, so api keys and signatures
+// never end up in a cassette.
+fn selected(request: &Request, headers: &[String]) -> Request {
+    Request {
+        headers: request
+            .headers
+            .iter()
+            .filter(|(name, _)| headers.contains(&name.to_lowercase()))
+            .map(|(name, value)| (name.to_lowercase(), value.clone()))
+            .collect(),
+        ..request.clone()
+    }
+}
+
+fn lowercase(headers: &[&str]) -> Vec<String> {
+    headers.iter().map(|name| name.to_lowercase()).collect()
+}
+
+// Sends requests with the inner client and saves every request and response to
+// the cassette file.
+pub struct Recorder<C> {
+    inner: C,
+    path: String,
+    headers: Vec<String>,
+    cassette: Mutex<Cassette>,
+}
+
+impl<C> Recorder<C> {
+    pub fn new(inner: C, path: impl Into<String>) -> Self {
+        Recorder {
+            inner,
+            path: path.into(),
+            headers: vec![],
+            cassette: Mutex::new(Cassette {
+                ..Default::default()
+            }),
+        }
+    }
+
+    pub fn with_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = lowercase(headers);
+        self
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient + Send + Sync> HttpClient for Recorder<C> {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let recorded = selected(&request, &self.headers);
+        let response = self.inner.send(request).await?;
+        let mut cassette = self.cassette.lock().expect("unpoisoned lock");
+        cassette.interactions.push(Interaction {
+            request: recorded,
+            response: response.clone(),
+        });
+        // saved after every request, so an aborted recording keeps what it got
+        cassette.save(&self.path)?;
+        Ok(response)
+    }
+}
+
+// Serves the responses of a cassette for requests with the same method, url,
+// body and selected headers. Equal requests get the recorded responses in
+// order, the last one is repeated once all were served.
+pub struct Replayer {
+    interactions: Vec<Interaction>,
+    served: Mutex<Vec<bool>>,
+    headers: Vec<String>,
+}
+
+impl Replayer {
+    pub fn new(cassette: Cassette) -> Self {
+        Replayer {
+            served: Mutex::new(vec![false; cassette.interactions.len()]),
+            interactions: cassette.interactions,
+            headers: vec![],
+        }
+    }
+
+    pub fn from_file(path: &str) -> Result<Self> {
+        Ok(Replayer::new(Cassette::load(path)?))
+    }
+
+    pub fn with_headers(mut self, headers: &[&str]) -> Self {
+        self.headers = lowercase(headers);
+        self
+    }
+}
+
+#[async_trait]
+impl HttpClient for Replayer {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let request = selected(&request, &self.headers);
+        let mut served = self.served.lock().expect("unpoisoned lock");
+        let matching: Vec<usize> = self
+            .interactions
+            .iter()
+            .enumerate()
+            .filter(|(_, interaction)| selected(&interaction.request, &self.headers) == request)
+            .map(|(index, _)| index)
+            .collect();
+        let index = matching
+            .iter()
+            .find(|index| !served[**index])
+            .or_else(|| matching.last())
+            .copied()
+            .ok_or_else(|| {
+                anyhow!(
+                    "no recorded response for {:?} {} with body {:?}",
+                    request.method,
+                    request.url,
+                    request.body
+                )
+            })?;
+        served[index] = true;
+        Ok(self.interactions[index].response.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tools::networking::{tests::MockClient, Headers, Method};
+    use pretty_assertions::assert_eq;
+    use std::collections::HashMap;
+
+    fn cassette_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::remove_file(&path).ok();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn response(status: u16, body: &str) -> Response {
+        Response {
+            status,
+            body: body.into(),
+        }
+    }
+
+    fn signed_request() -> Request {
+        Request {
+            method: Method::POST,
+            url: "http://somesite.com/order".into(),
+            body: "{\"sz\":\"1\"}".into(),
+            headers: Headers::from([
+                ("Content-Type".to_string(), "application/json".to_string()),
+                ("OK-ACCESS-SIGN".to_string(), "signature".to_string()),
+            ]),
+        }
+    }
+
+    fn interaction(url: &str, body: &str) -> Interaction {
+        Interaction {
+            request: Request {
+                url: url.into(),
+                ..Default::default()
+            },
+            response: response(200, body),
+        }
+    }
+
+    #[async_std::test]
+    async fn recorder_should_save_requests_with_selected_headers_only() {
+        let path = cassette_path("cassette_record.json");
+        let client = Recorder::new(
+            MockClient::new(HashMap::from([(
+                (Method::POST, "http://somesite.com/order".to_string()),
+                response(200, "placed"),
+            )])),
+            path.as_str(),
+        )
+        .with_headers(&["content-type"]);
+        let actual = client.send(signed_request()).await.unwrap();
+
+        let expected = Cassette {
+            interactions: vec![Interaction {
+                request: Request {
+                    headers: Headers::from([(
+                        "content-type".to_string(),
+                        "application/json".to_string(),
+                    )]),
+                    ..signed_request()
+                },
+                response: response(200, "placed"),
+            }],
+        };
+        assert_eq!(response(200, "placed"), actual);
+        assert_eq!(expected, Cassette::load(&path).unwrap())
+    }
+
+    #[async_std::test]
+    async fn replayer_should_serve_recorded_response() {
+        let path = cassette_path("cassette_replay.json");
+        Recorder::new(
+            MockClient::new(HashMap::from([(
+                (Method::POST, "http://somesite.com/order".to_string()),
+                response(200, "placed"),
+            )])),
+            path.as_str(),
+        )
+        .with_headers(&["content-type"])
+        .send(signed_request())
+        .await
+        .unwrap();
+
+        let client = Replayer::from_file(&path)
+            .unwrap()
+            .with_headers(&["content-type"]);
+        let actual = client.send(signed_request()).await.unwrap();
+        assert_eq!(response(200, "placed"), actual)
+    }
+
+    #[async_std::test]
+    async fn replayer_should_serve_responses_of_equal_requests_in_order() {
+        let client = Replayer::new(Cassette {
+            interactions: vec![
+                interaction("http://somesite.com/order", "live"),
+                interaction("http://somesite.com/balance", "balance"),
+                interaction("http://somesite.com/order", "filled"),
+            ],
+        });
+        let order = || Request {
+            url: "http://somesite.com/order".into(),
+            ..Default::default()
+        };
+        let mut actual = vec![];
+        for _ in 0..3 {
+            actual.push(client.send(order()).await.unwrap().body);
+        }
+        assert_eq!(vec!["live", "filled", "filled"], actual)
+    }
+
+    #[async_std::test]
+    async fn replayer_should_match_body_and_selected_headers() {
+        let client = Replayer::new(Cassette {
+            interactions: vec![Interaction {
+                request: selected(&signed_request(), &lowercase(&["content-type"])),
+                response: response(200, "placed"),
+            }],
+        })
+        .with_headers(&["content-type"]);
+        let other_body = Request {
+            body: "{\"sz\":\"2\"}".into(),
+            ..signed_request()
+        };
+        let other_header = Request {
+            headers: Headers::from([("content-type".to_string(), "text/plain".to_string())]),
+            ..signed_request()
+        };
+        assert!(client.send(other_body).await.is_err());
+        assert!(client.send(other_header).await.is_err());
+        assert!(client.send(signed_request()).await.is_ok())
+    }
+}
@@ -0,0 +1,321 @@
+use super::logging;
+use anyhow::{anyhow, bail, Result};
+use argon2::Argon2;
+use chacha20poly1305::{
+    aead::{Aead, KeyInit},
+    ChaCha20Poly1305, Key, Nonce,
+};
+use rand::Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::{env, fmt, fs, io::Write};
+use zeroize::{Zeroize, ZeroizeOnDrop, Zeroizing};
+
+const KDF: &str = "argon2id";
+
+// Zeroed when dropped and never printed or serialized, the value is only
+// available through expose.
+#[derive(Clone, PartialEq, Default, Zeroize, ZeroizeOnDrop)]
+pub struct Secret(String);
+
+impl Secret {
+    pub fn new(value: impl Into<String>) -> Self {
+        Secret(value.into())
+    }
+
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(***)")
+    }
+}
+
+impl Serialize for Secret {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str("***")
+    }
+}
+
+impl<'de> Deserialize<'de> for Secret {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Secret)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
+pub struct Credentials {
+    pub api_key: Secret,
+    pub secret: Secret,
+    pub passphrase: Secret,
+}
+
+// The plaintext which is encrypted into a keystore, the only place the exposed
+// secrets get serialized.
+#[derive(Serialize)]
+struct KeystoreContent<'a> {
+    api_key: &'a str,
+    secret: &'a str,
+    passphrase: &'a str,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Keystore {
+    kdf: String,
+    salt: String,
+    nonce: String,
+    ciphertext: String,
+}
+
+// Every loaded secret is redacted from the log from then on.
+impl Credentials {
+    // Reads <prefix>_KEY, <prefix>_SECRET and <prefix>_PASSPHRASE, e.g. with the
+    // prefix OK_ACCESS for okx.
+    pub fn from_env(prefix: &str) -> Result<Self> {
+        let var = |suffix: &str| {
+            let name = format!("{}_{}", prefix, suffix);
+            env::var(&name)
+                .map(Secret::new)
+                .map_err(|_| anyhow!("{} is not set", name))
+        };
+        Ok(Credentials {
+            api_key: var("KEY")?,
+            secret: var("SECRET")?,
+            passphrase: var("PASSPHRASE")?,
+        }
+        .redacted())
+    }
+
+    // Reads a json file with api_key, secret and passphrase, which must not be
+    // accessible by other users.
+    pub fn from_file(path: &str) -> Result<Self> {
+        check_permissions(path)?;
+        let content =
+            Zeroizing::new(fs::read_to_string(path).map_err(|err| anyhow!("{}: {}", path, err))?);
+        let credentials: Credentials =
+            serde_json::from_str(&content).map_err(|err| anyhow!("{}: {}", path, err))?;
+        Ok(credentials.redacted())
+    }
+
+    pub fn from_keystore(path: &str, passphrase: &Secret) -> Result<Self> {
+        let content = fs::read_to_string(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+        let keystore: Keystore =
+            serde_json::from_str(&content).map_err(|err| anyhow!("{}: {}", path, err))?;
+        if keystore.kdf != KDF {
+            bail!("{}: unsupported key derivation {}", path, keystore.kdf)
+        }
+        let decode = |value: &str| {
+            base64::decode(value).map_err(|err| anyhow!("{}: invalid keystore: {}", path, err))
+        };
+        let (salt, nonce, ciphertext) = (
+            decode(&keystore.salt)?,
+            decode(&keystore.nonce)?,
+            decode(&keystore.ciphertext)?,
+        );
+        if nonce.len() != 12 {
+            bail!("{}: invalid keystore nonce", path)
+        }
+        let key = derive_key(passphrase, &salt)?;
+        let plaintext = Zeroizing::new(
+            ChaCha20Poly1305::new(Key::from_slice(&*key))
+                .decrypt(Nonce::from_slice(&nonce), ciphertext.as_ref())
+                .map_err(|_| anyhow!("{}: wrong passphrase or corrupt keystore", path))?,
+        );
+        let credentials: Credentials =
+            serde_json::from_slice(&plaintext).map_err(|err| anyhow!("{}: {}", path, err))?;
+        Ok(credentials.redacted())
+    }
+
+    // Encrypts the credentials with a key derived from the passphrase.
+    pub fn save_keystore(&self, path: &str, passphrase: &Secret) -> Result<()> {
+        let mut salt = [0u8; 16];
+        let mut nonce = [0u8; 12];
+        rand::thread_rng().fill(&mut salt);
+        rand::thread_rng().fill(&mut nonce);
+        let key = derive_key(passphrase, &salt)?;
+        let plaintext = Zeroizing::new(serde_json::to_vec(&KeystoreContent {
+            api_key: self.api_key.expose(),
+            secret: self.secret.expose(),
+            passphrase: self.passphrase.expose(),
+        })?);
+        let ciphertext = ChaCha20Poly1305::new(Key::from_slice(&*key))
+            .encrypt(Nonce::from_slice(&nonce), plaintext.as_ref())
+            .map_err(|_| anyhow!("encrypting credentials failed"))?;
+        let keystore = Keystore {
+            kdf: KDF.into(),
+            salt: base64::encode(salt),
+            nonce: base64::encode(nonce),
+            ciphertext: base64::encode(ciphertext),
+        };
+        write_private(path, &serde_json::to_string_pretty(&keystore)?)
+    }
+
+    fn redacted(self) -> Self {
+        logging::redact(&self.api_key);
+        logging::redact(&self.secret);
+        logging::redact(&self.passphrase);
+        self
+    }
+}
+
+fn derive_key(passphrase: &Secret, salt: &[u8]) -> Result<Zeroizing<[u8; 32]>> {
+    let mut key = Zeroizing::new([0u8; 32]);
+    Argon2::default()
+        .hash_password_into(passphrase.expose().as_bytes(), salt, &mut *key)
+        .map_err(|err| anyhow!("deriving keystore key failed: {}", err))?;
+    Ok(key)
+}
+
+#[cfg(unix)]
+fn check_permissions(path: &str) -> Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+    let metadata = fs::metadata(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+    if metadata.permissions().mode() & 0o077 != 0 {
+        bail!(
+            "{} can be accessed by other users, restrict it with chmod 600",
+            path
+        )
+    }
+    Ok(())
+}
+
+#[cfg(not(unix))]
+fn check_permissions(_: &str) -> Result<()> {
+    Ok(())
+}
+
+fn write_private(path: &str, content: &str) -> Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{OpenOptionsExt, PermissionsExt};
+        options.mode(0o600);
+        if fs::metadata(path).is_ok() {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o600))?;
+        }
+    }
+    let mut file = options
+        .open(path)
+        .map_err(|err| anyhow!("{}: {}", path, err))?;
+    file.write_all(content.as_bytes())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use lazy_static::lazy_static;
+    use pretty_assertions::assert_eq;
+    use uuid::Uuid;
+
+    lazy_static! {
+        // Loaded credentials are redacted from every later log line, so they
+        // must not be words which appear in other tests' output.
+        static ref VALUES: [String; 3] = [(); 3].map(|_| Uuid::new_v4().to_string());
+    }
+
+    fn temp_path(name: &str) -> String {
+        let path = std::env::temp_dir().join(name);
+        fs::remove_file(&path).ok();
+        path.to_string_lossy().into_owned()
+    }
+
+    fn credentials() -> Credentials {
+        Credentials {
+            api_key: Secret::new(&VALUES[0]),
+            secret: Secret::new(&VALUES[1]),
+            passphrase: Secret::new(&VALUES[2]),
+        }
+    }
+
+    #[test]
+    fn debug_should_redact_secrets() {
+        let actual = format!("{:?}", credentials());
+        assert_eq!(
+            "Credentials { api_key: Secret(***), secret: Secret(***), passphrase: Secret(***) }",
+            actual
+        )
+    }
+
+    #[test]
+    fn serialize_should_redact_secrets() {
+        let actual = serde_json::to_string(&credentials()).unwrap();
+        assert_eq!(
+            "{\"api_key\":\"***\",\"secret\":\"***\",\"passphrase\":\"***\"}",
+            actual
+        )
+    }
+
+    #[test]
+    fn from_env_should_read_prefixed_variables() {
+        env::set_var("CREDENTIALS_TEST_KEY", &VALUES[0]);
+        env::set_var("CREDENTIALS_TEST_SECRET", &VALUES[1]);
+        env::set_var("CREDENTIALS_TEST_PASSPHRASE", &VALUES[2]);
+        let actual = Credentials::from_env("CREDENTIALS_TEST").unwrap();
+        assert_eq!(credentials(), actual)
+    }
+
+    #[test]
+    fn from_env_should_name_missing_variable() {
+        let actual = Credentials::from_env("CREDENTIALS_MISSING").unwrap_err();
+        assert_eq!("CREDENTIALS_MISSING_KEY is not set", actual.to_string())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_file_should_read_private_file() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path("credentials_private.json");
+        fs::write(
+            &path,
+            format!(
+                "{{\"api_key\": \"{}\", \"secret\": \"{}\", \"passphrase\": \"{}\"}}",
+                VALUES[0], VALUES[1], VALUES[2]
+            ),
+        )
+        .unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(credentials(), Credentials::from_file(&path).unwrap())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn from_file_should_refuse_file_readable_by_others() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = temp_path("credentials_public.json");
+        fs::write(&path, "{}").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o644)).unwrap();
+        let actual = Credentials::from_file(&path).unwrap_err();
+        assert!(actual.to_string().contains("chmod 600"))
+    }
+
+    #[test]
+    fn keystore_should_return_saved_credentials_for_passphrase() {
+        let path = temp_path("credentials_keystore.json");
+        let passphrase = Secret::new("correct horse");
+        credentials().save_keystore(&path, &passphrase).unwrap();
+        let content = fs::read_to_string(&path).unwrap();
+        assert!(!content.contains(&VALUES[1]));
+        assert_eq!(
+            credentials(),
+            Credentials::from_keystore(&path, &passphrase).unwrap()
+        )
+    }
+
+    #[test]
+    fn keystore_should_fail_with_wrong_passphrase() {
+        let path = temp_path("credentials_wrong.json");
+        credentials()
+            .save_keystore(&path, &Secret::new("correct horse"))
+            .unwrap();
+        let actual = Credentials::from_keystore(&path, &Secret::new("battery staple"));
+        assert!(actual.is_err())
+    }
+}
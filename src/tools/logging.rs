@@ -1,7 +1,13 @@
+use super::credentials::Secret;
 use chrono::Local;
 use env_logger::Builder;
+use lazy_static::lazy_static;
 use log::LevelFilter;
-use std::io::Write;
+use std::{io::Write, sync::RwLock};
+
+lazy_static! {
+    static ref SECRETS: RwLock<Vec<Secret>> = RwLock::new(vec![]);
+}
 
 pub fn setup() {
     Builder::new()
@@ -11,9 +17,40 @@ pub fn setup() {
                 "{} [{}] - {}",
                 Local::now().format("%Y-%m-%dT%H:%M:%S.%f"),
                 record.level(),
-                record.args()
+                redacted(record.args().to_string())
             )
         })
         .filter(None, LevelFilter::Info)
         .init();
 }
+
+// Replaces the secret with *** in every following log line.
+pub fn redact(secret: &Secret) {
+    if !secret.is_empty() {
+        SECRETS
+            .write()
+            .expect("unpoisoned lock")
+            .push(secret.clone());
+    }
+}
+
+fn redacted(line: String) -> String {
+    SECRETS
+        .read()
+        .expect("unpoisoned lock")
+        .iter()
+        .fold(line, |line, secret| line.replace(secret.expose(), "***"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn redacted_should_hide_registered_secrets() {
+        redact(&Secret::new("hunter2-logging"));
+        let actual = redacted("signing with hunter2-logging".into());
+        assert_eq!("signing with ***", actual)
+    }
+}
@@ -0,0 +1,381 @@
+use super::networking::{is_transient_status, HttpClient, HttpError, Method, Request, Response};
+use anyhow::{bail, Result};
+use async_std::{future, task};
+use async_trait::async_trait;
+use rand::Rng;
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+// Clients below wrap another client, so they can be layered, e.g.
+// Retry::new(RateLimit::new(Timeout::new(Client::new(), timeout), 10.0)?)
+
+pub struct Timeout<C> {
+    inner: C,
+    timeout: Duration,
+}
+
+impl<C> Timeout<C> {
+    pub fn new(inner: C, timeout: Duration) -> Self {
+        Timeout { inner, timeout }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient + Send + Sync> HttpClient for Timeout<C> {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let url = request.url.clone();
+        future::timeout(self.timeout, self.inner.send(request))
+            .await
+            .map_err(|_| HttpError::Timeout {
+                url,
+                after: self.timeout,
+            })?
+    }
+}
+
+// Sends GET requests again which got a 429, a server error or timed out,
+// waiting a random time up to an exponentially growing delay in between. When
+// no retries are left the last status is returned as an HttpError::Status.
+// Other methods are sent once, an order which timed out may still have been
+// placed.
+pub struct Retry<C> {
+    inner: C,
+    max_retries: u32,
+    initial: Duration,
+    max: Duration,
+}
+
+impl<C> Retry<C> {
+    pub fn new(inner: C) -> Self {
+        Retry {
+            inner,
+            max_retries: 3,
+            initial: Duration::from_millis(500),
+            max: Duration::from_secs(30),
+        }
+    }
+
+    pub fn with_max_retries(mut self, max_retries: u32) -> Self {
+        self.max_retries = max_retries;
+        self
+    }
+
+    pub fn with_backoff(mut self, initial: Duration, max: Duration) -> Self {
+        self.initial = initial;
+        self.max = max;
+        self
+    }
+
+    fn delay(&self, retry: u32) -> Duration {
+        let cap = self
+            .initial
+            .saturating_mul(2u32.saturating_pow(retry.saturating_sub(1)))
+            .min(self.max);
+        cap.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient + Send + Sync> HttpClient for Retry<C> {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let mut retry = 0;
+        loop {
+            let result = self.inner.send(request.clone()).await;
+            if request.method != Method::GET {
+                return result;
+            }
+            let transient = match &result {
+                Ok(response) => is_transient_status(response.status),
+                Err(err) => err
+                    .downcast_ref::<HttpError>()
+                    .is_some_and(HttpError::is_transient),
+            };
+            if !transient {
+                return result;
+            }
+            if retry >= self.max_retries {
+                return Ok(result?.error_for_status()?);
+            }
+            retry += 1;
+            task::sleep(self.delay(retry)).await;
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+// Token bucket per host: up to `burst` requests at once, refilled with
+// `per_second` requests per second.
+pub struct RateLimit<C> {
+    inner: C,
+    per_second: f64,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl<C> RateLimit<C> {
+    pub fn new(inner: C, per_second: f64) -> Result<Self> {
+        if !(per_second.is_finite() && per_second > 0.0) {
+            bail!(
+                "rate limit must be a positive number of requests per second, got {}",
+                per_second
+            )
+        }
+        Ok(RateLimit {
+            inner,
+            per_second,
+            burst: per_second.max(1.0),
+            buckets: Mutex::new(HashMap::new()),
+        })
+    }
+
+    pub fn with_burst(mut self, burst: u32) -> Result<Self> {
+        if burst < 1 {
+            bail!("rate limit burst must allow at least one request")
+        }
+        self.burst = burst as f64;
+        Ok(self)
+    }
+
+    async fn acquire(&self, host: &str) {
+        loop {
+            let wait = {
+                let mut buckets = self.buckets.lock().expect("unpoisoned lock");
+                let now = Instant::now();
+                let bucket = buckets.entry(host.into()).or_insert(Bucket {
+                    tokens: self.burst,
+                    updated: now,
+                });
+                let refill = now.duration_since(bucket.updated).as_secs_f64() * self.per_second;
+                bucket.tokens = (bucket.tokens + refill).min(self.burst);
+                bucket.updated = now;
+                if bucket.tokens >= 1.0 {
+                    bucket.tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second)
+            };
+            task::sleep(wait).await;
+        }
+    }
+}
+
+#[async_trait]
+impl<C: HttpClient + Send + Sync> HttpClient for RateLimit<C> {
+    async fn send(&self, request: Request) -> Result<Response> {
+        let host = reqwest::Url::parse(&request.url)
+            .ok()
+            .and_then(|url| url.host_str().map(String::from))
+            .unwrap_or_default();
+        self.acquire(&host).await;
+        self.inner.send(request).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::collections::VecDeque;
+
+    // Answers with the given results in order and counts the requests.
+    struct Sequence {
+        results: Mutex<VecDeque<Result<Response, HttpError>>>,
+        requests: Mutex<usize>,
+    }
+
+    impl Sequence {
+        fn new(results: Vec<Result<Response, HttpError>>) -> Self {
+            Sequence {
+                results: Mutex::new(results.into()),
+                requests: Mutex::new(0),
+            }
+        }
+
+        fn statuses(statuses: &[u16]) -> Self {
+            Sequence::new(
+                statuses
+                    .iter()
+                    .map(|status| {
+                        Ok(Response {
+                            status: *status,
+                            ..Default::default()
+                        })
+                    })
+                    .collect(),
+            )
+        }
+
+        fn requests(&self) -> usize {
+            *self.requests.lock().unwrap()
+        }
+    }
+
+    #[async_trait]
+    impl HttpClient for Sequence {
+        async fn send(&self, _: Request) -> Result<Response> {
+            *self.requests.lock().unwrap() += 1;
+            let result = self.results.lock().unwrap().pop_front();
+            Ok(result.expect("no response left")?)
+        }
+    }
+
+    struct Slow {
+        delay: Duration,
+    }
+
+    #[async_trait]
+    impl HttpClient for Slow {
+        async fn send(&self, _: Request) -> Result<Response> {
+            task::sleep(self.delay).await;
+            Ok(Response {
+                status: 200,
+                ..Default::default()
+            })
+        }
+    }
+
+    fn request(url: &str) -> Request {
+        Request {
+            url: url.into(),
+            ..Default::default()
+        }
+    }
+
+    fn fast_retry(inner: Sequence) -> Retry<Sequence> {
+        Retry::new(inner).with_backoff(Duration::from_millis(1), Duration::from_millis(2))
+    }
+
+    #[async_std::test]
+    async fn retry_should_retry_transient_statuses_until_success() {
+        let client = fast_retry(Sequence::statuses(&[503, 429, 200]));
+        let actual = client.send(request("http://somesite.com")).await.unwrap();
+        assert_eq!(200, actual.status);
+        assert_eq!(3, client.inner.requests())
+    }
+
+    #[async_std::test]
+    async fn retry_should_fail_with_last_status_without_retries_left() {
+        let client = fast_retry(Sequence::statuses(&[500, 502, 504])).with_max_retries(2);
+        let actual = client
+            .send(request("http://somesite.com"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&HttpError::Status {
+                status: 504,
+                body: "".into(),
+            }),
+            actual.downcast_ref::<HttpError>()
+        );
+        assert_eq!(3, client.inner.requests())
+    }
+
+    #[async_std::test]
+    async fn retry_should_not_retry_client_errors() {
+        let client = fast_retry(Sequence::statuses(&[404, 200]));
+        let actual = client.send(request("http://somesite.com")).await.unwrap();
+        assert_eq!(404, actual.status);
+        assert_eq!(1, client.inner.requests())
+    }
+
+    #[async_std::test]
+    async fn retry_should_retry_timeouts() {
+        let client = fast_retry(Sequence::new(vec![
+            Err(HttpError::Timeout {
+                url: "http://somesite.com".into(),
+                after: Duration::from_secs(1),
+            }),
+            Ok(Response {
+                status: 200,
+                ..Default::default()
+            }),
+        ]));
+        let actual = client.send(request("http://somesite.com")).await.unwrap();
+        assert_eq!(200, actual.status)
+    }
+
+    #[test]
+    fn retry_delay_should_stay_below_capped_exponential_backoff() {
+        let client = Retry::new(Sequence::statuses(&[]))
+            .with_backoff(Duration::from_millis(100), Duration::from_millis(300));
+        for _ in 0..100 {
+            assert!(client.delay(1) <= Duration::from_millis(100));
+            assert!(client.delay(2) <= Duration::from_millis(200));
+            assert!(client.delay(5) <= Duration::from_millis(300));
+        }
+    }
+
+    #[async_std::test]
+    async fn timeout_should_fail_slow_requests() {
+        let client = Timeout::new(
+            Slow {
+                delay: Duration::from_millis(200),
+            },
+            Duration::from_millis(10),
+        );
+        let actual = client
+            .send(request("http://somesite.com"))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            Some(&HttpError::Timeout {
+                url: "http://somesite.com".into(),
+                after: Duration::from_millis(10),
+            }),
+            actual.downcast_ref::<HttpError>()
+        )
+    }
+
+    #[async_std::test]
+    async fn rate_limit_should_delay_requests_beyond_burst() {
+        let client = RateLimit::new(Sequence::statuses(&[200, 200, 200]), 20.0)
+            .unwrap()
+            .with_burst(1)
+            .unwrap();
+        let started = Instant::now();
+        for _ in 0..3 {
+            client.send(request("http://somesite.com")).await.unwrap();
+        }
+        assert!(started.elapsed() >= Duration::from_millis(90))
+    }
+
+    #[async_std::test]
+    async fn rate_limit_should_limit_hosts_separately() {
+        let client = RateLimit::new(Sequence::statuses(&[200, 200]), 1.0).unwrap();
+        let started = Instant::now();
+        client.send(request("http://somesite.com")).await.unwrap();
+        client.send(request("http://othersite.com")).await.unwrap();
+        assert!(started.elapsed() < Duration::from_millis(500))
+    }
+
+    #[async_std::test]
+    async fn retry_should_not_retry_post() {
+        let client = fast_retry(Sequence::statuses(&[503, 200]));
+        let actual = client
+            .send(Request {
+                method: Method::POST,
+                ..request("http://somesite.com/order")
+            })
+            .await
+            .unwrap();
+        assert_eq!(503, actual.status);
+        assert_eq!(1, client.inner.requests())
+    }
+
+    #[test]
+    fn rate_limit_should_refuse_invalid_limits() {
+        assert!(RateLimit::new(Sequence::statuses(&[]), 0.0).is_err());
+        assert!(RateLimit::new(Sequence::statuses(&[]), f64::NAN).is_err());
+        assert!(RateLimit::new(Sequence::statuses(&[]), 1.0)
+            .unwrap()
+            .with_burst(0)
+            .is_err());
+    }
+}
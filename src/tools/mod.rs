@@ -1,4 +1,7 @@
+pub mod cassette;
+pub mod credentials;
 pub mod logging;
+pub mod middleware;
 pub mod networking;
 pub mod time;
 pub mod uuid;
@@ -1,18 +1,22 @@
 use anyhow::Result;
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::convert::TryInto;
+use std::fmt;
+use std::time::Duration;
+use zeroize::Zeroize;
 
 pub type StatusCode = u16;
 pub type Url = String;
 
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Response {
-    status: StatusCode,
-    body: String,
+    pub status: StatusCode,
+    pub body: String,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Serialize, Deserialize)]
 pub enum Method {
     GET,
     POST,
@@ -24,20 +28,106 @@ impl Default for Method {
     }
 }
 
-#[derive(Debug, PartialEq, Clone, Default)]
+#[derive(Debug, PartialEq, Clone, Default, Serialize, Deserialize)]
 pub struct Request {
-    method: Method,
-    url: Url,
-    body: String,
-    headers: HashMap<String, String>,
+    pub method: Method,
+    pub url: Url,
+    pub body: String,
+    pub headers: Headers,
 }
 
+// Headers carry api keys and passphrases, so every copy wipes its values once
+// it is dropped and debug output only shows their names.
+#[derive(PartialEq, Clone, Default, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Headers(HashMap<String, String>);
+
+impl fmt::Debug for Headers {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut names: Vec<&String> = self.0.keys().collect();
+        names.sort();
+        f.debug_map()
+            .entries(names.into_iter().map(|name| (name, "***")))
+            .finish()
+    }
+}
+
+impl Headers {
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &String)> {
+        self.0.iter()
+    }
+}
+
+impl<const N: usize> From<[(String, String); N]> for Headers {
+    fn from(headers: [(String, String); N]) -> Self {
+        Headers(HashMap::from(headers))
+    }
+}
+
+impl FromIterator<(String, String)> for Headers {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(headers: I) -> Self {
+        Headers(headers.into_iter().collect())
+    }
+}
+
+impl Drop for Headers {
+    fn drop(&mut self) {
+        self.0.values_mut().for_each(Zeroize::zeroize);
+    }
+}
+
+impl Response {
+    pub fn error_for_status(self) -> Result<Response, HttpError> {
+        if self.status >= 400 {
+            return Err(HttpError::Status {
+                status: self.status,
+                body: self.body,
+            });
+        }
+        Ok(self)
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum HttpError {
+    Status { status: StatusCode, body: String },
+    Timeout { url: Url, after: Duration },
+}
+
+// Too many requests, server errors and timeouts may succeed when sent again.
+pub fn is_transient_status(status: StatusCode) -> bool {
+    status == 429 || status >= 500
+}
+
+impl HttpError {
+    pub fn is_transient(&self) -> bool {
+        match self {
+            HttpError::Status { status, .. } => is_transient_status(*status),
+            HttpError::Timeout { .. } => true,
+        }
+    }
+}
+
+impl fmt::Display for HttpError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HttpError::Status { status, body } => write!(f, "http status {}: {}", status, body),
+            HttpError::Timeout { url, after } => {
+                write!(f, "request to {} timed out after {:?}", url, after)
+            }
+        }
+    }
+}
+
+impl std::error::Error for HttpError {}
+
 #[async_trait]
 pub trait HttpClient {
-    async fn send(self, request: Request) -> Result<Response>;
+    async fn send(&self, request: Request) -> Result<Response>;
 }
 
-struct Client {
+#[derive(Clone)]
+pub struct Client {
     client: reqwest::Client,
 }
 
@@ -51,7 +141,7 @@ impl Client {
 
 #[async_trait]
 impl HttpClient for Client {
-    async fn send(self, request: Request) -> Result<Response> {
+    async fn send(&self, request: Request) -> Result<Response> {
         let req = build_request(&self.client, request)?;
         let resp = self.client.execute(req).await?;
         Ok(Response {
@@ -68,7 +158,7 @@ fn build_request(client: &reqwest::Client, request: Request) -> Result<reqwest::
     };
     Ok(client
         .request(method, request.url)
-        .headers((&request.headers).try_into()?)
+        .headers((&request.headers.0).try_into()?)
         .body(request.body)
         .build()?)
 }
@@ -77,6 +167,7 @@ fn build_request(client: &reqwest::Client, request: Request) -> Result<reqwest::
 pub mod tests {
     use super::*;
 
+    #[derive(Clone)]
     pub struct MockClient {
         responses: HashMap<(Method, Url), Response>,
     }
@@ -89,7 +180,7 @@ pub mod tests {
 
     #[async_trait]
     impl HttpClient for MockClient {
-        async fn send(self, request: Request) -> Result<Response> {
+        async fn send(&self, request: Request) -> Result<Response> {
             Ok(self
                 .responses
                 .get(&(request.method, request.url))
@@ -184,6 +275,71 @@ pub mod tests {
             .unwrap();
     }
 
+    #[async_std::test]
+    async fn mock_client_should_be_reusable() {
+        let responses = HashMap::from([(
+            (Method::GET, "http://somesite.com".into()),
+            Response {
+                status: 200,
+                body: "".to_string(),
+            },
+        )]);
+        let client = MockClient::new(responses);
+        let request = Request {
+            url: "http://somesite.com".into(),
+            ..Default::default()
+        };
+        client.send(request.clone()).await.unwrap();
+        assert_eq!(200, client.send(request).await.unwrap().status)
+    }
+
+    #[test]
+    fn request_debug_should_redact_header_values() {
+        let request = Request {
+            url: "http://somesite.com".into(),
+            headers: Headers::from([
+                (
+                    "OK-ACCESS-PASSPHRASE".to_string(),
+                    "my-passphrase".to_string(),
+                ),
+                ("OK-ACCESS-KEY".to_string(), "my-key".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let actual = format!("{:?}", request);
+        assert!(
+            actual.contains(r#"headers: {"OK-ACCESS-KEY": "***", "OK-ACCESS-PASSPHRASE": "***"}"#)
+        );
+        assert!(!actual.contains("my-"))
+    }
+
+    #[test]
+    fn error_for_status_should_keep_successful_responses() {
+        let response = Response {
+            status: 200,
+            body: "ok".to_string(),
+        };
+        assert_eq!(Ok(response.clone()), response.error_for_status())
+    }
+
+    #[test]
+    fn error_for_status_should_return_status_and_body() {
+        let actual = Response {
+            status: 503,
+            body: "down".to_string(),
+        }
+        .error_for_status()
+        .unwrap_err();
+        assert_eq!(
+            HttpError::Status {
+                status: 503,
+                body: "down".to_string()
+            },
+            actual
+        );
+        assert!(actual.is_transient())
+    }
+
     #[test]
     fn build_request_should_build_correct_url() {
         let client = reqwest::Client::new();
@@ -282,7 +438,7 @@ pub mod tests {
             &client,
             Request {
                 url: "http://somesite.com".to_string(),
-                headers: HashMap::from([(
+                headers: Headers::from([(
                     "content-type".to_string(),
                     "application/json".to_string(),
                 )]),
@@ -306,7 +462,7 @@ pub mod tests {
             &client,
             Request {
                 url: "http://somesite.com".to_string(),
-                headers: HashMap::from([("accept-encoding".to_string(), "gzip".to_string())]),
+                headers: Headers::from([("accept-encoding".to_string(), "gzip".to_string())]),
                 ..Default::default()
             },
         )
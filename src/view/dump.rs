@@ -0,0 +1,81 @@
+use crate::messaging::{message::Msg, processor::Sink};
+use anyhow::{anyhow, Result};
+use std::{
+    fs::File,
+    io::{BufWriter, Write},
+};
+
+// Writes every message as one line of json.
+pub struct MessageDump<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> MessageDump<W> {
+    pub fn new(writer: W) -> Self {
+        MessageDump { writer }
+    }
+}
+
+impl MessageDump<BufWriter<File>> {
+    pub fn create(path: &str) -> Result<Self> {
+        let file = File::create(path).map_err(|err| anyhow!("{}: {}", path, err))?;
+        Ok(MessageDump::new(BufWriter::new(file)))
+    }
+}
+
+impl<W: Write> Sink for MessageDump<W> {
+    fn receive(&mut self, msg: &Msg) -> Result<()> {
+        serde_json::to_writer(&mut self.writer, msg)?;
+        self.writer.write_all(b"\n")?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::message::{MsgData, MsgMetaData, PriceUpdated};
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn message_dump_should_write_one_line_per_message() {
+        let mut dump = MessageDump::new(vec![]);
+        dump.receive(&Msg {
+            data: MsgData::LivePriceUpdated(PriceUpdated {
+                pair_id: "BTC/USDT",
+                datetime: 60_000,
+                price: 100.0,
+                candle: None,
+            }),
+            metadata: MsgMetaData {
+                correlation_pair_id: "BTC/USDT",
+                ..Default::default()
+            },
+        })
+        .unwrap();
+        dump.receive(&Msg::with_data(MsgData::Shutdown)).unwrap();
+        dump.finish().unwrap();
+
+        let lines: Vec<serde_json::Value> = String::from_utf8(dump.writer)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(2, lines.len());
+        assert_eq!(
+            serde_json::json!({"LivePriceUpdated": {
+                "pair_id": "BTC/USDT",
+                "datetime": 60000,
+                "price": 100.0,
+                "candle": null
+            }}),
+            lines[0]["data"]
+        );
+        assert_eq!("BTC/USDT", lines[0]["metadata"]["correlation_pair_id"]);
+        assert_eq!(serde_json::json!("Shutdown"), lines[1]["data"]);
+    }
+}
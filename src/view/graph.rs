@@ -1,170 +1,206 @@
-use crate::exchange::{Asset, Assets};
-use crate::messaging::message::{Msg, MsgData};
+use crate::exchange::{split_pair_id, Asset, Assets};
+use crate::messaging::{
+    message::{Msg, MsgData, Timestamp},
+    processor::Sink,
+};
+use anyhow::{bail, Result};
 use chrono::{DateTime, TimeZone, Utc};
-use crossbeam::channel;
 use plotters::prelude::*;
 
-pub fn draw_graph(out_receiver: channel::Receiver<Msg>, offset: f64) {
-    let mut data: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut data_avg: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut data_buys: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut data_sells: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut data_wealth: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut data_base_line: Vec<(DateTime<Utc>, f64)> = vec![];
-    let mut base_line_amount = 0.0;
-    let mut max_value_price = 0.0;
-    let mut min_value_price = f64::INFINITY;
-    let mut max_value_weatlh = 0.0;
-    let mut min_value_wealth = f64::INFINITY;
-    let mut assets = Assets {
-        quote: Some(Asset {
-            name: "USDT".into(),
-            amount: 1000.0,
-        }),
-        ..Default::default()
-    };
-    for event in out_receiver.iter() {
-        match event.data {
-            MsgData::LivePriceUpdated(price) => {
-                if base_line_amount == 0.0 {
-                    base_line_amount = 1000.0 / price.price;
-                }
-                if price.price > max_value_price {
-                    max_value_price = price.price;
-                }
-                if price.price < min_value_price {
-                    min_value_price = price.price
-                }
-                let base_amount = assets.base.clone().unwrap_or_default().amount;
-                let quote_amount = assets.quote.clone().unwrap_or_default().amount;
-                let timestamp = DateTime::from_utc(
-                    Utc.timestamp_millis(price.datetime as i64).naive_local(),
-                    Utc,
-                );
+type Series = Vec<(DateTime<Utc>, f64)>;
 
-                let current_wealth = f64::max(price.price * base_amount, quote_amount);
-                let baseline_wealth = price.price * base_line_amount;
+// Draws prices, averages and trades above the wealth compared to holding the
+// base asset from the first price on, written as svg once finished.
+pub struct Graph {
+    path: String,
+    offset: f64,
+    prices: Series,
+    averages: Series,
+    buys: Series,
+    sells: Series,
+    liquidations: Series,
+    wealth: Series,
+    base_line: Series,
+    base_line_amount: f64,
+    max_price: f64,
+    min_price: f64,
+    max_wealth: f64,
+    min_wealth: f64,
+    assets: Assets,
+}
 
-                if current_wealth > max_value_weatlh {
-                    max_value_weatlh = current_wealth;
-                }
-                if baseline_wealth > max_value_weatlh {
-                    max_value_weatlh = baseline_wealth;
-                }
-                if current_wealth < min_value_wealth {
-                    min_value_wealth = current_wealth
-                }
-                if baseline_wealth < min_value_wealth {
-                    min_value_wealth = baseline_wealth
+impl Graph {
+    pub fn new(path: impl Into<String>, offset: f64) -> Self {
+        Graph {
+            path: path.into(),
+            offset,
+            prices: vec![],
+            averages: vec![],
+            buys: vec![],
+            sells: vec![],
+            liquidations: vec![],
+            wealth: vec![],
+            base_line: vec![],
+            base_line_amount: 0.0,
+            max_price: 0.0,
+            min_price: f64::INFINITY,
+            max_wealth: 0.0,
+            min_wealth: f64::INFINITY,
+            assets: Assets::from(vec![Asset {
+                name: "USDT".into(),
+                amount: 1000.0,
+            }]),
+        }
+    }
+}
+
+fn to_datetime(timestamp: Timestamp) -> DateTime<Utc> {
+    DateTime::from_utc(Utc.timestamp_millis(timestamp as i64).naive_local(), Utc)
+}
+
+impl Sink for Graph {
+    fn receive(&mut self, msg: &Msg) -> Result<()> {
+        match &msg.data {
+            MsgData::LivePriceUpdated(price) => {
+                if self.base_line_amount == 0.0 {
+                    self.base_line_amount = 1000.0 / price.price;
                 }
-                data_wealth.push((timestamp, current_wealth));
-                data_base_line.push((timestamp, price.price * base_line_amount));
+                self.max_price = self.max_price.max(price.price);
+                self.min_price = self.min_price.min(price.price);
+                let (base, quote) = split_pair_id(price.pair_id);
+                let base_amount = self.assets.amount(base);
+                let quote_amount = self.assets.amount(quote);
+                let timestamp = to_datetime(price.datetime);
 
-                data.push((timestamp, price.price));
+                let current_wealth = f64::max(price.price * base_amount, quote_amount);
+                let baseline_wealth = price.price * self.base_line_amount;
+                self.max_wealth = self.max_wealth.max(current_wealth).max(baseline_wealth);
+                self.min_wealth = self.min_wealth.min(current_wealth).min(baseline_wealth);
+                self.wealth.push((timestamp, current_wealth));
+                self.base_line.push((timestamp, baseline_wealth));
+                self.prices.push((timestamp, price.price));
             }
             MsgData::AveragePriceUpdated(price) => {
-                data_avg.push((
-                    DateTime::from_utc(
-                        Utc.timestamp_millis(price.datetime as i64).naive_local(),
-                        Utc,
-                    ),
-                    price.price,
-                ));
+                self.averages
+                    .push((to_datetime(price.datetime), price.price));
             }
             MsgData::Bought(order) => {
-                assets = Assets {
-                    quote: None,
-                    base: Some(Asset {
-                        name: "BTC".into(),
-                        amount: order.amount,
-                    }),
-                };
-                data_buys.push((
-                    DateTime::from_utc(
-                        Utc.timestamp_millis(event.metadata.correlation_time as i64)
-                            .naive_local(),
-                        Utc,
-                    ),
-                    event.metadata.correlation_price,
+                self.assets = Assets::from(vec![Asset {
+                    name: order.base.clone(),
+                    amount: order.amount,
+                }]);
+                self.buys.push((
+                    to_datetime(msg.metadata.correlation_time),
+                    msg.metadata.correlation_price,
                 ))
             }
             MsgData::Sold(order) => {
-                assets = Assets {
-                    quote: Some(Asset {
-                        name: "USDT".into(),
-                        amount: order.amount,
-                    }),
-                    base: None,
-                };
-                data_sells.push((
-                    DateTime::from_utc(
-                        Utc.timestamp_millis(event.metadata.correlation_time as i64)
-                            .naive_local(),
-                        Utc,
-                    ),
-                    event.metadata.correlation_price,
+                self.assets = Assets::from(vec![Asset {
+                    name: order.quote.clone(),
+                    amount: order.amount,
+                }]);
+                self.sells.push((
+                    to_datetime(msg.metadata.correlation_time),
+                    msg.metadata.correlation_price,
+                ))
+            }
+            MsgData::Liquidated(liquidation) => {
+                self.assets = Assets::from(vec![Asset {
+                    name: liquidation.quote.clone(),
+                    amount: liquidation.equity,
+                }]);
+                self.liquidations.push((
+                    to_datetime(msg.metadata.correlation_time),
+                    liquidation.price,
                 ))
             }
             _ => (),
         }
+        Ok(())
     }
 
-    let root_area = SVGBackend::new("result.svg", (3600, 800)).into_drawing_area();
-    root_area.fill(&WHITE).unwrap();
-    let (upper, lower) = root_area.split_vertically((70).percent());
+    fn finish(&mut self) -> Result<()> {
+        let (start_date, end_date) = match (self.prices.first(), self.prices.last()) {
+            (Some(first), Some(last)) => (first.0, last.0),
+            _ => bail!("{}: no prices to draw", self.path),
+        };
+        let root_area = SVGBackend::new(&self.path, (3600, 800)).into_drawing_area();
+        root_area.fill(&WHITE)?;
+        let (upper, lower) = root_area.split_vertically((70).percent());
 
-    let start_date = data.first().unwrap().0;
-    let end_date = data.last().unwrap().0;
+        let mut upper_chart = ChartBuilder::on(&upper)
+            .set_label_area_size(LabelAreaPosition::Left, 70)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .build_cartesian_2d(start_date..end_date, self.min_price..self.max_price)?;
+        upper_chart.configure_mesh().draw()?;
 
-    let mut upper_chart = ChartBuilder::on(&upper)
-        .set_label_area_size(LabelAreaPosition::Left, 70)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .build_cartesian_2d(start_date..end_date, min_value_price..max_value_price)
-        .unwrap();
-    upper_chart.configure_mesh().draw().unwrap();
+        upper_chart.draw_series(self.buys.iter().map(|point| Circle::new(*point, 3, &GREEN)))?;
+        upper_chart.draw_series(self.sells.iter().map(|point| Circle::new(*point, 3, &RED)))?;
+        upper_chart.draw_series(
+            self.liquidations
+                .iter()
+                .map(|point| Cross::new(*point, 5, MAGENTA)),
+        )?;
 
-    upper_chart
-        .draw_series(data_buys.iter().map(|point| Circle::new(*point, 3, &GREEN)))
-        .unwrap();
-    upper_chart
-        .draw_series(data_sells.iter().map(|point| Circle::new(*point, 3, &RED)))
-        .unwrap();
-
-    upper_chart
-        .draw_series(LineSeries::new(data.into_iter(), &BLUE))
-        .unwrap();
-    upper_chart
-        .draw_series(LineSeries::new(data_avg.clone().into_iter(), &BLACK))
-        .unwrap();
-    upper_chart
-        .draw_series(LineSeries::new(
-            data_avg
-                .clone()
-                .into_iter()
-                .map(|(time, price)| (time, price * (1.0 - offset))),
+        upper_chart.draw_series(LineSeries::new(self.prices.iter().copied(), &BLUE))?;
+        upper_chart.draw_series(LineSeries::new(self.averages.iter().copied(), &BLACK))?;
+        let offset = self.offset;
+        upper_chart.draw_series(LineSeries::new(
+            self.averages
+                .iter()
+                .map(|(time, price)| (*time, price * (1.0 - offset))),
             &BLACK,
-        ))
-        .unwrap();
-    upper_chart
-        .draw_series(LineSeries::new(
-            data_avg
-                .clone()
-                .into_iter()
-                .map(|(time, price)| (time, price * (1.0 + offset))),
+        ))?;
+        upper_chart.draw_series(LineSeries::new(
+            self.averages
+                .iter()
+                .map(|(time, price)| (*time, price * (1.0 + offset))),
             &BLACK,
-        ))
-        .unwrap();
+        ))?;
+
+        let mut lower_chart = ChartBuilder::on(&lower)
+            .set_label_area_size(LabelAreaPosition::Left, 70)
+            .set_label_area_size(LabelAreaPosition::Bottom, 40)
+            .build_cartesian_2d(start_date..end_date, self.min_wealth..self.max_wealth)?;
+        lower_chart.configure_mesh().draw()?;
+        lower_chart.draw_series(LineSeries::new(self.wealth.iter().copied(), &RED))?;
+        lower_chart.draw_series(LineSeries::new(self.base_line.iter().copied(), &BLUE))?;
+        root_area.present()?;
+        Ok(())
+    }
+}
 
-    let mut lower_chart = ChartBuilder::on(&lower)
-        .set_label_area_size(LabelAreaPosition::Left, 70)
-        .set_label_area_size(LabelAreaPosition::Bottom, 40)
-        .build_cartesian_2d(start_date..end_date, min_value_wealth..max_value_weatlh)
-        .unwrap();
-    lower_chart.configure_mesh().draw().unwrap();
-    lower_chart
-        .draw_series(LineSeries::new(data_wealth.into_iter(), &RED))
-        .unwrap();
-    lower_chart
-        .draw_series(LineSeries::new(data_base_line.into_iter(), &BLUE))
-        .unwrap();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::message::PriceUpdated;
+    use std::fs;
+
+    fn price(datetime: Timestamp, price: f64) -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id: "BTC/USDT",
+            datetime,
+            price,
+            ..Default::default()
+        }))
+    }
+
+    #[test]
+    fn graph_should_fail_without_prices() {
+        let mut graph = Graph::new("graph_empty.svg", 0.01);
+        graph.receive(&Msg::with_data(MsgData::Shutdown)).unwrap();
+        assert!(graph.finish().is_err())
+    }
+
+    #[test]
+    fn graph_should_write_svg_when_finished() {
+        let path = std::env::temp_dir().join("graph_prices.svg");
+        fs::remove_file(&path).ok();
+        let mut graph = Graph::new(path.to_string_lossy(), 0.01);
+        for msg in [price(0, 100.0), price(60_000, 110.0), price(120_000, 105.0)] {
+            graph.receive(&msg).unwrap();
+        }
+        graph.finish().unwrap();
+        assert!(fs::read_to_string(&path).unwrap().starts_with("<svg"))
+    }
 }
@@ -1 +1,3 @@
-pub mod graph;
\ No newline at end of file
+pub mod dump;
+pub mod graph;
+pub mod summary;
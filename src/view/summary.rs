@@ -0,0 +1,312 @@
+use crate::exchange::split_pair_id;
+use crate::messaging::{
+    message::{Msg, MsgData, Order, Price},
+    processor::Sink,
+};
+use anyhow::Result;
+use std::{collections::BTreeMap, io::Write};
+
+#[derive(Default)]
+struct Prices {
+    count: usize,
+    first: Price,
+    last: Price,
+}
+
+// The base bought and not sold again and the quote sells brought in less what
+// buys spent, fees in either currency included.
+#[derive(Default)]
+struct Holding {
+    base: f64,
+    quote: f64,
+}
+
+// Counts prices, orders and fees and writes a short report once finished.
+// Prices and holdings are kept per pair, e.g. BTC/USDT.
+pub struct Summary<W: Write> {
+    writer: W,
+    prices: BTreeMap<String, Prices>,
+    bought: usize,
+    sold: usize,
+    rejected: usize,
+    cancelled: usize,
+    liquidated: usize,
+    fees: BTreeMap<String, f64>,
+    holdings: BTreeMap<String, Holding>,
+}
+
+impl<W: Write> Summary<W> {
+    pub fn new(writer: W) -> Self {
+        Summary {
+            writer,
+            prices: BTreeMap::new(),
+            bought: 0,
+            sold: 0,
+            rejected: 0,
+            cancelled: 0,
+            liquidated: 0,
+            fees: BTreeMap::new(),
+            holdings: BTreeMap::new(),
+        }
+    }
+
+    fn holding(&mut self, base: &str, quote: &str) -> &mut Holding {
+        self.holdings
+            .entry(format!("{}/{}", base, quote))
+            .or_default()
+    }
+
+    fn buy(&mut self, order: &Order) {
+        let (base_fee, quote_fee) = order.base_and_quote_fee();
+        let holding = self.holding(&order.base, &order.quote);
+        holding.base += order.amount;
+        holding.quote -= (order.amount + base_fee) * order.price + quote_fee;
+    }
+
+    fn sell(&mut self, order: &Order) {
+        let holding = self.holding(&order.base, &order.quote);
+        holding.base -= order.sold_base().unwrap_or_default();
+        holding.quote += order.amount;
+    }
+
+    fn pay(&mut self, currency: &str, amount: f64) {
+        if amount != 0.0 {
+            *self.fees.entry(currency.into()).or_default() += amount;
+        }
+    }
+
+    fn report(&self) -> Vec<String> {
+        let fees: Vec<String> = self
+            .fees
+            .iter()
+            .map(|(currency, amount)| format!("{} {}", amount, currency))
+            .collect();
+        let mut lines: Vec<String> = self
+            .prices
+            .iter()
+            .map(|(pair_id, prices)| {
+                format!(
+                    "prices: {} {} from {} to {}",
+                    prices.count, pair_id, prices.first, prices.last
+                )
+            })
+            .collect();
+        lines.extend([
+            format!("bought: {}", self.bought),
+            format!("sold: {}", self.sold),
+            format!("rejected: {}", self.rejected),
+            format!("cancelled: {}", self.cancelled),
+            format!("liquidated: {}", self.liquidated),
+            format!(
+                "fees: {}",
+                if fees.is_empty() {
+                    "none".into()
+                } else {
+                    fees.join(", ")
+                }
+            ),
+        ]);
+        // the base is valued at the last price of its pair
+        for (pair_id, holding) in &self.holdings {
+            let (base, quote) = split_pair_id(pair_id);
+            if holding.base != 0.0 {
+                lines.push(match self.prices.get(pair_id) {
+                    Some(prices) => format!(
+                        "holding: {} {} worth {} {}",
+                        holding.base,
+                        base,
+                        holding.base * prices.last,
+                        quote
+                    ),
+                    None => format!("holding: {} {}", holding.base, base),
+                });
+            }
+            if holding.quote != 0.0 {
+                lines.push(format!("holding: {} {}", holding.quote, quote));
+            }
+        }
+        lines
+    }
+}
+
+impl<W: Write> Sink for Summary<W> {
+    fn receive(&mut self, msg: &Msg) -> Result<()> {
+        match &msg.data {
+            MsgData::LivePriceUpdated(price) => {
+                let prices = self.prices.entry(price.pair_id.into()).or_default();
+                if prices.count == 0 {
+                    prices.first = price.price;
+                }
+                prices.count += 1;
+                prices.last = price.price;
+            }
+            MsgData::Bought(order) => {
+                self.bought += 1;
+                self.pay(&order.fee.currency, order.fee.amount);
+                self.buy(order);
+            }
+            MsgData::Sold(order) => {
+                self.sold += 1;
+                self.pay(&order.fee.currency, order.fee.amount);
+                self.sell(order);
+            }
+            MsgData::Rejected(_) => self.rejected += 1,
+            MsgData::OrderCancelled(_) => self.cancelled += 1,
+            MsgData::Liquidated(liquidation) => {
+                self.liquidated += 1;
+                // the position is closed, what is left of it is paid out in the quote
+                let holding = self.holding(&liquidation.base, &liquidation.quote);
+                holding.base = 0.0;
+                holding.quote += liquidation.equity;
+            }
+            _ => (),
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> Result<()> {
+        for line in self.report() {
+            writeln!(self.writer, "{}", line)?;
+        }
+        Ok(self.writer.flush()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::messaging::message::{Fee, Order, PairId, PriceUpdated, Rejection};
+    use pretty_assertions::assert_eq;
+
+    fn price(price: f64) -> Msg {
+        pair_price("BTC/USDT", price)
+    }
+
+    fn pair_price(pair_id: PairId, price: f64) -> Msg {
+        Msg::with_data(MsgData::LivePriceUpdated(PriceUpdated {
+            pair_id,
+            price,
+            ..Default::default()
+        }))
+    }
+
+    fn order(currency: &str, amount: f64, fee: f64) -> Order {
+        pair_order("BTC", currency, amount, fee)
+    }
+
+    fn pair_order(base: &str, currency: &str, amount: f64, fee: f64) -> Order {
+        Order {
+            base: base.into(),
+            quote: "USDT".into(),
+            amount,
+            price: 0.0,
+            fee: Fee {
+                currency: currency.into(),
+                amount: fee,
+            },
+        }
+    }
+
+    #[test]
+    fn summary_should_report_trades_fees_and_holding() {
+        let mut summary = Summary::new(vec![]);
+        for msg in [
+            price(100.0),
+            Msg::with_data(MsgData::Bought(order("BTC", 2.0, 0.5))),
+            price(110.0),
+            Msg::with_data(MsgData::Rejected(Rejection {
+                base: "BTC".into(),
+                quote: "USDT".into(),
+                amount: 2.0,
+                reason: "insufficient balance".into(),
+            })),
+            price(120.0),
+            Msg::with_data(MsgData::Shutdown),
+        ] {
+            summary.receive(&msg).unwrap();
+        }
+        summary.finish().unwrap();
+
+        let expected = "prices: 3 BTC/USDT from 100 to 120\n\
+            bought: 1\n\
+            sold: 0\n\
+            rejected: 1\n\
+            cancelled: 0\n\
+            liquidated: 0\n\
+            fees: 0.5 BTC\n\
+            holding: 2 BTC worth 240 USDT\n";
+        assert_eq!(expected, String::from_utf8(summary.writer).unwrap())
+    }
+
+    #[test]
+    fn summary_should_report_quote_after_selling() {
+        let mut summary = Summary::new(vec![]);
+        summary
+            .receive(&Msg::with_data(MsgData::Sold(order("USDT", 230.0, 1.0))))
+            .unwrap();
+        let report = summary.report();
+        assert_eq!(
+            vec!["fees: 1 USDT", "holding: 230 USDT"],
+            report[report.len() - 2..].to_vec()
+        )
+    }
+
+    #[test]
+    fn summary_should_value_holdings_at_price_of_their_pair() {
+        let mut summary = Summary::new(vec![]);
+        for msg in [
+            pair_price("BTC/USDT", 100.0),
+            pair_price("ETH/USDT", 10.0),
+            Msg::with_data(MsgData::Bought(pair_order("BTC", "BTC", 2.0, 0.0))),
+            Msg::with_data(MsgData::Bought(pair_order("ETH", "ETH", 5.0, 0.0))),
+            pair_price("BTC/USDT", 120.0),
+            pair_price("ETH/USDT", 12.0),
+        ] {
+            summary.receive(&msg).unwrap();
+        }
+        let report = summary.report();
+        assert_eq!(
+            vec![
+                "prices: 2 BTC/USDT from 100 to 120",
+                "prices: 2 ETH/USDT from 10 to 12"
+            ],
+            report[..2].to_vec()
+        );
+        assert_eq!(
+            vec![
+                "holding: 2 BTC worth 240 USDT",
+                "holding: 5 ETH worth 60 USDT"
+            ],
+            report[report.len() - 2..].to_vec()
+        )
+    }
+
+    #[test]
+    fn summary_should_add_up_holdings_of_several_fills() {
+        let mut summary = Summary::new(vec![]);
+        let fill = |amount: f64, price: f64, fee: Fee| Order {
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            amount,
+            price,
+            fee,
+        };
+        let fee = |currency: &str, amount: f64| Fee {
+            currency: currency.into(),
+            amount,
+        };
+        for msg in [
+            Msg::with_data(MsgData::Bought(fill(2.0, 100.0, fee("USDT", 2.0)))),
+            Msg::with_data(MsgData::Bought(fill(0.9, 110.0, fee("BTC", 0.1)))),
+            Msg::with_data(MsgData::Sold(fill(178.0, 120.0, fee("USDT", 2.0)))),
+            price(120.0),
+        ] {
+            summary.receive(&msg).unwrap();
+        }
+        let report = summary.report();
+        assert_eq!(
+            vec!["holding: 1.4 BTC worth 168 USDT", "holding: -134 USDT"],
+            report[report.len() - 2..].to_vec()
+        )
+    }
+}